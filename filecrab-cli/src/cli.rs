@@ -1,22 +1,26 @@
 mod config;
+mod download;
+mod tokens;
+mod tus;
 
 use crate::{Result, cli::config::Instance, error::Error};
 use age::{Decryptor, Encryptor, secrecy::SecretString};
 use anstyle::AnsiColor;
 use arboard::Clipboard;
+use bytes::Bytes;
+use chrono::{DateTime, Local, Utc};
 use clap::{Parser, Subcommand, builder::Styles};
 use config::Config;
-use file_format::FileFormat;
-use futures_util::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
+use download::{Download, PartFile};
+use futures_util::{Stream, StreamExt, future, stream};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use inquire::Confirm;
 use reqwest::{
-    Client,
+    Body, Client, Response, StatusCode, Url,
     multipart::{Form, Part},
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    cmp::min,
     env,
     io::{self, IsTerminal, Read, Write},
     iter,
@@ -24,14 +28,28 @@ use std::{
     time::Duration,
     vec,
 };
+use tokens::{DeleteToken, DeleteTokens, Kind};
 use tokio::{
     fs::{self, OpenOptions},
-    io::AsyncWriteExt,
+    io::{AsyncRead, AsyncWriteExt},
+};
+use tokio_util::{
+    compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt},
+    io::{ReaderStream, StreamReader},
 };
 
 const COPY_COMMAND: &str = "filecrab copy";
 const DOWNLOAD_COMMAND: &str = "filecrab download";
 
+/// Size from which files are sent with resumable uploads.
+const RESUMABLE_UPLOAD_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Number of times an interrupted download is resumed before giving up.
+const MAX_RESUME_ATTEMPTS: u32 = 5;
+
+/// Size of the pipe between the encryptor and the request body.
+const ENCRYPTION_BUFFER_SIZE: usize = 64 * 1024;
+
 /// Program to share files and text.
 #[derive(Parser)]
 #[command(styles=Self::styles())]
@@ -52,10 +70,27 @@ pub enum Command {
         /// Password to protect the file.
         #[arg(long)]
         pwd: Option<String>,
+        /// How long the file stays available, either a duration (ex. 15m, 2h, 7d) or an RFC3339
+        /// date. The server caps it to its maximum expire time.
+        #[arg(long)]
+        expire: Option<String>,
+        /// Deletes the file once it has been downloaded this many times.
+        #[arg(long)]
+        max_downloads: Option<u32>,
+        /// Only allows downloading the file with a signed link valid for this duration (ex. 1h),
+        /// the ID alone is not enough. Prints the link instead of the ID.
+        #[arg(long, value_name = "DURATION")]
+        signed_link: Option<String>,
+        /// Makes the signed link work only once.
+        #[arg(long, requires = "signed_link")]
+        single_use: bool,
+        /// Puts the password in the link to the web frontend, the recipient only needs the link.
+        #[arg(long)]
+        link_pwd: bool,
     },
     /// Download the file represented by the ID returned by the upload command.
     Download {
-        /// Memorable ID.
+        /// Memorable ID, or a signed link.
         id: String,
         /// Password to access the file.
         #[arg(long)]
@@ -63,6 +98,9 @@ pub enum Command {
         /// Path to the destination file (default to the current directory).
         #[arg(long, short)]
         path: Option<PathBuf>,
+        /// Only show the name, size and expiration of the file, without downloading it.
+        #[arg(long, conflicts_with_all = ["pwd", "path"])]
+        info: bool,
     },
     /// Paste a text and upload it to filecrab. Content can be either specified positionally or
     /// piped.
@@ -72,6 +110,9 @@ pub enum Command {
         /// Password to protect the text.
         #[arg(long)]
         pwd: String,
+        /// Puts the password in the link to the web frontend, the recipient only needs the link.
+        #[arg(long)]
+        link_pwd: bool,
     },
     /// Copy the text represented by the ID returned by the paste command to the clipboard.
     Copy {
@@ -83,6 +124,15 @@ pub enum Command {
         #[arg(long, short)]
         out: Option<PathBuf>,
     },
+    /// Delete a file or a text you shared, using the deletion token saved when it was created.
+    Delete {
+        /// Memorable ID.
+        id: String,
+    },
+    /// Show what the API key of the active instance stores, and its quota.
+    Usage,
+    /// Check whether the active instance and its database and storage are up.
+    Status,
     /// Switches the active instance in filecrab.
     Switch,
     /// Adds a new filecrab instance to the config.
@@ -93,10 +143,82 @@ pub enum Command {
     Init,
 }
 
+/// How an uploaded file is shared.
+struct UploadOptions {
+    expire: Option<String>,
+    max_downloads: Option<u32>,
+    signed_link: Option<String>,
+    single_use: bool,
+    link_pwd: bool,
+}
+
+/// What the server needs to know about an uploaded file.
+struct UploadMetadata {
+    file_name: String,
+    encrypted: bool,
+    expire: Option<String>,
+    max_downloads: Option<u32>,
+    signed_only: bool,
+}
+
 /// Represents the response of the upload request.
 #[derive(Deserialize)]
 struct UploadResponse {
     id: String,
+    #[serde(default)]
+    delete_token: String,
+}
+
+/// Represents the body of the link request.
+#[derive(Serialize)]
+struct LinkBody<'a> {
+    memo_id: &'a str,
+    expire: &'a str,
+    single_use: bool,
+}
+
+/// Represents the response of the link request.
+#[derive(Deserialize)]
+struct LinkResponse {
+    url: String,
+}
+
+/// Represents the response of the asset info request.
+#[derive(Deserialize)]
+struct AssetInfoResponse {
+    file_name: String,
+    size: u64,
+    encrypted: bool,
+    created_at: Option<DateTime<Utc>>,
+    expire: Option<DateTime<Utc>>,
+    remaining_downloads: Option<u32>,
+}
+
+/// Represents the response of the usage request.
+#[derive(Deserialize)]
+struct UsageResponse {
+    name: String,
+    size: u64,
+    files: u64,
+    quota_size: Option<u64>,
+    quota_files: Option<u64>,
+}
+
+/// Represents the response of the readiness request.
+#[derive(Deserialize)]
+struct ReadyResponse {
+    status: String,
+    database: DependencyCheck,
+    storage: DependencyCheck,
+}
+
+/// State of a dependency of the server.
+#[derive(Deserialize)]
+struct DependencyCheck {
+    status: String,
+    backend: String,
+    latency_ms: u64,
+    error: Option<String>,
 }
 
 /// Represents the body of the paste request.
@@ -109,6 +231,8 @@ struct PasteBody {
 #[derive(Deserialize)]
 struct PasteResponse {
     id: String,
+    #[serde(default)]
+    delete_token: String,
 }
 
 /// Represents the response of the copy request.
@@ -140,10 +264,36 @@ impl Cli {
 
         // Handles the subcommand.
         match self.cmd.clone() {
-            Command::Upload { path, pwd } => self.upload(path, pwd).await,
-            Command::Download { id, pwd, path } => self.download(id, pwd, path).await,
-            Command::Paste { content, pwd } => match content {
-                Some(content) => self.paste(content, pwd).await,
+            Command::Upload {
+                path,
+                pwd,
+                expire,
+                max_downloads,
+                signed_link,
+                single_use,
+                link_pwd,
+            } => {
+                let options = UploadOptions {
+                    expire,
+                    max_downloads,
+                    signed_link,
+                    single_use,
+                    link_pwd,
+                };
+                self.upload(path, pwd, options).await
+            }
+            Command::Download {
+                id,
+                pwd,
+                path,
+                info,
+            } => self.download(id, pwd, path, info).await,
+            Command::Paste {
+                content,
+                pwd,
+                link_pwd,
+            } => match content {
+                Some(content) => self.paste(content, pwd, link_pwd).await,
                 None if io::stdin().is_terminal() => Err(Error::NoPipedContent),
                 None => {
                     let mut content = String::new();
@@ -151,10 +301,13 @@ impl Cli {
                         .lock()
                         .read_to_string(&mut content)
                         .map_err(Error::LockStdIn)?;
-                    self.paste(content.trim().to_string(), pwd).await
+                    self.paste(content.trim().to_string(), pwd, link_pwd).await
                 }
             },
             Command::Copy { id, pwd, out } => self.copy(id, pwd, out).await,
+            Command::Delete { id } => self.delete(id).await,
+            Command::Usage => self.usage().await,
+            Command::Status => self.status().await,
             Command::Switch => self.switch().await,
             Command::Add => self.add().await,
             Command::Remove => self.remove().await,
@@ -163,19 +316,42 @@ impl Cli {
     }
 
     /// Uploads a file to filecrab.
-    async fn upload(&mut self, path: PathBuf, mut pwd: Option<String>) -> Result<()> {
+    async fn upload(
+        &mut self,
+        path: PathBuf,
+        mut pwd: Option<String>,
+        options: UploadOptions,
+    ) -> Result<()> {
+        let UploadOptions {
+            expire,
+            max_downloads,
+            signed_link,
+            single_use,
+            link_pwd,
+        } = options;
+
         // Destructures the config.
-        let Instance { url, api_key, name } = &self.config.get_active_instance();
+        let Instance {
+            url,
+            api_key,
+            name,
+            web_url,
+        } = &self.config.get_active_instance();
         println!("Active filecrab instance: {name}");
 
-        // Reads the file.
-        let mut bytes = fs::read(&path).await.map_err(|err| Error::ReadFile {
+        // Opens the file, it is streamed so it never sits in memory.
+        let file = fs::File::open(&path).await.map_err(|err| Error::ReadFile {
             path: format!("{}", path.display()),
             source: err,
         })?;
-
-        // Initializes the form.
-        let mut form = Form::new();
+        let size = file
+            .metadata()
+            .await
+            .map_err(|err| Error::ReadFile {
+                path: format!("{}", path.display()),
+                source: err,
+            })?
+            .len();
 
         // Prompt the user for a password
         if pwd.is_none()
@@ -187,37 +363,119 @@ impl Cli {
             pwd = Some(given_pwd);
         };
 
-        // If there's a password, adds it to the form and encrypts the file.
-        if let Some(pwd) = pwd {
-            // Sets the password.
-            form = form.text("encrypted", "true");
-            // Encrypts the file.
-            let mut bar = ProgressBar::new_spinner();
-            bar = bar.with_message("Encrypting file");
-            bar.enable_steady_tick(Duration::from_millis(100));
+        // Kept for the link to the web frontend, the password itself goes to the encryptor.
+        let link_pwd = pwd.clone().filter(|_| link_pwd);
+
+        // Inits the progress bar, it follows the bytes read from the file.
+        let pb = ProgressBar::new(size);
+        pb.set_style(ProgressStyle::default_bar()
+            .template("{msg}\n{spinner:.green} [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")?
+            .progress_chars("█░"));
+        let reader = pb.wrap_async_read(file);
+
+        let metadata = UploadMetadata {
+            file_name: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|str| str.to_string())
+                .unwrap_or_default(),
+            encrypted: pwd.is_some(),
+            expire,
+            max_downloads,
+            signed_only: signed_link.is_some(),
+        };
+
+        // If there's a password, encrypts the file on the fly.
+        pb.set_message(if metadata.encrypted {
+            "Encrypting and uploading file..."
+        } else {
+            "Uploading file..."
+        });
+
+        // Big files are sent in chunks, a dropped connection does not restart the whole upload.
+        let res = if size >= RESUMABLE_UPLOAD_THRESHOLD {
+            match pwd {
+                // The size of the encrypted file is only known at the end.
+                Some(pwd) => {
+                    let reader = StreamReader::new(Box::pin(Cli::encrypted_stream(reader, pwd)));
+                    tus::upload(url, api_key, reader, None, &metadata, &pb).await
+                }
+                None => tus::upload(url, api_key, reader, Some(size), &metadata, &pb).await,
+            }
+        } else {
+            let body = match pwd {
+                Some(pwd) => Part::stream(Body::wrap_stream(Cli::encrypted_stream(reader, pwd))),
+                None => {
+                    Part::stream_with_length(Body::wrap_stream(ReaderStream::new(reader)), size)
+                }
+            };
+            Cli::upload_form(url, api_key, body, metadata).await
+        };
+
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => {
+                pb.abandon();
+                return Err(err);
+            }
+        };
+        pb.finish_with_message("File correctly uploaded.");
+
+        // Saves the deletion token.
+        self.save_delete_token(name, &res.id, Kind::File, res.delete_token)
+            .await?;
+
+        // The file can only be downloaded with a signed link, which is shared instead of the ID.
+        if let Some(expire) = signed_link {
+            let link = Cli::signed_link(url, api_key, &res.id, &expire, single_use).await?;
+
+            println!(
+                "The ID of the file is {}, the link to share is the following:",
+                res.id
+            );
+            println!("-> {link}");
+            println!();
 
-            bytes = Cli::encrypt_slice(&bytes, pwd)?;
-            bar.finish_with_message("File encrypted.")
+            return self.copy_to_clipboard(Some(DOWNLOAD_COMMAND), &link);
         }
 
-        // Adds the file to the form.
-        let file_name = path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .map(|str| str.to_string())
-            .unwrap_or_default();
-        form = form.part("file", Part::bytes(bytes).file_name(file_name));
-
-        // Set Upload bar
-        let mut bar = ProgressBar::new_spinner();
-        bar = bar.with_message("Uploading to filecrab. ");
-        bar.enable_steady_tick(Duration::from_millis(100));
+        // Prints the ID.
+        println!("The ID to share is the following:");
+        println!("-> {}", res.id);
+        println!();
+
+        // The link to the web frontend is copied instead of the command, when there is one.
+        if let Some(web_url) = web_url {
+            let link = Cli::web_link(web_url, Kind::File, &res.id, link_pwd.as_deref());
+            println!("Or the link to the web frontend:");
+            println!("-> {link}");
+            println!();
+
+            return self.copy_to_clipboard(None, &link);
+        }
+
+        // Copies the ID to the clipboard.
+        self.copy_to_clipboard(Some(DOWNLOAD_COMMAND), &res.id)?;
+        Ok(())
+    }
 
+    /// Asks the server for a signed download link.
+    async fn signed_link(
+        url: &str,
+        api_key: &str,
+        id: &str,
+        expire: &str,
+        single_use: bool,
+    ) -> Result<String> {
         // Sends the request.
         let res = Client::new()
-            .post(format!("{url}/api/upload"))
+            .post(format!("{url}/api/link"))
             .header("filecrab-key", api_key)
-            .multipart(form)
+            .json(&LinkBody {
+                memo_id: id,
+                expire,
+                single_use,
+            })
             .send()
             .await?;
 
@@ -229,60 +487,81 @@ impl Cli {
             return Err(Error::UnsuccessfulRequest { status, body });
         }
 
-        let res: UploadResponse = res.json().await.map_err(Error::ReqwestJsonParse)?;
-        bar.finish_with_message("File correctly uploaded.");
-
-        // Prints the ID.
-        println!("The ID to share is the following:");
-        println!("-> {}", res.id);
-        println!();
-
-        // Copies the ID to the clipboard.
-        self.copy_to_clipboard(Some(DOWNLOAD_COMMAND), &res.id)?;
-        Ok(())
+        let link: LinkResponse = res.json().await.map_err(Error::ReqwestJsonParse)?;
+        Ok(format!("{url}{}", link.url))
     }
 
-    /// Downloads a file from filecrab.
-    async fn download(
-        &mut self,
-        id: String,
-        pwd: Option<String>,
-        path: Option<PathBuf>,
-    ) -> Result<()> {
-        // Destructures the config.
-        let Instance { url, api_key, name } = &self.config.get_active_instance();
-        println!("Active filecrab instance: {name}");
-
-        // Build the query params.
-        let query: Vec<(&str, &str)> = vec![("file", &id)];
+    /// Uploads a file in a single multipart request.
+    async fn upload_form(
+        url: &str,
+        api_key: &str,
+        part: Part,
+        metadata: UploadMetadata,
+    ) -> Result<UploadResponse> {
+        // Sets the encryption, expiration and download limit, before the file so the server knows
+        // them when the upload starts.
+        let mut form = Form::new();
+        if metadata.encrypted {
+            form = form.text("encrypted", "true");
+        }
+        if let Some(expire) = metadata.expire {
+            form = form.text("expire", expire);
+        }
+        if let Some(max_downloads) = metadata.max_downloads {
+            form = form.text("max_downloads", max_downloads.to_string());
+        }
+        if metadata.signed_only {
+            form = form.text("signed_only", "true");
+        }
 
-        // Set Upload bar
-        let mut bar = ProgressBar::new_spinner();
-        bar = bar.with_message("Requesting file to filecrab.");
-        bar.enable_steady_tick(Duration::from_millis(100));
+        // Adds the file to the form.
+        form = form.part("file", part.file_name(metadata.file_name));
 
         // Sends the request.
         let res = Client::new()
-            .get(format!("{url}/api/download"))
+            .post(format!("{url}/api/upload"))
             .header("filecrab-key", api_key)
-            .query(&query)
+            .multipart(form)
             .send()
             .await?;
-        bar.finish();
 
         // Checks if there's been an error.
         if !res.status().is_success() {
-            let status = res.status().to_string();
-            let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
-            let body = String::from_utf8(body.to_vec())?;
-            return Err(Error::UnsuccessfulRequest { status, body });
+            return Err(Cli::upload_error(res).await?);
         }
 
-        // Gets the filename from headers.
-        let file_name = match res.headers().get("filecrab-file-name") {
-            Some(file_name) => file_name.to_str()?.to_string(),
-            None => return Err(Error::MissingFileNameInHeaders),
-        };
+        res.json().await.map_err(Error::ReqwestJsonParse)
+    }
+
+    /// Turns the response of a failed upload into an error, explaining the quota and size limits.
+    async fn upload_error(res: Response) -> Result<Error> {
+        let status = res.status();
+        let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
+        let body = String::from_utf8(body.to_vec())?;
+
+        Ok(match status {
+            StatusCode::PAYLOAD_TOO_LARGE => Error::UploadTooLarge(body),
+            StatusCode::INSUFFICIENT_STORAGE => Error::QuotaExhausted(body),
+            _ => Error::UnsuccessfulRequest {
+                status: status.to_string(),
+                body,
+            },
+        })
+    }
+
+    /// Downloads a file from filecrab.
+    async fn download(
+        &mut self,
+        id: String,
+        pwd: Option<String>,
+        path: Option<PathBuf>,
+        info: bool,
+    ) -> Result<()> {
+        // Destructures the config.
+        let Instance {
+            url, api_key, name, ..
+        } = &self.config.get_active_instance();
+        println!("Active filecrab instance: {name}");
 
         // Computes the destination path.
         let path = if let Some(path) = path {
@@ -291,79 +570,172 @@ impl Cli {
             env::current_dir().map_err(Error::CurrentDir)?
         };
 
-        // Gets the content length for the progress bar.
-        let total_size = res.content_length().unwrap_or_default();
+        // A signed link already holds the ID and where to download it from. The API key of the
+        // instance is not sent along, the link may point to another server.
+        let (request_url, api_key, id) = match Url::parse(&id) {
+            Ok(link) if matches!(link.scheme(), "http" | "https") => {
+                let id = link
+                    .query_pairs()
+                    .find(|(key, _)| key == "file")
+                    .map(|(_, value)| value.to_string())
+                    .ok_or_else(|| Error::InvalidUrl(id.clone()))?;
+                (link, None, id)
+            }
+            _ => {
+                let request_url =
+                    Url::parse_with_params(&format!("{url}/api/download"), [("file", &id)])
+                        .map_err(|_| Error::InvalidUrl(url.clone()))?;
+                (request_url, Some(api_key.as_str()), id)
+            }
+        };
+
+        // Asking about the file first does not count as a download.
+        let asset = Cli::fetch_asset_info(&request_url, api_key).await?;
+        if info {
+            Cli::print_asset_info(&asset);
+            return Ok(());
+        }
+
+        // Fails early rather than after the download if the destination is taken.
+        let destination = path.join(&asset.file_name);
+        if fs::try_exists(&destination).await.unwrap_or(true) {
+            return Err(Error::OpenFile {
+                path: format!("{}", destination.display()),
+                source: io::ErrorKind::AlreadyExists.into(),
+            });
+        }
+
+        // The password is asked before the download starts when the file is known to be encrypted.
+        let pwd = match pwd {
+            Some(pwd) => Some(pwd),
+            None if asset.encrypted => Some(inquire::prompt_text(
+                "The file is encrypted, please provide a password:",
+            )?),
+            None => None,
+        };
+
+        // The content is decrypted as it comes into a part file named after the ID, an
+        // interrupted download is resumed from it.
+        let part = PartFile::new(&path, &id);
+        let mut download = Download {
+            request_url: &request_url,
+            api_key,
+            part: &part,
+            pwd,
+            resumable: false,
+        };
 
         // Inits the progress bar.
-        let pb = ProgressBar::new(total_size);
+        let pb = ProgressBar::new(0);
         pb.set_style(ProgressStyle::default_bar()
             .template("{msg}\n{spinner:.green} [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")?
             .progress_chars("█░"));
-        pb.set_message("Downloading file...");
+        pb.set_message("Requesting file to filecrab.");
+
+        // Downloads the file, resuming it when the connection drops. A server which does not
+        // resume it would count every attempt as a download, the download is then not retried.
+        let mut attempt = 0;
+        loop {
+            match download.fetch(&pb).await {
+                Ok(()) => break,
+                Err(err)
+                    if err.is_interruption()
+                        && download.resumable
+                        && attempt < MAX_RESUME_ATTEMPTS =>
+                {
+                    attempt += 1;
+                    pb.println(format!(
+                        "Download interrupted, resuming ({attempt}/{MAX_RESUME_ATTEMPTS})..."
+                    ));
+                    tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+                }
+                Err(err) => {
+                    pb.abandon();
+                    return Err(err);
+                }
+            }
+        }
+        // Finishes the progress bar.
+        pb.finish();
 
-        // Inits the stream.
-        let mut stream = res.bytes_stream();
-        let mut downloaded: u64 = 0;
+        Cli::move_file(&part.path, &destination).await?;
+        part.remove_state().await;
 
-        // Creates the buffer.
-        let mut buf: Vec<u8> = Vec::new();
+        println!("The name of the downloaded element is: {}", asset.file_name);
+        Ok(())
+    }
 
-        // Reads the stream.
-        while let Some(data) = stream.next().await {
-            let chunk = data?;
-            tokio::io::copy(&mut chunk.as_ref(), &mut buf)
-                .await
-                .map_err(Error::CopyChunk)?;
-            let pos = min(downloaded + (chunk.len() as u64), total_size);
-            downloaded = pos;
-            pb.set_position(pos);
+    /// Returns what the server knows about the file of a download url, nothing is downloaded.
+    async fn fetch_asset_info(
+        request_url: &Url,
+        api_key: Option<&str>,
+    ) -> Result<AssetInfoResponse> {
+        // The info endpoint takes the same query, a signed link included.
+        let mut info_url = request_url.clone();
+        info_url.set_path(
+            &request_url
+                .path()
+                .replace("/api/download", "/api/asset/info"),
+        );
+
+        // Sends the request.
+        let mut req = Client::new().get(info_url);
+        if let Some(api_key) = api_key {
+            req = req.header("filecrab-key", api_key);
         }
-        // Finishes the progress bar.
-        pb.finish();
+        let res = req.send().await?;
 
-        // Decrypts the file.
-        let bytes = if let Some(pwd) = pwd {
-            let mut bar = ProgressBar::new_spinner();
-            bar = bar.with_message("Decrypting file");
-            bar.enable_steady_tick(Duration::from_millis(100));
-
-            let output = Cli::decrypt_slice(&buf[..], pwd)?;
-            bar.finish();
-            output
-        } else if FileFormat::from_bytes(&buf) == FileFormat::AgeEncryption {
-            // If the data coming in is encrypted, Prompt the user for a password
-            let pwd = inquire::prompt_text("The file is encrypted, please provide a password:")?;
-
-            let mut bar = ProgressBar::new_spinner();
-            bar = bar.with_message("Decrypting file");
-            bar.enable_steady_tick(Duration::from_millis(100));
-
-            let output = Cli::decrypt_slice(&buf[..], pwd)?;
-            bar.finish();
-            output
-        } else {
-            buf
-        };
+        // Checks if there's been an error.
+        if !res.status().is_success() {
+            let status = res.status().to_string();
+            let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
+            let body = String::from_utf8(body.to_vec())?;
+            return Err(Error::UnsuccessfulRequest { status, body });
+        }
 
-        // Creates file with the name of the asset.
-        let mut file = Cli::create_file(format!("{}/{}", path.display(), file_name)).await?;
+        res.json().await.map_err(Error::ReqwestJsonParse)
+    }
 
-        // Writes the file.
-        file.write_all(&bytes)
-            .await
-            .map_err(|err| Error::WriteFile {
-                path: format!("{}", path.display()),
-                source: err,
-            })?;
+    /// Shows what the server knows about a file.
+    fn print_asset_info(info: &AssetInfoResponse) {
+        let date = |date: DateTime<Utc>| {
+            date.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
+        let created_at = info
+            .created_at
+            .map(date)
+            .unwrap_or_else(|| "unknown".into());
+        let expire = info.expire.map(date).unwrap_or_else(|| "never".into());
+        let remaining_downloads = match info.remaining_downloads {
+            Some(remaining) => remaining.to_string(),
+            None => String::from("unlimited"),
+        };
 
-        println!("The name of the downloaded element is: {file_name}");
-        Ok(())
+        println!("File name:      {}", info.file_name);
+        println!("Size:           {}", HumanBytes(info.size));
+        println!(
+            "Encrypted:      {}",
+            if info.encrypted { "yes" } else { "no" }
+        );
+        println!("Uploaded:       {created_at}");
+        println!("Expires:        {expire}");
+        println!("Downloads left: {remaining_downloads}");
     }
 
     /// Pastes a text to filecrab.
-    async fn paste(&mut self, content: String, pwd: String) -> Result<()> {
+    async fn paste(&mut self, content: String, pwd: String, link_pwd: bool) -> Result<()> {
         // Destructures the config.
-        let Instance { url, api_key, name } = &self.config.get_active_instance();
+        let Instance {
+            url,
+            api_key,
+            name,
+            web_url,
+        } = &self.config.get_active_instance();
+
+        // Kept for the link to the web frontend, the password itself goes to the encryptor.
+        let link_pwd = link_pwd.then(|| pwd.clone());
         println!("Active filecrab instance: {name}");
 
         // Set the spinner
@@ -394,9 +766,24 @@ impl Cli {
 
         let body: PasteResponse = res.json().await?;
 
+        // Saves the deletion token.
+        self.save_delete_token(name, &body.id, Kind::Text, body.delete_token)
+            .await?;
+
         println!("The ID to share is the following:");
         println!("-> {}", body.id);
         println!();
+
+        // The link to the web frontend is copied instead of the command, when there is one.
+        if let Some(web_url) = web_url {
+            let link = Cli::web_link(web_url, Kind::Text, &body.id, link_pwd.as_deref());
+            println!("Or the link to the web frontend:");
+            println!("-> {link}");
+            println!();
+
+            return self.copy_to_clipboard(None, &link);
+        }
+
         // Copies the command to retrieve the text and the ID to the clipboard.
         self.copy_to_clipboard(Some(COPY_COMMAND), &body.id)?;
         Ok(())
@@ -410,11 +797,7 @@ impl Cli {
         }
 
         // Destructures the config.
-        let Instance {
-            url,
-            api_key,
-            name: _,
-        } = &self.config.get_active_instance();
+        let Instance { url, api_key, .. } = &self.config.get_active_instance();
 
         // Build the query params.
         let query = vec![("memo_id", id)];
@@ -465,6 +848,134 @@ impl Cli {
         Ok(())
     }
 
+    /// Deletes a file or a text from filecrab with its saved deletion token.
+    async fn delete(&mut self, id: String) -> Result<()> {
+        // Destructures the config.
+        let Instance {
+            url, api_key, name, ..
+        } = &self.config.get_active_instance();
+        println!("Active filecrab instance: {name}");
+
+        // Finds the deletion token.
+        let mut tokens = DeleteTokens::load().await?;
+        let DeleteToken { kind, token } = tokens
+            .get(name, &id)
+            .cloned()
+            .ok_or_else(|| Error::MissingDeleteToken(id.clone()))?;
+
+        // Build the query params.
+        let query = vec![("memo_id", &id)];
+
+        // Sends the request.
+        let res = Client::new()
+            .delete(format!("{url}/api/{}", kind.endpoint()))
+            .query(&query)
+            .header("filecrab-key", api_key)
+            .header("filecrab-delete-token", token)
+            .send()
+            .await?;
+
+        // The token is useless once the element is gone, either deleted now or expired.
+        let status = res.status();
+        if status.is_success() || status == StatusCode::NOT_FOUND {
+            tokens.remove(name, &id);
+            tokens.save().await?;
+        }
+
+        // Checks if there's been an error.
+        if !status.is_success() {
+            let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
+            let body = String::from_utf8(body.to_vec())?;
+            return Err(Error::UnsuccessfulRequest {
+                status: status.to_string(),
+                body,
+            });
+        }
+
+        println!("Successfully deleted `{id}`.");
+        Ok(())
+    }
+
+    /// Shows what the API key stores and its quota.
+    async fn usage(&mut self) -> Result<()> {
+        // Destructures the config.
+        let Instance {
+            url, api_key, name, ..
+        } = &self.config.get_active_instance();
+        println!("Active filecrab instance: {name}");
+
+        // Sends the request.
+        let res = Client::new()
+            .get(format!("{url}/api/usage"))
+            .header("filecrab-key", api_key)
+            .send()
+            .await?;
+
+        // Checks if there's been an error.
+        if !res.status().is_success() {
+            let status = res.status().to_string();
+            let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
+            let body = String::from_utf8(body.to_vec())?;
+            return Err(Error::UnsuccessfulRequest { status, body });
+        }
+
+        let usage: UsageResponse = res.json().await.map_err(Error::ReqwestJsonParse)?;
+
+        let size_limit = match usage.quota_size {
+            Some(quota) => HumanBytes(quota).to_string(),
+            None => String::from("unlimited"),
+        };
+        let files_limit = match usage.quota_files {
+            Some(quota) => quota.to_string(),
+            None => String::from("unlimited"),
+        };
+
+        println!("API key: {}", usage.name);
+        println!("Storage: {} / {size_limit}", HumanBytes(usage.size));
+        println!("Files:   {} / {files_limit}", usage.files);
+        Ok(())
+    }
+
+    /// Shows whether the server and its dependencies are up.
+    async fn status(&mut self) -> Result<()> {
+        // Destructures the config.
+        let Instance { url, name, .. } = &self.config.get_active_instance();
+        println!("Active filecrab instance: {name}");
+
+        // Sends the request, the probe is open so no key is needed.
+        let res = Client::new().get(format!("{url}/readyz")).send().await?;
+
+        // An unavailable server still details its dependencies.
+        let status = res.status();
+        if !status.is_success() && status != StatusCode::SERVICE_UNAVAILABLE {
+            let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
+            let body = String::from_utf8(body.to_vec())?;
+            return Err(Error::UnsuccessfulRequest {
+                status: status.to_string(),
+                body,
+            });
+        }
+
+        let ready: ReadyResponse = res.json().await.map_err(Error::ReqwestJsonParse)?;
+
+        println!("Server:   {}", ready.status);
+        for (label, check) in [("Database:", ready.database), ("Storage:", ready.storage)] {
+            print!(
+                "{label:<9} {} ({}), {} ms",
+                check.status, check.backend, check.latency_ms
+            );
+            match check.error {
+                Some(err) => println!(", {err}"),
+                None => println!(),
+            }
+        }
+
+        if !status.is_success() {
+            return Err(Error::ServerUnavailable);
+        }
+        Ok(())
+    }
+
     // Switches the filecrab instance
     async fn switch(&mut self) -> Result {
         self.config.switch_instance().await
@@ -513,6 +1024,24 @@ impl Cli {
         Ok(())
     }
 
+    /// Saves the deletion token of a shared element so `filecrab delete` can use it later.
+    async fn save_delete_token(
+        &self,
+        instance: &str,
+        id: &str,
+        kind: Kind,
+        token: String,
+    ) -> Result {
+        // Older servers don't return a deletion token
+        if token.is_empty() {
+            return Ok(());
+        }
+
+        let mut tokens = DeleteTokens::load().await?;
+        tokens.insert(instance, id, DeleteToken { kind, token });
+        tokens.save().await
+    }
+
     /// Given a slice of bytes and a password, tries to decrypt it's values and returns the
     /// original content.
     /// Uses the age algorithm.
@@ -534,6 +1063,82 @@ impl Cli {
         Ok(output)
     }
 
+    /// Builds the link to an element on the web frontend. The password goes in the fragment, which
+    /// browsers never send to the server.
+    fn web_link(web_url: &str, kind: Kind, id: &str, pwd: Option<&str>) -> String {
+        let link = format!("{web_url}/{}/{id}", kind.web_path());
+
+        match pwd {
+            Some(pwd) => format!("{link}#{}", Cli::percent_encode(pwd)),
+            None => link,
+        }
+    }
+
+    /// Percent-encodes everything but the unreserved characters, the web frontend decodes it with
+    /// `decodeURIComponent`.
+    fn percent_encode(value: &str) -> String {
+        value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect()
+    }
+
+    /// Moves a completed download to its destination.
+    async fn move_file(from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to).await.map_err(|err| Error::MoveFile {
+            path: format!("{}", to.display()),
+            source: err,
+        })
+    }
+
+    /// Given a reader and a password, returns a stream of the reader going through the age
+    /// encryptor. Only small buffers are held in memory whatever the size of the content.
+    fn encrypted_stream<R>(
+        reader: R,
+        pwd: String,
+    ) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let (writer, output) = tokio::io::duplex(ENCRYPTION_BUFFER_SIZE);
+
+        // Encrypts in its own task, writing into the pipe the body reads from.
+        let task = tokio::spawn(async move {
+            let encryptor = Encryptor::with_user_passphrase(SecretString::from(pwd));
+            let mut writer = encryptor
+                .wrap_async_output(writer.compat_write())
+                .await
+                .map_err(Error::EncryptionWriterWrap)?;
+            futures_util::io::copy(reader.compat(), &mut writer)
+                .await
+                .map_err(|err| Error::WriteToWriter {
+                    r#type: String::from("encryption"),
+                    source: err,
+                })?;
+            futures_util::AsyncWriteExt::close(&mut writer)
+                .await
+                .map_err(Error::FinishEncryption)
+        });
+
+        // Once the pipe is drained, surfaces any encryption error so the request fails instead of
+        // uploading a truncated file.
+        let result = stream::once(async move {
+            match task.await {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(Err(io::Error::other(err))),
+                Err(err) => Some(Err(io::Error::other(err))),
+            }
+        })
+        .filter_map(future::ready);
+
+        ReaderStream::new(output).chain(result)
+    }
+
     /// Given a slice of bytes and a password encrypts the value and returns the resulting encryption.
     fn encrypt_slice(bytes: &[u8], pwd: String) -> Result<Vec<u8>> {
         let encryptor = Encryptor::with_user_passphrase(SecretString::from(pwd));
@@ -20,6 +20,9 @@ pub(super) struct Instance {
     pub(super) name: String,
     pub(super) url: String,
     pub(super) api_key: String,
+    /// URL of the web frontend of the instance, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) web_url: Option<String>,
 }
 
 /// Gives the original command and a pathbuf, used to create filecrab configs.
@@ -178,10 +181,33 @@ impl Config {
         // Reads the API key from the stdin.
         let api_key = Text::new("Enter the API key:").prompt()?.trim().to_string();
 
+        // Ask the user for the web frontend, links to it are printed when sharing
+        let web_url = Text::new("Enter the complete URL of the web frontend, if any:")
+            .with_validator(|val: &str| {
+                let val = val.trim();
+                if !val.is_empty() && !val.starts_with("http://") && !val.starts_with("https://") {
+                    return Ok(Validation::Invalid(
+                        "The given url is missing the `http(s)://` prefix.".into(),
+                    ));
+                }
+                Ok(Validation::Valid)
+            })
+            .with_help_message("Leave empty if the instance has no web frontend.")
+            .prompt()
+            .map_err(|err| match err {
+                InquireError::OperationCanceled | InquireError::OperationInterrupted => {
+                    Error::UserCancel
+                }
+                _ => err.into(),
+            })?;
+
+        let web_url = web_url.trim().trim_end_matches('/');
+
         Ok(Instance {
             name: instance_name,
             url,
             api_key,
+            web_url: (!web_url.is_empty()).then(|| web_url.to_string()),
         })
     }
 
@@ -0,0 +1,500 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    iter,
+    path::{Path, PathBuf},
+};
+
+use age::{Decryptor, secrecy::SecretString};
+use bytes::{Buf, Bytes};
+use file_format::FileFormat;
+use futures_util::{Stream, StreamExt};
+use indicatif::ProgressBar;
+use reqwest::{Client, Response, StatusCode, Url, header};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+    sync::mpsc,
+};
+
+use crate::{Result, error::Error};
+
+/// Size of the plaintext of a chunk of the age format, chunks are decrypted one at a time.
+const AGE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Size of an encrypted chunk, its plaintext followed by its authentication tag.
+const AGE_ENCRYPTED_CHUNK_SIZE: u64 = AGE_CHUNK_SIZE + 16;
+
+/// Size of the nonce following the age header.
+const AGE_NONCE_SIZE: usize = 16;
+
+/// Largest age header accepted, the passphrase ones are a few hundred bytes.
+const MAX_AGE_HEADER_SIZE: usize = 64 * 1024;
+
+/// Number of bytes read from a download to detect its format.
+const HEADER_PEEK_SIZE: usize = 256;
+
+/// Number of received chunks waiting to be decrypted.
+const DECRYPTION_QUEUE_SIZE: usize = 16;
+
+/// Files of a download in progress, named after the ID.
+pub(super) struct PartFile {
+    /// The content downloaded so far, already decrypted.
+    pub path: PathBuf,
+    /// The ETag of the file being downloaded, to resume only the same version of it.
+    etag_path: PathBuf,
+    /// The age header of an encrypted download, needed to resume decrypting it.
+    header_path: PathBuf,
+}
+
+impl PartFile {
+    pub(super) fn new(dir: &Path, id: &str) -> Self {
+        PartFile {
+            path: dir.join(format!("{id}.part")),
+            etag_path: dir.join(format!("{id}.part.etag")),
+            header_path: dir.join(format!("{id}.part.header")),
+        }
+    }
+
+    /// Removes what is kept along the part file to resume it.
+    pub(super) async fn remove_state(&self) {
+        let _ = fs::remove_file(&self.etag_path).await;
+        let _ = fs::remove_file(&self.header_path).await;
+    }
+
+    /// Removes the part file and its state, the download starts over.
+    async fn remove(&self) {
+        let _ = fs::remove_file(&self.path).await;
+        self.remove_state().await;
+    }
+}
+
+/// Where an interrupted download starts again.
+struct Resume {
+    /// Offset of the content requested to the server.
+    offset: u64,
+    /// Length of the part file kept.
+    written: u64,
+    /// Header of the encrypted content, `None` for a plain file.
+    header: Option<Vec<u8>>,
+}
+
+/// A download written to its part file as it comes, and decrypted on the fly when encrypted.
+pub(super) struct Download<'a> {
+    pub request_url: &'a Url,
+    pub api_key: Option<&'a str>,
+    pub part: &'a PartFile,
+    /// Password of the file, asked when it turns out to be encrypted.
+    pub pwd: Option<String>,
+    /// Whether the server resumes the download, a download it sends whole again is counted again.
+    pub resumable: bool,
+}
+
+impl Download<'_> {
+    /// Downloads the file, or what is left of it, to the part file.
+    pub(super) async fn fetch(&mut self, pb: &ProgressBar) -> Result<()> {
+        let resume = self.resume_point().await;
+
+        // Sends the request.
+        let mut req = Client::new().get(self.request_url.clone());
+        if let Some(api_key) = self.api_key {
+            req = req.header("filecrab-key", api_key);
+        }
+        if let Some((resume, etag)) = &resume {
+            req = req
+                .header(header::RANGE, format!("bytes={}-", resume.offset))
+                .header(header::IF_RANGE, etag.trim());
+        }
+        let res = req.send().await?;
+
+        // Only a server honouring ranges sends the rest of the file rather than counting a new
+        // download.
+        self.resumable = res.status() == StatusCode::PARTIAL_CONTENT
+            || res.status() == StatusCode::RANGE_NOT_SATISFIABLE
+            || res
+                .headers()
+                .get(header::ACCEPT_RANGES)
+                .is_some_and(|value| value.as_bytes() == b"bytes");
+
+        // The part file goes up to the end of the file or beyond, it is either complete or stale.
+        if res.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            let size = res
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("bytes */"))
+                .and_then(|size| size.parse::<u64>().ok());
+
+            // An encrypted download always asks for its last chunk again, it is never complete
+            // here.
+            let complete = resume
+                .as_ref()
+                .is_some_and(|(resume, _)| resume.header.is_none() && size == Some(resume.offset));
+            if complete {
+                return Ok(());
+            }
+
+            self.part.remove().await;
+            return Err(Error::StalePartFile);
+        }
+
+        // Checks if there's been an error.
+        if !res.status().is_success() {
+            let status = res.status().to_string();
+            let body = res.bytes().await.map_err(Error::ReqwestReadBody)?;
+            let body = String::from_utf8(body.to_vec())?;
+            return Err(Error::UnsuccessfulRequest { status, body });
+        }
+
+        // The server sent the rest of the file, or the whole file if it changed since.
+        let resume = match resume {
+            Some((resume, _)) if res.status() == StatusCode::PARTIAL_CONTENT => {
+                if content_range_start(&res) != Some(resume.offset) {
+                    self.part.remove().await;
+                    return Err(Error::StalePartFile);
+                }
+                Some(resume)
+            }
+            _ => None,
+        };
+
+        // Remembers the version of the file for a later resume.
+        match res.headers().get(header::ETAG) {
+            Some(etag) => fs::write(&self.part.etag_path, etag.as_bytes())
+                .await
+                .map_err(|err| Error::WriteFile {
+                    path: format!("{}", self.part.etag_path.display()),
+                    source: err,
+                })?,
+            None => {
+                let _ = fs::remove_file(&self.part.etag_path).await;
+            }
+        }
+
+        // Updates the progress bar.
+        let start = resume
+            .as_ref()
+            .map(|resume| resume.offset)
+            .unwrap_or_default();
+        pb.set_length(start + res.content_length().unwrap_or_default());
+        pb.set_position(start);
+        pb.set_message("Downloading file...");
+
+        let stream = res.bytes_stream();
+        match resume {
+            Some(Resume {
+                offset,
+                written,
+                header: Some(header),
+            }) => self.decrypt(stream, header, offset, written, pb).await,
+            Some(Resume { written, .. }) => self.write(stream, Vec::new(), written, pb).await,
+            None => self.start(stream, pb).await,
+        }
+    }
+
+    /// Returns where the part file resumes, along with the ETag of the file it belongs to.
+    async fn resume_point(&self) -> Option<(Resume, String)> {
+        // Resumes only if we know which version of the file the part belongs to.
+        let etag = fs::read_to_string(&self.part.etag_path).await.ok()?;
+        let written = fs::metadata(&self.part.path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+
+        match fs::read(&self.part.header_path).await.ok() {
+            // The chunk holding the last byte written is decrypted again, seeking the decryption
+            // to the start of a chunk is not supported.
+            Some(header) => {
+                let written = match written {
+                    0 => 0,
+                    written if written % AGE_CHUNK_SIZE == 0 => written - 1,
+                    written => written,
+                };
+                let offset =
+                    header.len() as u64 + (written / AGE_CHUNK_SIZE) * AGE_ENCRYPTED_CHUNK_SIZE;
+
+                Some((
+                    Resume {
+                        offset,
+                        written,
+                        header: Some(header),
+                    },
+                    etag,
+                ))
+            }
+            None if written > 0 => Some((
+                Resume {
+                    offset: written,
+                    written,
+                    header: None,
+                },
+                etag,
+            )),
+            None => None,
+        }
+    }
+
+    /// Starts the download from the beginning of the file, its first bytes tell whether it is
+    /// encrypted.
+    async fn start<S>(&mut self, mut stream: S, pb: &ProgressBar) -> Result<()>
+    where
+        S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+    {
+        let _ = fs::remove_file(&self.part.header_path).await;
+
+        let mut head = Vec::with_capacity(HEADER_PEEK_SIZE);
+        while head.len() < HEADER_PEEK_SIZE {
+            match stream.next().await {
+                Some(chunk) => head.extend_from_slice(&chunk.map_err(Error::DownloadInterrupted)?),
+                None => break,
+            }
+        }
+
+        if FileFormat::from_bytes(&head) != FileFormat::AgeEncryption {
+            pb.inc(head.len() as u64);
+            return self.write(stream, head, 0, pb).await;
+        }
+
+        // The header is kept to resume decrypting the file.
+        let header_len = loop {
+            if let Some(len) = age_header_len(&head) {
+                break len;
+            }
+            if head.len() > MAX_AGE_HEADER_SIZE {
+                return Err(Error::InvalidEncryptedFile);
+            }
+            match stream.next().await {
+                Some(chunk) => head.extend_from_slice(&chunk.map_err(Error::DownloadInterrupted)?),
+                None => return Err(Error::InvalidEncryptedFile),
+            }
+        };
+        fs::write(&self.part.header_path, &head[..header_len])
+            .await
+            .map_err(|err| Error::WriteFile {
+                path: format!("{}", self.part.header_path.display()),
+                source: err,
+            })?;
+
+        pb.inc(head.len() as u64);
+        let offset = head.len() as u64;
+        self.decrypt(stream, head, offset, 0, pb).await
+    }
+
+    /// Writes a plain file to the part file, after the `written` bytes kept.
+    async fn write<S>(&self, mut stream: S, head: Vec<u8>, written: u64, pb: &ProgressBar) -> Result
+    where
+        S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+    {
+        let write_error = |err| Error::WriteFile {
+            path: format!("{}", self.part.path.display()),
+            source: err,
+        };
+
+        let resumed = written > 0;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&self.part.path)
+            .await
+            .map_err(|err| Error::OpenFile {
+                path: format!("{}", self.part.path.display()),
+                source: err,
+            })?;
+
+        file.write_all(&head).await.map_err(write_error)?;
+        while let Some(data) = stream.next().await {
+            let chunk = data.map_err(Error::DownloadInterrupted)?;
+            file.write_all(&chunk).await.map_err(write_error)?;
+            pb.inc(chunk.len() as u64);
+        }
+
+        file.flush().await.map_err(write_error)?;
+        file.sync_all().await.map_err(write_error)
+    }
+
+    /// Decrypts an encrypted file into the part file as it comes. `prefix` holds the start of the
+    /// content up to `offset`, where the stream begins, and the `written` bytes of the part file
+    /// are kept.
+    async fn decrypt<S>(
+        &mut self,
+        mut stream: S,
+        prefix: Vec<u8>,
+        offset: u64,
+        written: u64,
+        pb: &ProgressBar,
+    ) -> Result
+    where
+        S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+    {
+        let pwd = match self.pwd.clone() {
+            Some(pwd) => pwd,
+            None => {
+                let pwd =
+                    inquire::prompt_text("The file is encrypted, please provide a password:")?;
+                self.pwd = Some(pwd.clone());
+                pwd
+            }
+        };
+
+        // Decrypts in a blocking task, only the decryption of a blocking reader can be resumed
+        // in the middle of the file.
+        let (sender, receiver) = mpsc::channel(DECRYPTION_QUEUE_SIZE);
+        let reader = ResumedReader {
+            prefix,
+            offset,
+            pos: 0,
+            receiver,
+            chunk: Bytes::new(),
+        };
+        let path = self.part.path.clone();
+        let task =
+            tokio::task::spawn_blocking(move || decrypt_to_file(reader, &path, written, pwd));
+
+        let mut received = Ok(());
+        while let Some(data) = stream.next().await {
+            match data {
+                Ok(chunk) => {
+                    pb.inc(chunk.len() as u64);
+                    // The decryption stopped, its error is returned below
+                    if sender.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    let _ = sender
+                        .send(Err(io::Error::other("the download was interrupted")))
+                        .await;
+                    received = Err(Error::DownloadInterrupted(err));
+                    break;
+                }
+            }
+        }
+        drop(sender);
+
+        let decrypted = task.await.map_err(Error::DecryptionTask)?;
+        received?;
+        decrypted
+    }
+}
+
+/// Reads an encrypted download for the decryptor: `prefix` up to its length, then the content
+/// received from `offset`. Seeking is limited to jumping to `offset`, what lies between was not
+/// downloaded again.
+struct ResumedReader {
+    prefix: Vec<u8>,
+    offset: u64,
+    pos: u64,
+    receiver: mpsc::Receiver<io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl Read for ResumedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.prefix.len() as u64 {
+            let mut rest = &self.prefix[self.pos as usize..];
+            let n = rest.read(buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        if self.pos < self.offset {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "this part of the file was not downloaded again",
+            ));
+        }
+
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(err)) => return Err(err),
+                None => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ResumedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.pos),
+            SeekFrom::Start(target) if target == self.pos => Ok(self.pos),
+            SeekFrom::Start(target) if target == self.offset && self.pos <= self.offset => {
+                self.pos = target;
+                Ok(target)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only the content received can be read",
+            )),
+        }
+    }
+}
+
+/// Decrypts the content of the reader into the part file, after the `written` bytes kept.
+fn decrypt_to_file(reader: ResumedReader, path: &Path, written: u64, pwd: String) -> Result<()> {
+    let decrypt_error = |err| Error::ReadFromReader {
+        r#type: String::from("decrypt"),
+        source: err,
+    };
+    let write_error = |err| Error::WriteFile {
+        path: format!("{}", path.display()),
+        source: err,
+    };
+
+    let decryptor = Decryptor::new(reader).map_err(Error::CreateDecryptor)?;
+    let identity = age::scrypt::Identity::new(SecretString::from(pwd));
+    let mut decrypted = decryptor
+        .decrypt(iter::once(&identity as _))
+        .map_err(Error::FailedToDecrypt)?;
+    if written > 0 {
+        decrypted
+            .seek(SeekFrom::Start(written))
+            .map_err(decrypt_error)?;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|err| Error::OpenFile {
+            path: format!("{}", path.display()),
+            source: err,
+        })?;
+    file.set_len(written).map_err(write_error)?;
+    file.seek(SeekFrom::End(0)).map_err(write_error)?;
+
+    io::copy(&mut decrypted, &mut file).map_err(decrypt_error)?;
+    file.sync_all().map_err(write_error)
+}
+
+/// Returns the length of the age header at the start of the content, its nonce included, once
+/// the content holds all of it.
+fn age_header_len(content: &[u8]) -> Option<usize> {
+    // The header ends with its MAC line, `--- <MAC>`.
+    let mac_line = content.windows(5).position(|window| window == b"\n--- ")? + 1;
+    let end = mac_line + content[mac_line..].iter().position(|&byte| byte == b'\n')? + 1;
+
+    let len = end + AGE_NONCE_SIZE;
+    (content.len() >= len).then_some(len)
+}
+
+/// Returns the first byte of the range sent in a partial response.
+fn content_range_start(res: &Response) -> Option<u64> {
+    res.headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,120 @@
+use crate::{Result, error::Error};
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+const TOKENS_PATH: &str = "filecrab/delete_tokens.toml";
+
+/// Represents the deletion tokens of what the user shared, by instance name and memorable ID.
+#[derive(Deserialize, Serialize, Default)]
+pub(super) struct DeleteTokens {
+    #[serde(default)]
+    instances: HashMap<String, HashMap<String, DeleteToken>>,
+}
+
+/// Represents the deletion token of a shared file or text.
+#[derive(Deserialize, Serialize, Clone)]
+pub(super) struct DeleteToken {
+    pub(super) kind: Kind,
+    pub(super) token: String,
+}
+
+/// What has been shared.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Kind {
+    File,
+    Text,
+}
+
+impl Kind {
+    /// Returns the filecrab endpoint used to delete this kind of element.
+    pub(super) fn endpoint(&self) -> &'static str {
+        match self {
+            Kind::File => "asset",
+            Kind::Text => "text",
+        }
+    }
+
+    /// Returns the path of the pages showing this kind of element on the web frontend.
+    pub(super) fn web_path(&self) -> &'static str {
+        match self {
+            Kind::File => "d",
+            Kind::Text => "t",
+        }
+    }
+}
+
+impl DeleteTokens {
+    /// Returns the path of the tokens file.
+    fn path() -> Result<PathBuf> {
+        match dirs::config_dir() {
+            Some(config_dir) => Ok(config_dir.join(TOKENS_PATH)),
+            None => Err(Error::ConfigNotFound),
+        }
+    }
+
+    /// Loads the tokens, an empty set is returned if nothing has been saved yet.
+    pub(super) async fn load() -> Result<DeleteTokens> {
+        let path = DeleteTokens::path()?;
+
+        if !path.exists() {
+            return Ok(DeleteTokens::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .await
+            .map_err(|err| Error::ReadFile {
+                path: format!("{}", path.display()),
+                source: err,
+            })?;
+
+        toml::from_str(&content).map_err(Error::ParseToml)
+    }
+
+    /// Writes the tokens to the tokens file.
+    pub(super) async fn save(&self) -> Result {
+        let path = DeleteTokens::path()?;
+
+        let parent = match path.parent() {
+            Some(parent) => parent,
+            None => return Err(Error::NoParentDir),
+        };
+
+        // Create dir all if needed
+        fs::create_dir_all(parent)
+            .await
+            .map_err(Error::CreateConfigDir)?;
+        fs::write(&path, &toml::to_string(self).map_err(Error::SerializeToml)?)
+            .await
+            .map_err(|err| Error::WriteFile {
+                path: format!("{}", path.display()),
+                source: err,
+            })
+    }
+
+    /// Returns the token of the given ID in the given instance.
+    pub(super) fn get(&self, instance: &str, id: &str) -> Option<&DeleteToken> {
+        self.instances.get(instance).and_then(|ids| ids.get(id))
+    }
+
+    /// Adds the token of the given ID in the given instance.
+    pub(super) fn insert(&mut self, instance: &str, id: &str, token: DeleteToken) {
+        self.instances
+            .entry(instance.to_string())
+            .or_default()
+            .insert(id.to_string(), token);
+    }
+
+    /// Removes the token of the given ID in the given instance.
+    pub(super) fn remove(&mut self, instance: &str, id: &str) {
+        if let Some(ids) = self.instances.get_mut(instance) {
+            ids.remove(id);
+
+            if ids.is_empty() {
+                self.instances.remove(instance);
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use indicatif::ProgressBar;
+use reqwest::{Client, RequestBuilder, Response, StatusCode, header};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{Cli, UploadMetadata, UploadResponse};
+use crate::{Result, error::Error};
+
+/// Version of the tus protocol spoken with the server.
+const TUS_VERSION: &str = "1.0.0";
+
+/// Size of the chunks sent, a multiple of the parts the server stores so that it keeps every byte.
+const CHUNK_SIZE: usize = 32 * 1024 * 1024;
+
+/// Number of times sending a chunk is retried before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Header carrying the memorable id of the file once the upload is complete.
+const MEMO_ID_HEADER: &str = "filecrab-memo-id";
+
+/// Header carrying the deletion token of the file.
+const DELETE_TOKEN_HEADER: &str = "filecrab-delete-token";
+
+/// Progress of an upload as reported by the server.
+struct Progress {
+    offset: u64,
+    memo_id: Option<String>,
+}
+
+/// Uploads the content of the reader with the tus protocol. Chunks are kept in memory until the
+/// server acknowledged them, so that a dropped connection only resends the current chunk.
+///
+/// The length can be left unknown, ex. for encrypted content, it is then sent with the last chunk.
+pub(super) async fn upload<R>(
+    url: &str,
+    api_key: &str,
+    mut reader: R,
+    length: Option<u64>,
+    metadata: &UploadMetadata,
+    pb: &ProgressBar,
+) -> Result<UploadResponse>
+where
+    R: AsyncRead + Unpin,
+{
+    let client = Client::new();
+
+    // Creates the upload.
+    let req = client
+        .post(format!("{url}/api/tus"))
+        .header("filecrab-key", api_key)
+        .header("tus-resumable", TUS_VERSION)
+        .header("upload-metadata", encode_metadata(metadata));
+    let req = match length {
+        Some(length) => req.header("upload-length", length),
+        None => req.header("upload-defer-length", "1"),
+    };
+    let res = check_status(req.send().await?).await?;
+
+    let location =
+        header_str(&res, header::LOCATION.as_str()).ok_or(Error::MissingUploadLocation)?;
+    let upload_url = format!("{url}{location}");
+    let delete_token = header_str(&res, DELETE_TOKEN_HEADER).unwrap_or_default();
+
+    let mut offset = 0;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    loop {
+        // Reads the next chunk, only the last one is shorter.
+        chunk.clear();
+        (&mut reader)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)
+            .await
+            .map_err(|err| Error::ReadFromReader {
+                r#type: String::from("upload"),
+                source: err,
+            })?;
+        let last = chunk.len() < CHUNK_SIZE;
+        let start = offset;
+        let end = start + chunk.len() as u64;
+
+        // Sends the chunk, the server may keep only the beginning of it and the rest is sent
+        // again.
+        let mut attempt = 0;
+        loop {
+            let sent = chunk[(offset - start) as usize..].to_vec();
+            let res = send_chunk(
+                &client,
+                &upload_url,
+                api_key,
+                offset,
+                sent,
+                last.then_some(end),
+            );
+
+            let progress = match res.await {
+                Ok(progress) => progress,
+                Err(err) if err.is_interruption() && attempt < MAX_ATTEMPTS => {
+                    attempt += 1;
+                    pb.println(format!(
+                        "Upload interrupted, resuming ({attempt}/{MAX_ATTEMPTS})..."
+                    ));
+                    tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+
+                    // Asks the server what it kept.
+                    match current_progress(&client, &upload_url, api_key).await {
+                        Ok(progress) => progress,
+                        Err(err) if err.is_interruption() => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+
+            if progress.offset < start || progress.offset > end {
+                return Err(Error::InvalidUploadOffset(progress.offset));
+            }
+            offset = progress.offset;
+
+            if let Some(memo_id) = progress.memo_id {
+                return Ok(UploadResponse {
+                    id: memo_id,
+                    delete_token,
+                });
+            }
+            if offset == end && !last {
+                break;
+            }
+        }
+    }
+}
+
+/// Sends a chunk starting at the given offset and returns the progress of the upload.
+async fn send_chunk(
+    client: &Client,
+    upload_url: &str,
+    api_key: &str,
+    offset: u64,
+    chunk: Vec<u8>,
+    length: Option<u64>,
+) -> Result<Progress> {
+    let mut req = client
+        .patch(upload_url)
+        .header("filecrab-key", api_key)
+        .header("tus-resumable", TUS_VERSION)
+        .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+        .header("upload-offset", offset);
+    if let Some(length) = length {
+        req = req.header("upload-length", length);
+    }
+
+    progress(req.body(chunk)).await
+}
+
+/// Asks the server the progress of the upload.
+async fn current_progress(client: &Client, upload_url: &str, api_key: &str) -> Result<Progress> {
+    let req = client
+        .head(upload_url)
+        .header("filecrab-key", api_key)
+        .header("tus-resumable", TUS_VERSION);
+
+    progress(req).await
+}
+
+/// Sends the request and reads the progress from the response headers.
+async fn progress(req: RequestBuilder) -> Result<Progress> {
+    let res = check_status(req.send().await?).await?;
+
+    let offset = header_str(&res, "upload-offset")
+        .and_then(|offset| offset.parse().ok())
+        .ok_or(Error::InvalidUploadOffset(0))?;
+
+    Ok(Progress {
+        offset,
+        memo_id: header_str(&res, MEMO_ID_HEADER),
+    })
+}
+
+/// Returns the response if successful, the error otherwise.
+async fn check_status(res: Response) -> Result<Response> {
+    // The server still writes the previous chunk, it is retried like a dropped connection.
+    if res.status() == StatusCode::LOCKED {
+        return Err(Error::UploadLocked);
+    }
+
+    if !res.status().is_success() {
+        return Err(Cli::upload_error(res).await?);
+    }
+
+    Ok(res)
+}
+
+/// Returns the value of a header, if any and valid.
+fn header_str(res: &Response, name: &str) -> Option<String> {
+    res.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Encodes the metadata of the file in the `Upload-Metadata` format, keys followed by base64
+/// encoded values.
+fn encode_metadata(metadata: &UploadMetadata) -> String {
+    let mut pairs = vec![
+        ("filename", metadata.file_name.clone()),
+        ("encrypted", metadata.encrypted.to_string()),
+    ];
+    if let Some(expire) = &metadata.expire {
+        pairs.push(("expire", expire.clone()));
+    }
+    if let Some(max_downloads) = metadata.max_downloads {
+        pairs.push(("max_downloads", max_downloads.to_string()));
+    }
+    if metadata.signed_only {
+        pairs.push(("signed_only", String::from("true")));
+    }
+
+    pairs
+        .into_iter()
+        .map(|(key, value)| format!("{key} {}", BASE64_STANDARD.encode(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
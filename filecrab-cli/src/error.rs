@@ -55,6 +55,8 @@ pub enum Error {
     CurrentDir(#[source] io::Error),
     #[error("could not open file: {path}, please make sure the file doesn't already exist")]
     OpenFile { path: String, source: io::Error },
+    #[error("could not move the downloaded file to {path}")]
+    MoveFile { path: String, source: io::Error },
     #[error("could not delete temporary out file")]
     DeleteTempFile,
     #[error("could not delete config file")]
@@ -73,6 +75,10 @@ pub enum Error {
     CreateDecryptor(#[source] DecryptError),
     #[error("failed to decrypt data")]
     FailedToDecrypt(#[source] DecryptError),
+    #[error("the file is not a valid encrypted file")]
+    InvalidEncryptedFile,
+    #[error("the decryption stopped unexpectedly")]
+    DecryptionTask(#[source] tokio::task::JoinError),
 
     // Reader and Writer
     #[error("could not write to {type} writer")]
@@ -88,11 +94,36 @@ pub enum Error {
     #[error("could not read reqwest body")]
     ReqwestReadBody(#[source] reqwest::Error),
 
+    #[error("invalid url `{0}`")]
+    InvalidUrl(String),
+
     // Filecrab Response
     #[error("Unsuccessful request. \nStatus: {status}\nBody: {body}")]
     UnsuccessfulRequest { status: String, body: String },
+    #[error("Upload refused, {0}.")]
+    UploadTooLarge(String),
+    #[error(
+        "Upload refused, {0}.\nRun `filecrab usage` to see what you store, and `filecrab delete` to remove what you don't need anymore."
+    )]
+    QuotaExhausted(String),
+    #[error("the server is not ready")]
+    ServerUnavailable,
+    #[error("the download was interrupted, run the same command again to resume it")]
+    DownloadInterrupted(#[source] reqwest::Error),
+    #[error("the partial download does not match the file anymore")]
+    StalePartFile,
+    #[error("the server did not return the location of the upload")]
+    MissingUploadLocation,
+    #[error("the server returned an invalid upload offset: {0}")]
+    InvalidUploadOffset(u64),
+    #[error("the upload is still busy on the server")]
+    UploadLocked,
     #[error("could not retrieve the file name from the headers")]
     MissingFileNameInHeaders,
+    #[error(
+        "there is no deletion token for `{0}` in the active instance, only what you shared can be deleted"
+    )]
+    MissingDeleteToken(String),
 
     // String
     #[error("could not parse utf8 bytes")]
@@ -118,3 +149,14 @@ pub enum Error {
     #[error("Canceled.")]
     UserCancel,
 }
+
+impl Error {
+    /// Whether the error is a network failure after which a download can be resumed.
+    pub fn is_interruption(&self) -> bool {
+        match self {
+            Error::DownloadInterrupted(_) | Error::StalePartFile | Error::UploadLocked => true,
+            Error::Reqwest(err) => err.is_connect() || err.is_timeout() || err.is_request(),
+            _ => false,
+        }
+    }
+}
@@ -1,81 +1,792 @@
-use std::{env, sync::OnceLock};
+// Configuration of the server, layered from the lowest to the highest priority:
+//
+// 1. a TOML file given with `--config` (or `FILECRAB_CONFIG`),
+// 2. the environment variables,
+// 3. the `--set KEY=VALUE` flags of the command line.
+//
+// Every setting is named after its environment variable (ex. `RATE_LIMIT_BURST`). In the file it
+// is lowercased and its prefix becomes a table (ex. `burst` in `[rate_limit]`), see `toml_key`.
+// Loading goes through every setting and reports all the problems at once.
+
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
 
 use chrono::TimeDelta;
 
-use crate::{Error, Result};
+use crate::{Error, Result, time};
+
+static INSTANCE: OnceLock<Config> = OnceLock::new();
 
 //We want to get the config only once
 pub fn config() -> &'static Config {
-    //First the instance is empty
-    static INSTANCE: OnceLock<Config> = OnceLock::new();
+    INSTANCE.get().expect("the config is loaded at startup")
+}
 
-    INSTANCE.get_or_init(|| {
-        Config::load_from_env()
-            .unwrap_or_else(|err| panic!("FATAL WHILE LOADING CONFIG. Cause: {err:?}"))
-    })
+/// Loads the config, it can then be read with `config()`.
+pub fn init(sources: &Sources) -> Result<&'static Config> {
+    let config = Config::load(sources)?;
+
+    Ok(INSTANCE.get_or_init(|| config))
+}
+
+/// Where the files are stored.
+pub enum StorageConfig {
+    S3 {
+        bucket_name: String,
+        region: String,
+        endpoint: String,
+        access_key: String,
+        secret_key: String,
+    },
+    Fs {
+        path: PathBuf,
+    },
+}
+
+/// An address the server listens on, `host:port` or `unix:/path/to/socket`.
+pub enum BindAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl BindAddr {
+    pub fn parse(value: &str) -> Option<BindAddr> {
+        let value = value.trim();
+        match value.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Some(BindAddr::Unix(path.into())),
+            Some(_) => None,
+            None => value.parse().ok().map(BindAddr::Tcp),
+        }
+    }
+}
+
+impl fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddr::Tcp(addr) => write!(f, "{addr}"),
+            BindAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Certificate chain and private key of the TLS listeners, both PEM files reloaded on SIGHUP.
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// An address or a network in CIDR notation, ex. `10.0.0.1` or `10.0.0.0/8`.
+pub struct IpRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn parse(value: &str) -> Option<IpRange> {
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+            None => (value.trim().parse::<IpAddr>().ok()?, None),
+        };
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+
+        (prefix <= max).then_some(IpRange { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Limits the failed lookups (unknown ids, invalid keys) of each client.
+pub struct RateLimitConfig {
+    /// Failures allowed in a row, 0 disables the limit.
+    pub burst: u32,
+    /// Failures given back every minute.
+    pub per_minute: u32,
+    /// How long a client is refused once it has no failure left.
+    pub lockout: Duration,
+    /// Proxies whose `X-Forwarded-For` header is trusted to find the client address.
+    pub trusted_proxies: Vec<IpRange>,
+}
+
+/// Where and to whom the Prometheus metrics are served, disabled when neither is set.
+pub struct MetricsConfig {
+    /// Bearer token required to scrape the metrics.
+    pub token: Option<String>,
+    /// Address of a dedicated listener serving the metrics, instead of the api listener.
+    pub bind: Option<SocketAddr>,
+}
+
+impl MetricsConfig {
+    pub fn enabled(&self) -> bool {
+        self.token.is_some() || self.bind.is_some()
+    }
+}
+
+/// Schedule of the reconciliation of the storage with the database.
+pub struct ReconcileConfig {
+    pub interval: Duration,
+    /// Deletes the orphans found, they are only reported otherwise.
+    pub delete: bool,
+}
+
+/// Connection settings of SurrealDB.
+pub struct SurrealConfig {
+    pub host_or_path: String,
+    pub ns: String,
+    pub dbname: String,
+
+    // In rocksdb mode there's no auth and clippy isn't happy
+    #[cfg_attr(feature = "rocksdb", expect(unused))]
+    pub user: String,
+    #[cfg_attr(feature = "rocksdb", expect(unused))]
+    pub password: String,
+}
+
+/// Where the metadata of the assets and texts is stored.
+pub enum DbConfig {
+    Surreal(SurrealConfig),
+    Sqlite { path: PathBuf },
 }
 
 #[allow(non_snake_case)]
 pub struct Config {
-    pub S3_BUCKET_NAME: String,
-    pub S3_REGION: String,
-    pub S3_ENDPOINT: String,
-    pub S3_ACCESS_KEY: String,
-    pub S3_SECRET_KEY: String,
+    pub BIND: Vec<BindAddr>,
+    /// Serves the tcp addresses over TLS when set, unix sockets stay in clear.
+    pub TLS: Option<TlsConfig>,
 
-    pub MAXIMUM_FILE_SIZE: usize,
+    pub STORAGE: StorageConfig,
+
+    /// Bytes a file can weigh.
+    pub MAXIMUM_FILE_SIZE: u64,
     pub DEFAULT_EXPIRE_TIME: TimeDelta,
-    pub CLEANUP_INTERVAL: u32,
+    pub MAXIMUM_EXPIRE_TIME: TimeDelta,
+    pub CLEANUP_INTERVAL: Duration,
+    pub RECONCILE: ReconcileConfig,
 
-    pub DB_HOST_OR_PATH: String,
-    pub DB_NS: String,
-    pub DB_DBNAME: String,
+    /// Bytes each api key can store, unlimited when not set.
+    pub QUOTA_SIZE: Option<u64>,
+    /// Files each api key can store, unlimited when not set.
+    pub QUOTA_FILES: Option<u64>,
 
-    #[expect(unused)]
-    // In rocksdb mode there's no auth and clippy isn't happy
-    pub DB_USER: String,
-    #[expect(unused)]
-    pub DB_PASSWORD: String,
+    pub DB: DbConfig,
+
+    pub RATE_LIMIT: RateLimitConfig,
+
+    pub METRICS: MetricsConfig,
 
     pub API_KEY: String,
+    /// Secret signing the download links, derived from the api key when not set.
+    pub SIGNING_SECRET: Option<String>,
+}
+
+/// Every setting, by the name of its environment variable.
+const SETTINGS: &[&str] = &[
+    "BIND",
+    "TLS_CERT",
+    "TLS_KEY",
+    "STORAGE_BACKEND",
+    "STORAGE_PATH",
+    "S3_BUCKET_NAME",
+    "S3_REGION",
+    "S3_ENDPOINT",
+    "S3_ACCESS_KEY",
+    "S3_SECRET_KEY",
+    "MAXIMUM_FILE_SIZE",
+    "DEFAULT_EXPIRE_TIME",
+    "MAXIMUM_EXPIRE_TIME",
+    "CLEANUP_INTERVAL",
+    "RECONCILE_INTERVAL",
+    "RECONCILE_DELETE",
+    "QUOTA_SIZE",
+    "QUOTA_FILES",
+    "DB_BACKEND",
+    "DB_HOST_OR_PATH",
+    "DB_NS",
+    "DB_DBNAME",
+    "DB_USER",
+    "DB_PASSWORD",
+    "RATE_LIMIT_BURST",
+    "RATE_LIMIT_PER_MINUTE",
+    "RATE_LIMIT_LOCKOUT",
+    "TRUSTED_PROXIES",
+    "METRICS_TOKEN",
+    "METRICS_BIND",
+    "API_KEY",
+    "SIGNING_SECRET",
+];
+
+/// Prefixes of the settings grouped in a table of the config file.
+const TABLES: &[&str] = &[
+    "RATE_LIMIT",
+    "STORAGE",
+    "METRICS",
+    "RECONCILE",
+    "QUOTA",
+    "TLS",
+    "S3",
+    "DB",
+];
+
+/// Key of a setting in the config file, ex. `RATE_LIMIT_BURST` is `rate_limit.burst`.
+fn toml_key(name: &str) -> String {
+    let name = name.to_uppercase();
+    for table in TABLES {
+        if let Some(key) = name
+            .strip_prefix(table)
+            .and_then(|key| key.strip_prefix('_'))
+        {
+            return format!("{}.{}", table.to_lowercase(), key.to_lowercase());
+        }
+    }
+
+    name.to_lowercase()
+}
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+
+/// Parses a size with its unit, ex. `512KB`, `100MiB` or `10GiB`. Plain numbers are in
+/// `bare_unit`, the unit of the older configs.
+fn parse_size(value: &str, bare_unit: u64) -> Option<u64> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+
+    let unit = match unit.trim().to_lowercase().as_str() {
+        "" => bare_unit,
+        "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000u64.pow(2),
+        "gb" => 1000u64.pow(3),
+        "tb" => 1000u64.pow(4),
+        "kib" => KIB,
+        "mib" => MIB,
+        "gib" => 1024 * MIB,
+        "tib" => 1024 * 1024 * MIB,
+        _ => return None,
+    };
+
+    let bytes = amount * unit as f64;
+    (bytes.is_finite() && bytes < u64::MAX as f64).then_some(bytes as u64)
+}
+
+/// Parses a duration with its unit, ex. `30s`, `15m` or `7d`. Plain numbers are in `bare_unit`,
+/// the unit of the older configs.
+fn parse_duration(value: &str, bare_unit: Duration) -> Option<Duration> {
+    match value.parse::<u32>() {
+        Ok(amount) => bare_unit.checked_mul(amount),
+        Err(_) => time::parse_duration(value)?.to_std().ok(),
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Where the settings come from, besides the environment.
+#[derive(Default)]
+pub struct Sources {
+    /// Settings of the config file, by key.
+    file: HashMap<String, String>,
+    /// Settings of the command line, by key.
+    overrides: HashMap<String, String>,
+}
+
+impl Sources {
+    /// Reads the config file, if any, and the `KEY=VALUE` overrides of the command line. Keys
+    /// are either names of environment variables or keys of the config file.
+    pub fn new(file: Option<&Path>, overrides: &[String]) -> Result<Sources> {
+        let mut sources = Sources::default();
+        let mut problems = Vec::new();
+
+        if let Some(path) = file {
+            let table = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|content| {
+                    content
+                        .parse::<toml::Table>()
+                        .map_err(|err| err.to_string())
+                });
+            match table {
+                Ok(table) => flatten(&table, "", &mut sources.file),
+                Err(err) => problems.push(format!("{}: {}", path.display(), err.trim())),
+            }
+        }
+
+        for value in overrides {
+            match value.split_once('=') {
+                Some((key, value)) => {
+                    let key = key.trim();
+                    let key = if key.contains('.') {
+                        key.to_lowercase()
+                    } else {
+                        toml_key(key)
+                    };
+                    sources.overrides.insert(key, value.to_string());
+                }
+                None => problems.push(format!("--set {value}: expected KEY=VALUE")),
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(sources)
+        } else {
+            Err(Error::InvalidConfig(problems))
+        }
+    }
+}
+
+/// Collects the values of a TOML table by their dotted key, lists are joined with commas.
+fn flatten(table: &toml::Table, prefix: &str, values: &mut HashMap<String, String>) {
+    let to_string = |value: &toml::Value| match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+
+    for (key, value) in table {
+        let key = format!("{prefix}{}", key.to_lowercase());
+        let value = match value {
+            toml::Value::Table(table) => {
+                flatten(table, &format!("{key}."), values);
+                continue;
+            }
+            toml::Value::Array(list) => list.iter().map(to_string).collect::<Vec<_>>().join(","),
+            value => to_string(value),
+        };
+        values.insert(key, value);
+    }
+}
+
+/// Reads the settings, collecting the problems instead of stopping at the first one.
+struct Loader<'a> {
+    sources: &'a Sources,
+    problems: Vec<String>,
+}
+
+impl Loader<'_> {
+    /// Value of a setting from the highest source setting it, an empty value is not set.
+    fn value(&self, name: &'static str) -> Option<String> {
+        let key = toml_key(name);
+
+        self.sources
+            .overrides
+            .get(&key)
+            .cloned()
+            .filter(|value| !value.trim().is_empty())
+            .or_else(|| env::var(name).ok().filter(|value| !value.trim().is_empty()))
+            .or_else(|| self.sources.file.get(&key).cloned())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn problem(&mut self, name: &str, problem: impl fmt::Display) {
+        self.problems.push(format!("{name}: {problem}"));
+    }
+
+    /// Parses an optional setting, `expected` describes a valid value.
+    fn optional<T>(
+        &mut self,
+        name: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+        expected: &str,
+    ) -> Option<T> {
+        let value = self.value(name)?;
+        let parsed = parse(&value);
+        if parsed.is_none() {
+            self.problem(
+                name,
+                format!("invalid value `{value}`, expected {expected}"),
+            );
+        }
+
+        parsed
+    }
+
+    /// Parses a required setting, the default value only stands in until the problems are
+    /// reported.
+    fn required<T: Default>(
+        &mut self,
+        name: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+        expected: &str,
+    ) -> T {
+        if self.value(name).is_none() {
+            self.problem(name, "missing");
+            return T::default();
+        }
+
+        self.optional(name, parse, expected).unwrap_or_default()
+    }
+
+    fn required_str(&mut self, name: &'static str) -> String {
+        self.required(name, |value| Some(value.to_string()), "a value")
+    }
+
+    /// Checks a parsed value, reporting `problem` when `valid` is false. Values already reported
+    /// as missing or invalid are not checked.
+    fn check<T>(
+        &mut self,
+        name: &str,
+        value: T,
+        valid: impl FnOnce(&T) -> bool,
+        problem: &str,
+    ) -> T {
+        let reported = self
+            .problems
+            .iter()
+            .any(|reported| reported.starts_with(&format!("{name}:")));
+        if !reported && !valid(&value) {
+            self.problem(name, problem);
+        }
+
+        value
+    }
 }
 
 impl Config {
-    pub fn load_from_env() -> Result<Config> {
-        Ok(Config {
-            S3_BUCKET_NAME: get_env("S3_BUCKET_NAME")?,
-            S3_REGION: get_env("S3_REGION")?,
-            S3_ENDPOINT: get_env("S3_ENDPOINT")?,
-            S3_ACCESS_KEY: get_env("S3_ACCESS_KEY")?,
-            S3_SECRET_KEY: get_env("S3_SECRET_KEY")?,
-
-            MAXIMUM_FILE_SIZE: get_env("MAXIMUM_FILE_SIZE")?.parse().map_err(|err| {
-                eprintln!("{err}");
-                Error::InvalidEnvType("MAXIMUM_FILE_SIZE")
-            })?,
-            DEFAULT_EXPIRE_TIME: convert_to_hours(get_env("DEFAULT_EXPIRE_TIME")?)?,
-            CLEANUP_INTERVAL: get_env("CLEANUP_INTERVAL")?.parse().map_err(|err| {
-                eprintln!("{err}");
-                Error::InvalidEnvType("CLEANUP_INTERVAL")
-            })?,
-
-            DB_HOST_OR_PATH: get_env("DB_HOST_OR_PATH")?,
-            DB_NS: get_env("DB_NS")?,
-            DB_DBNAME: get_env("DB_DBNAME")?,
-            DB_USER: get_env("DB_USER")?,
-            DB_PASSWORD: get_env("DB_PASSWORD")?,
-            API_KEY: get_env("API_KEY")?,
-        })
-    }
-}
-
-fn get_env(name: &'static str) -> Result<String> {
-    env::var(name).map_err(|_| Error::ConfigMissingEnv(name))
-}
-
-fn convert_to_hours(time: String) -> Result<TimeDelta> {
-    //Convert string to i64
-    let hours: i64 = time.parse().map_err(|_| Error::CouldNotParseInt(time))?;
-
-    TimeDelta::try_hours(hours).ok_or(Error::CouldNotConvertToHours)
+    /// Loads the config from the sources and the environment, every problem is reported.
+    pub fn load(sources: &Sources) -> Result<Config> {
+        let mut l = Loader {
+            sources,
+            problems: Vec::new(),
+        };
+
+        let default_expire_time = load_expire_time(&mut l, "DEFAULT_EXPIRE_TIME");
+        // Defaults to the default expire time, so clients can only shorten it
+        let maximum_expire_time = match l.value("MAXIMUM_EXPIRE_TIME") {
+            Some(_) => load_expire_time(&mut l, "MAXIMUM_EXPIRE_TIME"),
+            None => default_expire_time,
+        };
+        if default_expire_time > maximum_expire_time {
+            l.problem("MAXIMUM_EXPIRE_TIME", "shorter than DEFAULT_EXPIRE_TIME");
+        }
+
+        let config = Config {
+            BIND: load_bind(&mut l),
+            TLS: load_tls(&mut l),
+
+            STORAGE: load_storage(&mut l),
+
+            MAXIMUM_FILE_SIZE: {
+                let size = l.required(
+                    "MAXIMUM_FILE_SIZE",
+                    |value| parse_size(value, MIB),
+                    "a size (ex. 1GiB)",
+                );
+                l.check("MAXIMUM_FILE_SIZE", size, |size| *size > 0, "must not be 0")
+            },
+            DEFAULT_EXPIRE_TIME: default_expire_time,
+            MAXIMUM_EXPIRE_TIME: maximum_expire_time,
+            CLEANUP_INTERVAL: {
+                let interval = l.required(
+                    "CLEANUP_INTERVAL",
+                    |value| parse_duration(value, Duration::from_secs(1)),
+                    "a duration (ex. 1h)",
+                );
+                l.check(
+                    "CLEANUP_INTERVAL",
+                    interval,
+                    |interval| interval.as_secs() > 0,
+                    "must be at least a second",
+                )
+            },
+            RECONCILE: load_reconcile(&mut l),
+
+            QUOTA_SIZE: l.optional(
+                "QUOTA_SIZE",
+                |value| parse_size(value, MIB),
+                "a size (ex. 10GiB)",
+            ),
+            QUOTA_FILES: l.optional("QUOTA_FILES", |value| value.parse().ok(), "a number"),
+
+            DB: load_db(&mut l),
+
+            RATE_LIMIT: load_rate_limit(&mut l),
+
+            METRICS: MetricsConfig {
+                token: l.value("METRICS_TOKEN"),
+                bind: l.optional(
+                    "METRICS_BIND",
+                    |value| value.parse().ok(),
+                    "an address (ex. 127.0.0.1:9100)",
+                ),
+            },
+
+            API_KEY: l.required_str("API_KEY"),
+            SIGNING_SECRET: l.value("SIGNING_SECRET"),
+        };
+
+        // Catch the typos, a misspelled setting would otherwise silently keep its default
+        let known: Vec<String> = SETTINGS.iter().map(|name| toml_key(name)).collect();
+        for (keys, source) in [
+            (&sources.file, "in the config file"),
+            (&sources.overrides, "given with --set"),
+        ] {
+            let mut unknown: Vec<&String> =
+                keys.keys().filter(|key| !known.contains(key)).collect();
+            unknown.sort();
+            for key in unknown {
+                l.problems.push(format!("unknown setting `{key}` {source}"));
+            }
+        }
+
+        if l.problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(Error::InvalidConfig(l.problems))
+        }
+    }
+}
+
+/// Loads the comma separated addresses to listen on, defaults to `0.0.0.0:8080`.
+fn load_bind(l: &mut Loader) -> Vec<BindAddr> {
+    l.optional(
+        "BIND",
+        |value| {
+            value
+                .split(',')
+                .filter(|addr| !addr.trim().is_empty())
+                .map(BindAddr::parse)
+                .collect::<Option<Vec<_>>>()
+                .filter(|binds| !binds.is_empty())
+        },
+        "addresses (ex. 0.0.0.0:8080,unix:/run/filecrab.sock)",
+    )
+    .unwrap_or_else(|| {
+        vec![BindAddr::Tcp(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            8080,
+        ))]
+    })
+}
+
+/// Loads the TLS files, either both or none of them must be set.
+fn load_tls(l: &mut Loader) -> Option<TlsConfig> {
+    match (l.value("TLS_CERT"), l.value("TLS_KEY")) {
+        (Some(cert), Some(key)) => Some(TlsConfig {
+            cert: cert.into(),
+            key: key.into(),
+        }),
+        (None, None) => None,
+        (Some(_), None) => {
+            l.problem("TLS_KEY", "missing, required along TLS_CERT");
+            None
+        }
+        (None, Some(_)) => {
+            l.problem("TLS_CERT", "missing, required along TLS_KEY");
+            None
+        }
+    }
+}
+
+/// Loads an expire time, plain numbers are in hours.
+fn load_expire_time(l: &mut Loader, name: &'static str) -> TimeDelta {
+    let expire = l.required(
+        name,
+        |value| {
+            parse_duration(value, Duration::from_secs(3600))
+                .and_then(|duration| TimeDelta::from_std(duration).ok())
+        },
+        "a duration (ex. 7d)",
+    );
+
+    l.check(
+        name,
+        expire,
+        |expire| *expire > TimeDelta::zero(),
+        "must not be 0",
+    )
+}
+
+/// Loads the rate limit config, by default 10 failures in a row then 5 per minute, with a 15
+/// minutes lockout.
+fn load_rate_limit(l: &mut Loader) -> RateLimitConfig {
+    RateLimitConfig {
+        burst: l
+            .optional("RATE_LIMIT_BURST", |value| value.parse().ok(), "a number")
+            .unwrap_or(10),
+        per_minute: l
+            .optional(
+                "RATE_LIMIT_PER_MINUTE",
+                |value| value.parse().ok(),
+                "a number",
+            )
+            .unwrap_or(5),
+        lockout: l
+            .optional(
+                "RATE_LIMIT_LOCKOUT",
+                |value| parse_duration(value, Duration::from_secs(1)),
+                "a duration (ex. 15m)",
+            )
+            .unwrap_or(Duration::from_secs(900)),
+        trusted_proxies: l
+            .optional(
+                "TRUSTED_PROXIES",
+                |value| {
+                    value
+                        .split(',')
+                        .filter(|proxy| !proxy.trim().is_empty())
+                        .map(IpRange::parse)
+                        .collect()
+                },
+                "addresses or networks (ex. 10.0.0.0/8)",
+            )
+            .unwrap_or_default(),
+    }
+}
+
+/// Loads the reconciliation config, by default the orphans are looked for daily and only reported.
+fn load_reconcile(l: &mut Loader) -> ReconcileConfig {
+    let interval = l
+        .optional(
+            "RECONCILE_INTERVAL",
+            |value| parse_duration(value, Duration::from_secs(1)),
+            "a duration (ex. 1d)",
+        )
+        .unwrap_or(Duration::from_secs(24 * 60 * 60));
+
+    ReconcileConfig {
+        interval: l.check(
+            "RECONCILE_INTERVAL",
+            interval,
+            |interval| interval.as_secs() > 0,
+            "must be at least a second",
+        ),
+        delete: l
+            .optional("RECONCILE_DELETE", parse_bool, "true or false")
+            .unwrap_or(false),
+    }
+}
+
+/// Loads the storage config, defaults to S3 when `STORAGE_BACKEND` is not set.
+fn load_storage(l: &mut Loader) -> StorageConfig {
+    let backend = l
+        .value("STORAGE_BACKEND")
+        .unwrap_or_else(|| "s3".to_string());
+
+    match backend.to_lowercase().as_str() {
+        "s3" => StorageConfig::S3 {
+            bucket_name: l.required_str("S3_BUCKET_NAME"),
+            region: l.required_str("S3_REGION"),
+            endpoint: l.required_str("S3_ENDPOINT"),
+            access_key: l.required_str("S3_ACCESS_KEY"),
+            secret_key: l.required_str("S3_SECRET_KEY"),
+        },
+        "fs" => StorageConfig::Fs {
+            path: l.required_str("STORAGE_PATH").into(),
+        },
+        _ => {
+            l.problem(
+                "STORAGE_BACKEND",
+                format!("invalid value `{backend}`, expected s3 or fs"),
+            );
+            StorageConfig::Fs {
+                path: PathBuf::new(),
+            }
+        }
+    }
+}
+
+/// Loads the database config, defaults to SurrealDB when `DB_BACKEND` is not set.
+fn load_db(l: &mut Loader) -> DbConfig {
+    let backend = l
+        .value("DB_BACKEND")
+        .unwrap_or_else(|| "surreal".to_string());
+
+    match backend.to_lowercase().as_str() {
+        "surreal" => DbConfig::Surreal(SurrealConfig {
+            host_or_path: l.required_str("DB_HOST_OR_PATH"),
+            ns: l.required_str("DB_NS"),
+            dbname: l.required_str("DB_DBNAME"),
+            user: l.required_str("DB_USER"),
+            password: l.required_str("DB_PASSWORD"),
+        }),
+        "sqlite" => DbConfig::Sqlite {
+            path: l.required_str("DB_HOST_OR_PATH").into(),
+        },
+        _ => {
+            l.problem(
+                "DB_BACKEND",
+                format!("invalid value `{backend}`, expected surreal or sqlite"),
+            );
+            DbConfig::Sqlite {
+                path: PathBuf::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("512B", MIB), Some(512));
+        assert_eq!(parse_size("512KB", MIB), Some(512_000));
+        assert_eq!(parse_size("100MB", MIB), Some(100_000_000));
+        assert_eq!(parse_size("2GB", MIB), Some(2_000_000_000));
+        assert_eq!(parse_size("1TB", MIB), Some(1_000_000_000_000));
+        assert_eq!(parse_size("512KiB", MIB), Some(512 * KIB));
+        assert_eq!(parse_size("100MiB", MIB), Some(100 * MIB));
+        assert_eq!(parse_size("10GiB", MIB), Some(10 * 1024 * MIB));
+        assert_eq!(parse_size("1TiB", MIB), Some(1024 * 1024 * MIB));
+        // Units are case insensitive and can be apart from the number
+        assert_eq!(parse_size("10 mib", MIB), Some(10 * MIB));
+        assert_eq!(parse_size("1.5KiB", MIB), Some(1536));
+    }
+
+    #[test]
+    fn parse_size_bare_unit() {
+        assert_eq!(parse_size("100", MIB), Some(100 * MIB));
+        assert_eq!(parse_size("100", 1), Some(100));
+        assert_eq!(parse_size("0.5", MIB), Some(MIB / 2));
+    }
+
+    #[test]
+    fn parse_size_invalid() {
+        for value in ["", "MB", "-5MB", "1.2.3MB", "5XB", "5 M B"] {
+            assert_eq!(parse_size(value, MIB), None, "{value}");
+        }
+        // Sizes which do not fit in 64 bits
+        assert_eq!(parse_size("99999999999TiB", MIB), None);
+    }
+
+    #[test]
+    fn parse_duration_bare_unit() {
+        assert_eq!(
+            parse_duration("24", Duration::from_secs(3600)),
+            Some(Duration::from_secs(24 * 3600))
+        );
+        assert_eq!(
+            parse_duration("15m", Duration::from_secs(3600)),
+            Some(Duration::from_secs(15 * 60))
+        );
+        assert_eq!(parse_duration("15x", Duration::from_secs(3600)), None);
+    }
 }
@@ -5,21 +5,18 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Error, Debug, Serialize, Clone)]
 pub enum Error {
-    #[error("missing environment variable {0}")]
-    ConfigMissingEnv(&'static str),
-
-    #[error("the value of the environment {0} inavild")]
-    InvalidEnvType(&'static str),
+    #[error("invalid configuration:\n  - {}", .0.join("\n  - "))]
+    InvalidConfig(Vec<String>),
 
     #[error("error initializing the model manager")]
     CouldNotInitModelManager,
 
-    #[error("error initializing server tcp listener: {0}")]
-    CouldNotInitTcpListener(&'static str),
+    #[error("error binding {0}")]
+    CouldNotBind(String),
 
-    #[error("error converting value to hours")]
-    CouldNotConvertToHours,
+    #[error("error loading the TLS certificate and key: {0}")]
+    CouldNotInitTls(String),
 
-    #[error("could not parse int from string {0}")]
-    CouldNotParseInt(String),
+    #[error("error initializing the metrics: {0}")]
+    CouldNotInitMetrics(String),
 }
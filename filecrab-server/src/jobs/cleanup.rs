@@ -0,0 +1,62 @@
+// Deletes what has expired: the assets with their files, the texts, the abandoned uploads and the
+// nonces of the signed links.
+//
+// A file is deleted from the storage before the row of its asset. When the storage fails the row
+// is kept, so the deletion is tried again at the next run instead of leaving the file behind.
+
+use super::Outcome;
+use crate::{
+    model::{
+        ModelManager, Result,
+        asset::Asset,
+        job::{JobRun, ObjectFailure},
+        link::SignedLink,
+        text::Text,
+        upload::Upload,
+    },
+    telemetry,
+};
+
+pub const NAME: &str = "cleanup";
+
+pub async fn run(mm: ModelManager) -> Result<Outcome> {
+    let mut outcome = Outcome::default();
+
+    for id in Asset::expired(&mm).await? {
+        match mm.delete_files(vec![id.clone()]).await {
+            Ok(()) => {
+                Asset::delete(mm.clone(), &id).await?;
+                ObjectFailure::clear(&mm, "asset", &id).await?;
+                outcome.add("asset", 1);
+            }
+            Err(err) => outcome.fail(&mm, NAME, "asset", &id, err).await?,
+        }
+    }
+
+    let texts = Text::clean_text(&mm).await?;
+    outcome.add("text", texts);
+
+    // The parts of the unfinished uploads are dropped along with their row
+    for upload in Upload::expired(&mm).await? {
+        match Upload::delete(mm.clone(), &upload).await {
+            Ok(()) => {
+                ObjectFailure::clear(&mm, "upload", &upload.object_key).await?;
+                outcome.add("upload", 1);
+            }
+            Err(err) => {
+                outcome
+                    .fail(&mm, NAME, "upload", &upload.object_key, err)
+                    .await?
+            }
+        }
+    }
+
+    SignedLink::clean_nonces(&mm).await?;
+    JobRun::clean_history(&mm).await?;
+
+    for (kind, count) in &outcome.counts {
+        telemetry::record_cleaned(kind, *count);
+    }
+
+    Ok(outcome)
+}
@@ -0,0 +1,292 @@
+// Background jobs, run on a schedule and on demand by the admins.
+//
+// A job never runs twice at the same time, a run asked while the job is running is refused. A
+// failed attempt is retried with an exponential backoff, and every run is recorded in the database
+// so the admins can see what the jobs did.
+
+mod cleanup;
+pub mod reconcile;
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use rand::distr::{Alphanumeric, SampleString};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::config,
+    model::{
+        self, ModelManager,
+        job::{JobRun, ObjectFailure, RunStatus},
+    },
+    telemetry,
+};
+
+/// Attempts made by a run before it is recorded as failed.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled at each attempt.
+const RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Spread of the waits, so instances started together do not run their jobs together.
+const JITTER: f64 = 0.1;
+
+static JOBS: OnceLock<Jobs> = OnceLock::new();
+
+type JobFuture = Pin<Box<dyn Future<Output = model::Result<Outcome>> + Send>>;
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("no job named `{0}`")]
+    NotFound(String),
+    #[error("the job `{0}` is already running")]
+    AlreadyRunning(&'static str),
+}
+
+/// A job as shown to the admins.
+#[derive(Debug, Serialize)]
+pub struct JobInfo {
+    pub name: &'static str,
+    pub interval_seconds: u64,
+    pub running: bool,
+}
+
+struct Jobs {
+    mm: ModelManager,
+    jobs: Vec<Arc<Job>>,
+}
+
+struct Job {
+    name: &'static str,
+    interval: Duration,
+    /// Makes one attempt.
+    run: fn(ModelManager) -> JobFuture,
+    running: AtomicBool,
+}
+
+impl Job {
+    fn new(name: &'static str, interval: Duration, run: fn(ModelManager) -> JobFuture) -> Arc<Job> {
+        Arc::new(Job {
+            name,
+            interval,
+            run,
+            running: AtomicBool::new(false),
+        })
+    }
+
+    /// Marks the job as running, unless it already is.
+    fn try_start(self: &Arc<Self>) -> Option<RunningGuard> {
+        self.running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| RunningGuard(self.clone()))
+    }
+
+    fn info(&self) -> JobInfo {
+        JobInfo {
+            name: self.name,
+            interval_seconds: self.interval.as_secs(),
+            running: self.running.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Marks the job as not running anymore once dropped, even if the run panicked.
+struct RunningGuard(Arc<Job>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.running.store(false, Ordering::Release);
+    }
+}
+
+/// What an attempt of a job did.
+#[derive(Debug, Default)]
+struct Outcome {
+    counts: BTreeMap<&'static str, u64>,
+    failures: u64,
+}
+
+impl Outcome {
+    fn add(&mut self, kind: &'static str, count: u64) {
+        if count > 0 {
+            *self.counts.entry(kind).or_default() += count;
+        }
+    }
+
+    /// Records an object the job could not handle, it is tried again at the next run.
+    async fn fail(
+        &mut self,
+        mm: &ModelManager,
+        job: &'static str,
+        kind: &'static str,
+        key: &str,
+        err: model::ModelManagerError,
+    ) -> model::Result<()> {
+        self.failures += 1;
+
+        object_failed(mm, job, kind, key, err).await
+    }
+}
+
+/// Logs and records an object a job could not handle.
+async fn object_failed(
+    mm: &ModelManager,
+    job: &'static str,
+    kind: &'static str,
+    key: &str,
+    err: model::ModelManagerError,
+) -> model::Result<()> {
+    warn!("job {job} could not handle {kind} {key}: {err}");
+    telemetry::record_object_failure(job, kind);
+
+    ObjectFailure::record(mm, kind, key, &err.to_string()).await
+}
+
+/// Starts the schedules of the jobs.
+pub fn start(mm: ModelManager) {
+    let jobs = vec![
+        Job::new(cleanup::NAME, config().CLEANUP_INTERVAL, |mm| {
+            Box::pin(cleanup::run(mm))
+        }),
+        Job::new(reconcile::NAME, config().RECONCILE.interval, |mm| {
+            Box::pin(reconcile::run(mm))
+        }),
+    ];
+
+    for job in &jobs {
+        telemetry::record_job_interval(job.name, job.interval);
+        tokio::spawn(schedule(mm.clone(), job.clone()));
+    }
+
+    if JOBS.set(Jobs { mm, jobs }).is_err() {
+        error!("the jobs were started twice");
+    }
+}
+
+pub fn list() -> Vec<JobInfo> {
+    JOBS.get()
+        .map(|jobs| jobs.jobs.iter().map(|job| job.info()).collect())
+        .unwrap_or_default()
+}
+
+pub fn info(name: &str) -> Result<JobInfo, JobError> {
+    find(name).map(|(_, job)| job.info())
+}
+
+/// Starts a run of the job in the background, fails if the job is already running.
+pub fn trigger(name: &str) -> Result<(), JobError> {
+    let (mm, job) = find(name)?;
+    let guard = job.try_start().ok_or(JobError::AlreadyRunning(job.name))?;
+
+    tokio::spawn(execute(mm.clone(), guard, true));
+
+    Ok(())
+}
+
+fn find(name: &str) -> Result<(&'static ModelManager, &'static Arc<Job>), JobError> {
+    JOBS.get()
+        .and_then(|jobs| {
+            jobs.jobs
+                .iter()
+                .find(|job| job.name == name)
+                .map(|job| (&jobs.mm, job))
+        })
+        .ok_or_else(|| JobError::NotFound(name.to_string()))
+}
+
+/// Runs the job at every interval, skipping the runs due while it is still running.
+async fn schedule(mm: ModelManager, job: Arc<Job>) {
+    loop {
+        sleep(jittered(job.interval)).await;
+
+        match job.try_start() {
+            Some(guard) => execute(mm.clone(), guard, false).await,
+            None => info!(
+                "job {} is still running, skipping its scheduled run",
+                job.name
+            ),
+        }
+    }
+}
+
+/// Makes attempts until one succeeds or none are left, then records the run.
+async fn execute(mm: ModelManager, guard: RunningGuard, manual: bool) {
+    let job = &guard.0;
+    let started_at = Utc::now();
+    let start = Instant::now();
+
+    let mut attempts = 0;
+    let res = loop {
+        attempts += 1;
+
+        match (job.run)(mm.clone()).await {
+            Ok(outcome) => break Ok(outcome),
+            Err(err) if attempts < MAX_ATTEMPTS => {
+                let delay = jittered(RETRY_BACKOFF * 2u32.pow(attempts - 1));
+                warn!(
+                    "job {} failed (attempt {attempts}/{MAX_ATTEMPTS}), retrying in {}s: {err}",
+                    job.name,
+                    delay.as_secs()
+                );
+                sleep(delay).await;
+            }
+            Err(err) => break Err(err),
+        }
+    };
+
+    let (status, outcome, error) = match res {
+        Ok(outcome) if outcome.failures > 0 => (RunStatus::Partial, outcome, None),
+        Ok(outcome) => (RunStatus::Success, outcome, None),
+        Err(err) => {
+            error!("job {} failed after {attempts} attempts: {err}", job.name);
+            (RunStatus::Failed, Outcome::default(), Some(err.to_string()))
+        }
+    };
+
+    telemetry::record_job_run(job.name, status.as_str(), start.elapsed());
+    info!(
+        "{:12} - {} {} {:?}",
+        "JOB",
+        job.name,
+        status.as_str(),
+        outcome.counts
+    );
+
+    let run = JobRun {
+        id: Alphanumeric.sample_string(&mut rand::rng(), 20),
+        job: job.name.to_string(),
+        manual,
+        started_at,
+        finished_at: Utc::now(),
+        status,
+        attempts,
+        counts: outcome
+            .counts
+            .into_iter()
+            .map(|(kind, count)| (kind.to_string(), count))
+            .collect(),
+        failures: outcome.failures,
+        error,
+    };
+    if let Err(err) = JobRun::create(&mm, &run).await {
+        error!("could not record the run of job {}: {err}", job.name);
+    }
+}
+
+/// Spreads a duration by up to `JITTER` either way.
+fn jittered(duration: Duration) -> Duration {
+    duration.mul_f64(rand::random_range(1.0 - JITTER..=1.0 + JITTER))
+}
@@ -0,0 +1,215 @@
+// Reconciliation of the storage with the database. It finds the files without an asset, left
+// behind when an upload failed once its file was stored, and the assets without a file, left when
+// the deletion of a file went through but not the deletion of its asset.
+//
+// An upload reserves its asset before storing its file, but the asset is only listed once committed,
+// after its file is stored. The files of the pending assets, and those assembled by tus uploads whose
+// asset is not committed yet, are not orphans however old they are. Everything is listed before the
+// files, so the file of a listed asset is never missing only because it is not stored yet. Recent
+// files and assets are left alone all the same: what is committed or deleted while the job runs only
+// shows on one side.
+
+use std::collections::HashSet;
+
+use chrono::{TimeDelta, Utc};
+use tracing::warn;
+
+use super::{Outcome, object_failed};
+use crate::{
+    config::config,
+    model::{ModelManager, Result, asset::Asset, upload::Upload},
+};
+
+pub const NAME: &str = "reconcile";
+
+/// Age under which a file without an asset, or an asset without a file, is not an orphan yet.
+const GRACE_PERIOD: TimeDelta = TimeDelta::hours(1);
+
+/// What a reconciliation found, and deleted unless it was a dry run.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub files: usize,
+    pub assets: usize,
+    /// Files without an asset.
+    pub orphan_files: Vec<String>,
+    /// Total size of the files without an asset.
+    pub orphan_bytes: u64,
+    /// Assets without a file.
+    pub orphan_assets: Vec<String>,
+    /// Files without an asset, not counted as orphans because they are recent.
+    pub recent_files: usize,
+    /// Assets without a file, not counted as orphans because they are recent.
+    pub recent_assets: usize,
+    pub deleted_files: u64,
+    pub deleted_assets: u64,
+    /// Orphans which could not be deleted.
+    pub failures: u64,
+}
+
+impl From<Report> for Outcome {
+    fn from(report: Report) -> Self {
+        let mut outcome = Outcome::default();
+        outcome.add("orphan_file", report.orphan_files.len() as u64);
+        outcome.add("orphan_asset", report.orphan_assets.len() as u64);
+        outcome.add("recent_file", report.recent_files as u64);
+        outcome.add("recent_asset", report.recent_assets as u64);
+        outcome.add("deleted_file", report.deleted_files);
+        outcome.add("deleted_asset", report.deleted_assets);
+        outcome.failures = report.failures;
+
+        outcome
+    }
+}
+
+pub(super) async fn run(mm: ModelManager) -> Result<Outcome> {
+    let report = reconcile(&mm, !config().RECONCILE.delete).await?;
+
+    Ok(report.into())
+}
+
+/// Looks for the orphans on both sides and, unless `dry_run`, deletes them.
+pub async fn reconcile(mm: &ModelManager, dry_run: bool) -> Result<Report> {
+    let assets = Asset::list(mm).await?;
+    let asset_ids: HashSet<&str> = assets.iter().map(|asset| asset.id.as_str()).collect();
+    // Files which are expected to get a committed asset
+    let reserved_keys = Asset::list_pending(mm).await?;
+    let stored_keys = Upload::stored_keys(mm).await?;
+    let known_keys: HashSet<&str> = asset_ids
+        .iter()
+        .copied()
+        .chain(reserved_keys.iter().map(String::as_str))
+        .chain(stored_keys.iter().map(String::as_str))
+        .collect();
+    let files = mm.list_files().await?;
+    let file_keys: HashSet<&str> = files.iter().map(|file| file.key.as_str()).collect();
+
+    let mut report = Report {
+        files: files.len(),
+        assets: assets.len(),
+        ..Default::default()
+    };
+
+    let recent = Utc::now() - GRACE_PERIOD;
+    for file in files
+        .iter()
+        .filter(|file| !known_keys.contains(file.key.as_str()))
+    {
+        if file.last_modified.is_none_or(|modified| modified > recent) {
+            report.recent_files += 1;
+        } else {
+            report.orphan_files.push(file.key.clone());
+            report.orphan_bytes += file.size;
+        }
+    }
+    // The assets stored before their creation date was recorded are old enough
+    for asset in assets
+        .iter()
+        .filter(|asset| !file_keys.contains(asset.id.as_str()))
+    {
+        if asset.created_at.is_some_and(|created| created > recent) {
+            report.recent_assets += 1;
+        } else {
+            report.orphan_assets.push(asset.id.clone());
+        }
+    }
+
+    report.orphan_files.sort();
+    report.orphan_assets.sort();
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    for key in &report.orphan_files {
+        match mm.delete_files(vec![key.clone()]).await {
+            Ok(()) => report.deleted_files += 1,
+            Err(err) => {
+                report.failures += 1;
+                object_failed(mm, NAME, "file", key, err).await?;
+            }
+        }
+    }
+
+    // An empty storage with assets is more likely a wrong bucket or path than lost files
+    if files.is_empty() && !report.orphan_assets.is_empty() {
+        warn!(
+            "job {NAME} found no file for any of the {} assets, keeping them",
+            report.assets
+        );
+        return Ok(report);
+    }
+
+    for id in &report.orphan_assets {
+        Asset::delete(mm.clone(), id).await?;
+        report.deleted_assets += 1;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::model::asset::AssetToCreate;
+
+    fn asset(pending: bool) -> AssetToCreate {
+        AssetToCreate {
+            encrypted: false,
+            file_name: "a.txt".to_string(),
+            expire: None,
+            memo_id: None,
+            max_downloads: None,
+            delete_token: None,
+            created_by: None,
+            size: None,
+            created_at: None,
+            signed_only: false,
+            pending,
+        }
+    }
+
+    /// Stores a file modified two grace periods ago.
+    fn old_file(dir: &std::path::Path, key: &str) {
+        let file = File::create(dir.join(key)).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(2 * 60 * 60);
+        file.set_modified(modified).unwrap();
+    }
+
+    #[tokio::test]
+    async fn files_waiting_for_their_asset_are_not_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let mm = ModelManager::for_tests(dir.path()).await;
+
+        // A committed asset, an upload in progress, an assembled tus upload and a lost file
+        mm.store()
+            .create_asset("committed", asset(false))
+            .await
+            .unwrap();
+        mm.store()
+            .create_asset("pending", asset(true))
+            .await
+            .unwrap();
+        let mut upload = Upload::create(mm.clone(), Some(1), asset(false))
+            .await
+            .unwrap();
+        upload.stored = true;
+        mm.store().update_upload(&upload, 0).await.unwrap();
+        for key in ["committed", "pending", upload.object_key.as_str(), "lost"] {
+            old_file(dir.path(), key);
+        }
+
+        let report = reconcile(&mm, false).await.unwrap();
+
+        assert_eq!(report.orphan_files, vec!["lost".to_string()]);
+        assert_eq!(report.deleted_files, 1);
+        assert!(report.orphan_assets.is_empty());
+        assert!(!dir.path().join("lost").exists());
+        assert!(dir.path().join("pending").exists());
+        assert!(dir.path().join(&upload.object_key).exists());
+    }
+}
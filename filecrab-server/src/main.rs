@@ -1,12 +1,19 @@
 mod config;
 mod error;
+mod jobs;
 mod model;
+mod server;
+mod telemetry;
+mod time;
 mod web;
 
 use crate::{
-    config::config,
-    model::{ModelManager, asset::Asset, text::Text},
-    web::routes::routes,
+    config::{Config, Sources, config},
+    model::ModelManager,
+    web::{
+        routes::{DELETE_TOKEN_HEADER, metrics_routes, routes},
+        tus::{CHUNK_SIZE_HEADER, MEMO_ID_HEADER},
+    },
 };
 
 pub use self::error::{Error, Result};
@@ -16,9 +23,9 @@ use axum::{
     body::Bytes,
     http::{HeaderName, HeaderValue, Method, header},
 };
-use clokwerk::{AsyncScheduler, TimeUnits};
-use std::{iter::once, time::Duration};
-use tokio::{net::TcpListener, signal};
+use clap::{Parser, Subcommand};
+use std::{path::PathBuf, process, time::Duration};
+use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::{
     LatencyUnit,
@@ -29,34 +36,123 @@ use tower_http::{
     set_header::SetResponseHeaderLayer,
     trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
 };
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
 
+/// The filecrab server. Its settings come from a TOML file, overridden by the environment, itself
+/// overridden by the `--set` flags.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Path to the TOML config file.
+    #[arg(short, long, env = "FILECRAB_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    /// Overrides a setting, ex. `--set MAXIMUM_FILE_SIZE=1GiB` or `--set rate_limit.burst=20`.
+    #[arg(short, long = "set", value_name = "KEY=VALUE", global = true)]
+    set: Vec<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the server, what is done without a command.
+    #[command(alias = "server")]
+    Serve,
+    /// Checks the configuration and reports every problem found.
+    CheckConfig,
+    /// Looks for the files without an asset and the assets without a file, and deletes them.
+    Reconcile {
+        /// Only reports the orphans.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
     tracing_subscriber::fmt()
         .with_target(true)
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
+    let loaded =
+        Sources::new(args.config.as_deref(), &args.set).and_then(|sources| config::init(&sources));
+
+    if let Some(Command::CheckConfig) = args.command {
+        check_config(loaded);
+        return Ok(());
+    }
+    if let Err(err) = loaded {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+
+    if config().METRICS.enabled() {
+        telemetry::install()?;
+    }
+
     let mm = ModelManager::new().await.map_err(|err| {
         eprintln!("{err}");
         Error::CouldNotInitModelManager
     })?;
 
+    if let Some(Command::Reconcile { dry_run }) = args.command {
+        reconcile(&mm, dry_run).await;
+        return Ok(());
+    }
+
     let filecrab_header = HeaderName::from_static("filecrab-key");
     let filecrab_download_header = HeaderName::from_static("filecrab-file-name");
+    let filecrab_delete_header = HeaderName::from_static(DELETE_TOKEN_HEADER);
 
     // Get the cors middlewares
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([filecrab_header.clone()])
-        .expose_headers([filecrab_download_header])
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::DELETE,
+            Method::HEAD,
+            Method::PATCH,
+        ])
+        .allow_headers([
+            filecrab_header.clone(),
+            filecrab_delete_header.clone(),
+            header::RANGE,
+            header::IF_RANGE,
+            header::CONTENT_TYPE,
+            HeaderName::from_static("tus-resumable"),
+            HeaderName::from_static("upload-length"),
+            HeaderName::from_static("upload-defer-length"),
+            HeaderName::from_static("upload-offset"),
+            HeaderName::from_static("upload-metadata"),
+        ])
+        .expose_headers([
+            filecrab_download_header,
+            header::ACCEPT_RANGES,
+            header::CONTENT_RANGE,
+            header::ETAG,
+            header::LAST_MODIFIED,
+            header::LOCATION,
+            header::RETRY_AFTER,
+            filecrab_delete_header.clone(),
+            HeaderName::from_static(MEMO_ID_HEADER),
+            HeaderName::from_static(CHUNK_SIZE_HEADER),
+            HeaderName::from_static("tus-resumable"),
+            HeaderName::from_static("tus-version"),
+            HeaderName::from_static("upload-length"),
+            HeaderName::from_static("upload-offset"),
+        ])
         .allow_origin(Any);
 
     // Build our middleware stack
     let middleware = ServiceBuilder::new()
-        .layer(SetSensitiveHeadersLayer::new(once(filecrab_header)))
+        .layer(SetSensitiveHeadersLayer::new([
+            filecrab_header,
+            filecrab_delete_header,
+        ]))
             // Add high level tracing/logging to all requests
             .layer(
                 TraceLayer::new_for_http()
@@ -77,68 +173,109 @@ async fn main() -> Result<()> {
             ))
             .layer(cors);
 
-    // Setup cleaning with a schedule
-    let mut scheduler = AsyncScheduler::with_tz(chrono::Utc);
-    // Clone because borrowchecker :)
-    let mmc = mm.clone();
-    scheduler
-        .every(config().CLEANUP_INTERVAL.seconds())
-        .run(move || {
-            let mmc = mmc.clone();
-            async move { clean_database(mmc).await }
-        });
-    // Spawn task that will run and clean
-    tokio::spawn(async move {
-        loop {
-            // Run pending jobs
-            scheduler.run_pending().await;
-            // Sleep for a second
-            tokio::time::sleep(Duration::from_millis(1000)).await;
-        }
-    });
+    // Schedule the cleanup, admins can also run it on demand
+    jobs::start(mm.clone());
 
-    let routes = Router::new().merge(routes(mm.clone())).layer(middleware);
+    let mut routes = Router::new().merge(routes(mm.clone()));
 
-    let listener = TcpListener::bind("0.0.0.0:8080")
-        .await
-        .map_err(|_| Error::CouldNotInitTcpListener("Could not start the listener"))?;
+    // The metrics get their own listener when a bind address is given, otherwise they are served
+    // along the api behind their token
+    match config().METRICS.bind {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|err| Error::CouldNotBind(format!("{addr}: {err}")))?;
+            info!("{:12} - {:?}", "METRICS", listener.local_addr());
 
-    info!("{:12} - {:?}", "LISTENING", listener.local_addr());
-
-    axum::serve(listener, routes.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+            let metrics = metrics_routes(mm.clone());
+            tokio::spawn(async move {
+                if let Err(err) = axum::serve(listener, metrics)
+                    .with_graceful_shutdown(server::shutdown_signal())
+                    .await
+                {
+                    error!("the metrics listener stopped: {err}");
+                }
+            });
+        }
+        None if config().METRICS.enabled() => routes = routes.merge(metrics_routes(mm.clone())),
+        None => {}
+    }
 
-    Ok(())
+    server::serve(routes.layer(middleware)).await
 }
 
-async fn shutdown_signal() {
-    let ctrl_c = async {
-        signal::ctrl_c()
-            .await
-            .expect("failed to install Ctrl+c handler")
+/// Reports the problems of the configuration, exits with an error if there are any.
+fn check_config(loaded: Result<&'static Config>) {
+    let problems = match loaded {
+        // The TLS files are only read once the server starts, check them too
+        Ok(conf) => match conf.TLS.as_ref().map(server::check_tls) {
+            Some(Err(err)) => vec![format!("TLS_CERT, TLS_KEY: {err}")],
+            _ => Vec::new(),
+        },
+        Err(Error::InvalidConfig(problems)) => problems,
+        Err(err) => vec![err.to_string()],
     };
 
-    let terminate = async {
-        signal::unix::signal(signal::unix::SignalKind::terminate())
-            .expect("failed to install signal handler")
-            .recv()
-            .await;
-    };
+    if problems.is_empty() {
+        println!("The configuration is valid.");
+        return;
+    }
 
-    tokio::select! {
-        _ = ctrl_c => {}
-        _ = terminate => {}
+    eprintln!("The configuration has {} problem(s):", problems.len());
+    for problem in problems {
+        eprintln!("  - {problem}");
     }
+    process::exit(1);
 }
 
-async fn clean_database(mm: ModelManager) {
-    info!("Cleaning databases");
-    let res = Asset::clean_assets(mm.clone()).await.unwrap();
-    // Delete assets from the minio
-    mm.delete_files(res).await.unwrap();
+/// Reconciles the storage with the database and prints what was found, exits with an error if
+/// it could not complete.
+async fn reconcile(mm: &ModelManager, dry_run: bool) {
+    let report = match jobs::reconcile::reconcile(mm, dry_run).await {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Could not reconcile the storage with the database: {err}");
+            process::exit(1);
+        }
+    };
+
+    println!("Files in the storage:    {}", report.files);
+    println!("Assets in the database:  {}", report.assets);
+    println!(
+        "Files without an asset:  {} ({} bytes)",
+        report.orphan_files.len(),
+        report.orphan_bytes
+    );
+    for key in &report.orphan_files {
+        println!("  - {key}");
+    }
+    println!("Assets without a file:   {}", report.orphan_assets.len());
+    for id in &report.orphan_assets {
+        println!("  - {id}");
+    }
+    if report.recent_files > 0 {
+        println!(
+            "Recent files without an asset, skipped: {}",
+            report.recent_files
+        );
+    }
+    if report.recent_assets > 0 {
+        println!(
+            "Recent assets without a file, skipped: {}",
+            report.recent_assets
+        );
+    }
 
-    // Delete text
-    Text::clean_text(&mm).await.unwrap();
+    if dry_run {
+        println!("Dry run, nothing was deleted.");
+        return;
+    }
+    println!(
+        "Deleted {} file(s) and {} asset(s).",
+        report.deleted_files, report.deleted_assets
+    );
+    if report.failures > 0 {
+        eprintln!("{} file(s) could not be deleted.", report.failures);
+        process::exit(1);
+    }
 }
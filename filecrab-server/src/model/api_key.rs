@@ -0,0 +1,200 @@
+use chrono::prelude::*;
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ModelManagerError, Result},
+    token,
+};
+use crate::{config::config, model::ModelManager};
+
+/// Id recorded for what the key from the config (`API_KEY`) creates.
+pub const ROOT_KEY_ID: &str = "root";
+
+/// What a key is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Upload files.
+    Upload,
+    /// Paste texts.
+    Paste,
+    /// Copy texts.
+    Copy,
+    /// Manage the keys.
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::Upload, Scope::Paste, Scope::Copy, Scope::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Upload => "upload",
+            Scope::Paste => "paste",
+            Scope::Copy => "copy",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Scope> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == value.trim())
+    }
+}
+
+/// A named key allowed to request the api, only the hash of the secret is stored.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<Scope>,
+    pub expire: Option<DateTime<Utc>>,
+    pub revoked: bool,
+    pub created_at: DateTime<Utc>,
+    /// Bytes the key can store, defaults to `QUOTA_SIZE` when not set.
+    pub quota_size: Option<u64>,
+    /// Files the key can store, defaults to `QUOTA_FILES` when not set.
+    pub quota_files: Option<u64>,
+}
+
+/// What a key currently stores.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// Total size of the files in bytes.
+    pub size: u64,
+    /// Number of files.
+    pub files: u64,
+}
+
+impl ApiKey {
+    /// The key from the config, it has every scope and can not be revoked.
+    fn root() -> ApiKey {
+        ApiKey {
+            id: ROOT_KEY_ID.to_string(),
+            name: ROOT_KEY_ID.to_string(),
+            key_hash: String::new(),
+            scopes: Scope::ALL.to_vec(),
+            expire: None,
+            revoked: false,
+            created_at: DateTime::UNIX_EPOCH,
+            quota_size: None,
+            quota_files: None,
+        }
+    }
+
+    /// Creates a key and returns it along with its secret, which is not stored.
+    pub async fn create(
+        mm: ModelManager,
+        name: String,
+        scopes: Vec<Scope>,
+        expire: Option<DateTime<Utc>>,
+        quota_size: Option<u64>,
+        quota_files: Option<u64>,
+    ) -> Result<(ApiKey, String)> {
+        let secret = token::generate();
+
+        let api_key = ApiKey {
+            id: Alphanumeric.sample_string(&mut rand::rng(), 12),
+            name,
+            key_hash: token::hash(&secret),
+            scopes,
+            expire,
+            revoked: false,
+            created_at: Utc::now(),
+            quota_size,
+            quota_files,
+        };
+
+        mm.store().create_api_key(&api_key).await?;
+
+        Ok((api_key, secret))
+    }
+
+    /// Finds the key matching a secret, revoked and expired keys included.
+    pub async fn read_by_secret(mm: ModelManager, secret: &str) -> Result<ApiKey> {
+        if secret == config().API_KEY {
+            return Ok(ApiKey::root());
+        }
+
+        mm.store().read_api_key_by_hash(&token::hash(secret)).await
+    }
+
+    pub async fn read(mm: ModelManager, id: &str) -> Result<ApiKey> {
+        if id == ROOT_KEY_ID {
+            return Ok(ApiKey::root());
+        }
+
+        mm.store().read_api_key(id).await
+    }
+
+    pub async fn list(mm: ModelManager) -> Result<Vec<ApiKey>> {
+        mm.store().list_api_keys().await
+    }
+
+    pub async fn revoke(mm: ModelManager, id: &str) -> Result<()> {
+        mm.store().revoke_api_key(id).await
+    }
+
+    /// Whether the key is expired at `now`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expire.is_some_and(|expire| expire <= now)
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Bytes the key can store, `None` when unlimited.
+    pub fn size_limit(&self) -> Option<u64> {
+        self.quota_size.or(config().QUOTA_SIZE)
+    }
+
+    /// Files the key can store, `None` when unlimited.
+    pub fn files_limit(&self) -> Option<u64> {
+        self.quota_files.or(config().QUOTA_FILES)
+    }
+
+    /// What the key currently stores. The root key also owns the assets created before the keys
+    /// existed, so a single key setup keeps a global quota.
+    pub async fn usage(&self, mm: ModelManager) -> Result<Usage> {
+        let mut usage = mm.store().usage(Some(&self.id)).await?;
+
+        if self.id == ROOT_KEY_ID {
+            let unowned = mm.store().usage(None).await?;
+            usage.size += unowned.size;
+            usage.files += unowned.files;
+        }
+
+        Ok(usage)
+    }
+
+    /// Checks that a new file of `size` bytes fits in the quota of the key. Fails with
+    /// `QuotaExhausted` when nothing more can be stored and `QuotaExceeded` when the file is too
+    /// large for what is left. A size of 0 only checks that the quota is not used up.
+    pub async fn check_quota(&self, mm: ModelManager, size: u64) -> Result<()> {
+        let (size_limit, files_limit) = (self.size_limit(), self.files_limit());
+        if size_limit.is_none() && files_limit.is_none() {
+            return Ok(());
+        }
+
+        let usage = self.usage(mm).await?;
+
+        if files_limit.is_some_and(|limit| usage.files >= limit)
+            || size_limit.is_some_and(|limit| usage.size >= limit)
+        {
+            return Err(ModelManagerError::QuotaExhausted);
+        }
+
+        if let Some(limit) = size_limit {
+            let remaining = limit - usage.size;
+            if size > remaining {
+                return Err(ModelManagerError::QuotaExceeded { size, remaining });
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,85 +1,193 @@
-use chrono::prelude::*;
+use chrono::{TimeDelta, prelude::*};
 use serde::{Deserialize, Serialize};
-use surrealdb::sql::{Datetime, Thing};
+use tracing::error;
 
-use super::error::{ModelManagerError, Result};
+use super::{error::Result, with_memo_id};
 use crate::{config::config, model::ModelManager};
 
-#[derive(Clone, Deserialize)]
+/// How long an asset can stay reserved, an upload which did not commit by then is cleaned up.
+const PENDING_EXPIRE_TIME: TimeDelta = TimeDelta::days(1);
+
+#[derive(Clone)]
 pub struct Asset {
-    pub id: Thing,
+    pub id: String,
     pub file_name: String,
     pub memo_id: String,
+    pub delete_token: Option<String>,
+    /// Only downloadable with a signed link, the memo id alone is not enough.
+    pub signed_only: bool,
+    pub encrypted: bool,
+    /// Size of the file in bytes, `None` for the assets stored before it was recorded.
+    pub size: Option<u64>,
+    /// `None` for the assets stored before it was recorded.
+    pub created_at: Option<DateTime<Utc>>,
+    pub expire: Option<DateTime<Utc>>,
+    /// Downloads left, `None` when unlimited.
+    pub remaining_downloads: Option<u32>,
+    /// Id of the api key which created the asset.
+    pub created_by: Option<String>,
+}
+
+/// A committed asset, as listed to be compared with the storage.
+pub struct AssetEntry {
+    pub id: String,
+    /// `None` for the assets stored before it was recorded.
+    pub created_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct AssetToCreate {
     pub encrypted: bool,
     pub file_name: String,
-    pub expire: Option<Datetime>,
+    pub expire: Option<DateTime<Utc>>,
     pub memo_id: Option<String>,
+    pub max_downloads: Option<u32>,
+    /// Hash of the token allowing the owner to delete the asset.
+    pub delete_token: Option<String>,
+    /// Id of the api key which created the asset.
+    pub created_by: Option<String>,
+    /// Size of the file in bytes, counted in the quota of the key.
+    pub size: Option<u64>,
+    /// Set once the file is stored.
+    pub created_at: Option<DateTime<Utc>>,
+    /// Only downloadable with a signed link.
+    #[serde(default)]
+    pub signed_only: bool,
+    /// Reserved while its file is stored, the asset can not be downloaded until it is committed.
+    #[serde(default)]
+    pub pending: bool,
 }
 
 impl Asset {
-    pub async fn create(mm: ModelManager, id: &str, mut data: AssetToCreate) -> Result<Asset> {
-        let db = mm.db();
+    /// Stores the asset under an autogenerated memo id, another one is drawn if it is in use.
+    async fn insert(mm: ModelManager, id: &str, data: AssetToCreate) -> Result<Asset> {
+        with_memo_id(|memo_id| {
+            let data = AssetToCreate {
+                memo_id: Some(memo_id),
+                ..data.clone()
+            };
+            mm.store().create_asset(id, data)
+        })
+        .await
+    }
 
-        // Add an expire time if it's not set
-        if data.expire.is_none() {
-            // If nothing is set default to the config's default expire time
-            let exp = Utc::now() + config().DEFAULT_EXPIRE_TIME;
-            data.expire = Some(exp.into());
-        }
+    /// Deletes the file of the asset, then the asset. When the file can not be deleted the asset
+    /// is kept, so that the cleanup tries again once it expires.
+    pub async fn discard(mm: ModelManager, id: &str) -> Result<()> {
+        mm.delete_files(vec![id.to_string()]).await?;
 
-        //Set the autogenerated memo id
-        data.memo_id = Some(memorable_wordlist::snake_case(40));
+        Asset::delete(mm, id).await
+    }
+
+    pub async fn read_by_memo_id(mm: ModelManager, memo_id: &str) -> Result<Asset> {
+        mm.store().read_asset_by_memo_id(memo_id).await
+    }
 
-        let res: Option<Asset> = db
-            .create(("asset", id))
-            .content(data)
-            .await
-            .map_err(ModelManagerError::CreateAsset)?;
+    /// Counts a download of the asset and returns how many are left, `None` when unlimited.
+    /// Fails with `AssetNotFound` once the limit has been reached.
+    pub async fn register_download(mm: ModelManager, id: &str) -> Result<Option<u32>> {
+        mm.store().register_download(id).await
+    }
 
-        res.ok_or_else(|| ModelManagerError::AssetNotFound)
+    pub async fn delete(mm: ModelManager, id: &str) -> Result<()> {
+        mm.store().delete_asset(id).await
     }
 
-    pub async fn read_by_memo_id(mm: ModelManager, memo_id: &str) -> Result<Asset> {
-        let db = mm.db();
+    /// Returns every committed asset, their ids are also the keys of their files.
+    pub async fn list(mm: &ModelManager) -> Result<Vec<AssetEntry>> {
+        mm.store().list_assets().await
+    }
 
-        let res: Option<Asset> = db
-            .query("SELECT * FROM asset WHERE memo_id = $memo_id LIMIT 1")
-            .bind(("memo_id", memo_id.to_string()))
-            .await
-            .map_err(ModelManagerError::SearchAsset)?
-            .take(0)
-            .map_err(ModelManagerError::TakeError)?;
+    /// Returns the ids of the assets being uploaded, their files may be stored already.
+    pub async fn list_pending(mm: &ModelManager) -> Result<Vec<String>> {
+        mm.store().list_pending_assets().await
+    }
 
-        res.ok_or_else(|| ModelManagerError::AssetNotFound)
+    /// Returns the ids of the expired assets, which are also the keys of their files.
+    pub async fn expired(mm: &ModelManager) -> Result<Vec<String>> {
+        mm.store().expired_assets(Utc::now()).await
     }
+}
 
-    pub async fn clean_assets(mm: ModelManager) -> Result<Vec<String>> {
-        let db = mm.db();
+/// An asset reserved before its file is stored. Unless it is committed, the file and the asset are
+/// deleted once it is dropped, including when the request is cancelled midway.
+pub struct PendingAsset {
+    mm: ModelManager,
+    pub asset: Asset,
+    committed: bool,
+    /// Only the asset is deleted once dropped, the file is left to its owner.
+    keep_file: bool,
+}
 
-        let now: Datetime = Utc::now().into();
+impl PendingAsset {
+    /// Reserves the asset under the key `id` of its file, the file can be stored afterwards.
+    pub async fn reserve(mm: ModelManager, id: &str, mut data: AssetToCreate) -> Result<Self> {
+        data.expire = Some(Utc::now() + PENDING_EXPIRE_TIME);
+        data.pending = true;
 
-        // Get all deletable assets
-        let res: Vec<Thing> = db
-            .query("SELECT id FROM asset WHERE expire <= $now")
-            .bind(("now", now.clone()))
-            .await
-            .map_err(ModelManagerError::DeleteAsset)?
-            .take((0, "id"))
-            .map_err(ModelManagerError::TakeError)?;
+        let asset = Asset::insert(mm.clone(), id, data).await?;
 
-        // Collect only the id out of the things
-        let res = res.into_iter().map(|v| v.id.to_string()).collect();
+        Ok(PendingAsset {
+            mm,
+            asset,
+            committed: false,
+            keep_file: false,
+        })
+    }
+
+    /// Keeps the file if the asset is dropped without being committed, for a file which can be
+    /// committed again later.
+    pub fn keep_file(&mut self) {
+        self.keep_file = true;
+    }
 
-        let _ = db
-            .query("DELETE asset WHERE expire <= $now")
-            .bind(("now", now.clone()))
-            .await
-            .map_err(ModelManagerError::DeleteAsset)?;
+    /// Saves the final metadata of the asset and makes it available.
+    pub async fn commit(mut self, mut data: AssetToCreate) -> Result<Asset> {
+        // If nothing is set default to the config's default expire time
+        if data.expire.is_none() {
+            data.expire = Some(Utc::now() + config().DEFAULT_EXPIRE_TIME);
+        }
+        data.created_at = Some(Utc::now());
+        data.pending = false;
+
+        self.mm
+            .store()
+            .commit_asset(&self.asset.id, data.clone())
+            .await?;
+        self.committed = true;
+
+        Ok(Asset {
+            file_name: data.file_name,
+            delete_token: data.delete_token,
+            signed_only: data.signed_only,
+            encrypted: data.encrypted,
+            size: data.size,
+            created_at: data.created_at,
+            expire: data.expire,
+            remaining_downloads: data.max_downloads,
+            ..self.asset.clone()
+        })
+    }
+}
+
+impl Drop for PendingAsset {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
 
-        Ok(res)
+        let mm = self.mm.clone();
+        let id = self.asset.id.clone();
+        let keep_file = self.keep_file;
+        tokio::spawn(async move {
+            let res = if keep_file {
+                Asset::delete(mm, &id).await
+            } else {
+                Asset::discard(mm, &id).await
+            };
+            if let Err(err) = res {
+                error!("could not roll back the upload of {id}: {err}");
+            }
+        });
     }
 }
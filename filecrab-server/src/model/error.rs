@@ -11,6 +11,12 @@ pub enum ModelManagerError {
     #[error(transparent)]
     S3Error(#[from] S3Error),
 
+    //Storage
+    #[error("object not found in the storage")]
+    ObjectNotFound,
+    #[error("invalid object key: {0}")]
+    InvalidObjectKey(String),
+
     //SurrealDB
     #[error("error connecting to new surrealdb database: {0}")]
     NewDB(#[source] surrealdb::Error),
@@ -31,6 +37,14 @@ pub enum ModelManagerError {
     CouldNotSetTableIndex(#[source] surrealdb::Error),
     #[error("error using take method on surrealdb result {0}")]
     TakeError(#[source] surrealdb::Error),
+    #[error("the database is not healthy: {0}")]
+    DbHealth(#[source] surrealdb::Error),
+
+    //SQLite
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("sqlite task failed: {0}")]
+    SqliteTask(#[from] tokio::task::JoinError),
 
     //Assets
     #[error("create asset error")]
@@ -41,6 +55,8 @@ pub enum ModelManagerError {
     DeleteAsset(#[source] surrealdb::Error),
     #[error("asset not found")]
     AssetNotFound,
+    #[error("the memo id is already in use")]
+    MemoIdConflict,
 
     //Texts
     #[error("create text error")]
@@ -52,7 +68,61 @@ pub enum ModelManagerError {
     #[error("text not found")]
     TextNotFound,
 
+    //Uploads
+    #[error("create upload error")]
+    CreateUpload(#[source] surrealdb::Error),
+    #[error("search upload error")]
+    SearchUpload(#[source] surrealdb::Error),
+    #[error("update upload error")]
+    UpdateUpload(#[source] surrealdb::Error),
+    #[error("delete upload error")]
+    DeleteUpload(#[source] surrealdb::Error),
+    #[error("upload not found")]
+    UploadNotFound,
+    #[error("the upload has been modified by another request")]
+    UploadConflict,
+
+    //Api keys
+    #[error("create api key error")]
+    CreateApiKey(#[source] surrealdb::Error),
+    #[error("search api key error")]
+    SearchApiKey(#[source] surrealdb::Error),
+    #[error("update api key error")]
+    UpdateApiKey(#[source] surrealdb::Error),
+    #[error("api key not found")]
+    ApiKeyNotFound,
+
+    //Signed links
+    #[error("create link nonce error")]
+    CreateLinkNonce(#[source] surrealdb::Error),
+    #[error("delete link nonce error")]
+    DeleteLinkNonce(#[source] surrealdb::Error),
+    #[error("the link has already been used")]
+    LinkAlreadyUsed,
+
+    //Jobs
+    #[error("create job run error")]
+    CreateJobRun(#[source] surrealdb::Error),
+    #[error("search job run error")]
+    SearchJobRun(#[source] surrealdb::Error),
+    #[error("delete job run error")]
+    DeleteJobRun(#[source] surrealdb::Error),
+    #[error("update object failure error")]
+    UpdateObjectFailure(#[source] surrealdb::Error),
+    #[error("search object failure error")]
+    SearchObjectFailure(#[source] surrealdb::Error),
+
+    //Quotas
+    #[error("the storage quota of the key is used up")]
+    QuotaExhausted,
+    #[error("the file ({size} bytes) does not fit in the remaining quota ({remaining} bytes)")]
+    QuotaExceeded { size: u64, remaining: u64 },
+
+    //Json
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
     //Stdio
-    #[error("std io error")]
+    #[error("std io error: {0}")]
     StdIo(#[from] std::io::Error),
 }
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use chrono::{TimeDelta, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::error::Result;
+use crate::model::ModelManager;
+
+/// How long the runs and the failures of the jobs are kept.
+const HISTORY_RETENTION: TimeDelta = TimeDelta::days(30);
+
+/// How a run of a job ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    /// Everything was done.
+    Success,
+    /// The run went through but some objects could not be handled, they are retried next time.
+    Partial,
+    /// Every attempt failed.
+    Failed,
+}
+
+impl RunStatus {
+    pub const ALL: [RunStatus; 3] = [RunStatus::Success, RunStatus::Partial, RunStatus::Failed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Success => "success",
+            RunStatus::Partial => "partial",
+            RunStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<RunStatus> {
+        RunStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == value)
+    }
+}
+
+/// A run of a background job, kept for the admins to see what the jobs did.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobRun {
+    pub id: String,
+    pub job: String,
+    /// Asked by an admin rather than scheduled.
+    pub manual: bool,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: RunStatus,
+    /// Attempts made, failed attempts are retried with a backoff.
+    pub attempts: u32,
+    /// What the run did, by kind of object (ex. `asset: 3`).
+    pub counts: BTreeMap<String, u64>,
+    /// Number of objects which could not be handled.
+    pub failures: u64,
+    /// Error of the last attempt when the run failed.
+    pub error: Option<String>,
+}
+
+/// An object a job failed to handle, it stays until a later run succeeds.
+#[derive(Clone, Debug, Serialize)]
+pub struct ObjectFailure {
+    /// Kind of the object, ex. asset or upload.
+    pub kind: String,
+    /// Key of the object in the storage.
+    pub key: String,
+    pub attempts: u32,
+    /// Error of the last attempt.
+    pub error: String,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+impl JobRun {
+    pub async fn create(mm: &ModelManager, run: &JobRun) -> Result<()> {
+        mm.store().create_job_run(run).await
+    }
+
+    /// Lists the last runs of a job, the most recent first.
+    pub async fn list(mm: &ModelManager, job: &str, limit: u32) -> Result<Vec<JobRun>> {
+        mm.store().list_job_runs(job, limit).await
+    }
+
+    /// Forgets the runs and the failures older than the retention.
+    pub async fn clean_history(mm: &ModelManager) -> Result<()> {
+        let before = Utc::now() - HISTORY_RETENTION;
+
+        mm.store().clean_job_runs(before).await?;
+        mm.store().clean_object_failures(before).await
+    }
+}
+
+impl ObjectFailure {
+    /// Records a failed attempt on an object, counting the attempts made on it.
+    pub async fn record(mm: &ModelManager, kind: &str, key: &str, error: &str) -> Result<()> {
+        mm.store()
+            .record_object_failure(kind, key, error, Utc::now())
+            .await
+    }
+
+    /// Forgets the failures of an object once it has been handled.
+    pub async fn clear(mm: &ModelManager, kind: &str, key: &str) -> Result<()> {
+        mm.store().clear_object_failure(kind, key).await
+    }
+
+    pub async fn list(mm: &ModelManager) -> Result<Vec<ObjectFailure>> {
+        mm.store().list_object_failures().await
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::LazyLock;
+
+use chrono::prelude::*;
+use hmac::{Hmac, Mac};
+use rand::distr::{Alphanumeric, SampleString};
+use sha2::Sha256;
+
+use super::error::{ModelManagerError, Result};
+use crate::{config::config, model::ModelManager};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key signing the links, `SIGNING_SECRET` or derived from the api key when not set.
+static SIGNING_KEY: LazyLock<Vec<u8>> = LazyLock::new(|| match &config().SIGNING_SECRET {
+    Some(secret) => secret.as_bytes().to_vec(),
+    None => {
+        let mut mac = HmacSha256::new_from_slice(config().API_KEY.as_bytes())
+            .expect("hmac accepts keys of any size");
+        mac.update(b"filecrab signed links");
+        mac.finalize().into_bytes().to_vec()
+    }
+});
+
+/// A download link signed by the server, valid until it expires and, with a nonce, only once.
+pub struct SignedLink {
+    pub memo_id: String,
+    /// Unix timestamp after which the link is refused.
+    pub expires: i64,
+    /// Single use links carry a nonce, forgotten by the server once used.
+    pub nonce: Option<String>,
+}
+
+impl SignedLink {
+    pub async fn create(
+        mm: ModelManager,
+        memo_id: String,
+        expire: DateTime<Utc>,
+        single_use: bool,
+    ) -> Result<SignedLink> {
+        let nonce = single_use.then(|| Alphanumeric.sample_string(&mut rand::rng(), 24));
+        if let Some(nonce) = &nonce {
+            mm.store().create_link_nonce(nonce, expire).await?;
+        }
+
+        Ok(SignedLink {
+            memo_id,
+            expires: expire.timestamp(),
+            nonce,
+        })
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&SIGNING_KEY).expect("hmac accepts keys of any size");
+        mac.update(self.memo_id.as_bytes());
+        mac.update(b"\n");
+        mac.update(self.expires.to_string().as_bytes());
+        mac.update(b"\n");
+        mac.update(self.nonce.as_deref().unwrap_or_default().as_bytes());
+        mac
+    }
+
+    pub fn signature(&self) -> String {
+        hex::encode(self.mac().finalize().into_bytes())
+    }
+
+    /// Checks the signature of the link in constant time.
+    pub fn verify(&self, signature: &str) -> bool {
+        hex::decode(signature).is_ok_and(|signature| self.mac().verify_slice(&signature).is_ok())
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires <= now.timestamp()
+    }
+
+    /// Uses the link, fails with `LinkAlreadyUsed` if it was single use and has been used.
+    pub async fn consume(&self, mm: ModelManager) -> Result<()> {
+        match &self.nonce {
+            Some(nonce) if !mm.store().consume_link_nonce(nonce).await? => {
+                Err(ModelManagerError::LinkAlreadyUsed)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Path of the download endpoint with the signed query.
+    pub fn path(&self) -> String {
+        let mut path = format!(
+            "/api/download?file={}&expires={}",
+            self.memo_id, self.expires
+        );
+        if let Some(nonce) = &self.nonce {
+            path.push_str(&format!("&nonce={nonce}"));
+        }
+        path.push_str(&format!("&sig={}", self.signature()));
+
+        path
+    }
+
+    /// Forgets the nonces of the expired links.
+    pub async fn clean_nonces(mm: &ModelManager) -> Result<()> {
+        mm.store().clean_link_nonces(Utc::now()).await
+    }
+}
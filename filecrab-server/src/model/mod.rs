@@ -1,173 +1,212 @@
+pub mod api_key;
 pub mod asset;
 mod error;
+pub mod job;
+pub mod link;
+mod storage;
+mod store;
 pub mod text;
+pub mod token;
+pub mod upload;
 
-use std::io;
+use std::{future::Future, io, ops::RangeInclusive, sync::Arc};
 
 pub use error::{ModelManagerError, Result};
 
 use axum::{BoxError, body::Bytes};
 use futures::{Stream, TryStreamExt};
-
-#[cfg(not(feature = "rocksdb"))]
-use surrealdb::opt::auth::Namespace;
-#[cfg(not(feature = "rocksdb"))]
-use surrealdb::opt::auth::Root;
-
-use surrealdb::{
-    Surreal,
-    engine::any::{self, Any},
-};
 use tokio_util::io::StreamReader;
 
-use crate::config::config;
-use s3::{Bucket, BucketConfiguration, Region, creds::Credentials, request::ResponseDataStream};
-
-type SurrealConnection = Surreal<Any>;
+pub use storage::{ObjectMeta, StoredObject};
+use storage::{ObjectStream, Storage, UploadedPart};
+use store::MetadataStore;
+pub use store::Stats;
+
+/// Memo ids drawn before giving up on finding one nobody uses.
+const MEMO_ID_ATTEMPTS: u32 = 5;
+
+/// Creates something identified by a memorable id, drawing another id while the previous one is
+/// already in use.
+async fn with_memo_id<T, F, Fut>(mut create: F) -> Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+
+        match create(memorable_wordlist::snake_case(40)).await {
+            Err(ModelManagerError::MemoIdConflict) if attempts < MEMO_ID_ATTEMPTS => continue,
+            res => return res,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ModelManager {
-    //Bucket is cloneable as its references are behind an arc
-    bucket: Box<Bucket>,
-    //Surrealdb is also cloneable
-    db: SurrealConnection,
+    //The storage is shared behind an arc
+    storage: Arc<dyn Storage>,
+    //The metadata store is also shared behind an arc
+    store: Arc<dyn MetadataStore>,
 }
 
 impl ModelManager {
     pub async fn new() -> Result<Self> {
-        let bucket = ModelManager::connect_minio().await?;
+        let storage = storage::connect().await?;
 
-        let db = ModelManager::connect_db().await?;
+        let store = store::connect().await?;
 
-        Ok(ModelManager { bucket, db })
+        Ok(ModelManager { storage, store })
     }
 
-    /// Function that tries to connect to the SurrealDB instance and panics if it doesn't achieve
-    /// it
-    async fn connect_db() -> Result<SurrealConnection> {
-        //SurrealDB
-        let db = any::connect(&config().DB_HOST_OR_PATH)
-            .await
-            .map_err(ModelManagerError::NewDB)?;
-
-        // Sign in when not on rocksdb
-        #[cfg(not(feature = "rocksdb"))]
-        if &config().DB_USER == "root" {
-            db.signin(Root {
-                username: &config().DB_USER,
-                password: &config().DB_PASSWORD,
-            })
-            .await
-            .map_err(ModelManagerError::SignIn)?;
-        } else {
-            db.signin(Namespace {
-                namespace: &config().DB_NS,
-                username: &config().DB_USER,
-                password: &config().DB_PASSWORD,
-            })
-            .await
-            .map_err(ModelManagerError::SignIn)?;
-        }
-
-        //Set DB from config
-        db.use_ns(&config().DB_NS)
-            .use_db(&config().DB_DBNAME)
-            .await
-            .map_err(|err| ModelManagerError::SetUseNSandDb {
-                ns: config().DB_NS.to_string(),
-                db: config().DB_NS.to_string(),
-                source: err,
-            })?;
-
-        // Create the assets table
-        db.query("DEFINE TABLE IF NOT EXISTS asset")
-            .await
-            .map_err(ModelManagerError::CouldNotDefineTable)?;
-
-        // Set the search index in memo_id asset column
-        db.query(
-            "DEFINE INDEX IF NOT EXISTS fileMemoIdUnique ON TABLE asset COLUMNS memo_id UNIQUE",
-        )
-        .await
-        .map_err(ModelManagerError::CouldNotSetTableIndex)?;
-
-        Ok(db)
-    }
-
-    /// Function that tries to connect to the bucket and creates it
-    async fn connect_minio() -> Result<Box<Bucket>> {
-        let bucket_name = &config().S3_BUCKET_NAME;
-
-        let region = Region::Custom {
-            region: config().S3_REGION.to_string(),
-            endpoint: config().S3_ENDPOINT.to_string(),
-        };
-
-        //Init credentials, unwrap if cannot create default
-        let creds = Credentials::new(
-            Some(&config().S3_ACCESS_KEY),
-            Some(&config().S3_SECRET_KEY),
-            None,
-            None,
-            None,
-        )?;
-
-        let mut bucket = Bucket::new(bucket_name, region.clone(), creds.clone())?.with_path_style();
-
-        if !bucket.exists().await? {
-            bucket = Bucket::create_with_path_style(
-                bucket_name,
-                region,
-                creds,
-                BucketConfiguration::default(),
-            )
-            .await?
-            .bucket;
+    /// Creates a manager keeping the files in `root` and the metadata in memory.
+    #[cfg(test)]
+    pub async fn for_tests(root: &std::path::Path) -> Self {
+        ModelManager {
+            storage: Arc::new(storage::FsStorage::new(root).await.unwrap()),
+            store: Arc::new(store::SqliteStore::new(":memory:").await.unwrap()),
         }
-
-        Ok(bucket)
     }
 
-    pub async fn upload<S, E>(&self, file_name: &str, stream: S) -> Result<()>
+    pub async fn upload<S, E>(&self, file_name: &str, stream: S) -> Result<u64>
     where
-        S: Stream<Item = std::result::Result<Bytes, E>>,
+        S: Stream<Item = std::result::Result<Bytes, E>> + Send,
         E: Into<BoxError>,
     {
-        async {
-            //Convert the stream into an 'AsyncRead'
-            let body_with_io_error =
-                stream.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
-            let body_reader = StreamReader::new(body_with_io_error);
-            futures::pin_mut!(body_reader);
-
-            self.bucket
-                .put_object_stream(&mut body_reader, file_name)
-                .await
-        }
-        .await?;
+        //Convert the stream into an 'AsyncRead'
+        let body_with_io_error = stream.map_err(io::Error::other);
+        let body_reader = StreamReader::new(body_with_io_error);
+        futures::pin_mut!(body_reader);
 
-        Ok(())
+        self.storage.put(file_name, &mut body_reader).await
+    }
+
+    pub async fn file_meta(&self, file_name: &str) -> Result<ObjectMeta> {
+        self.storage.head(file_name).await
+    }
+
+    pub async fn download(
+        &self,
+        file_name: &str,
+        range: Option<RangeInclusive<u64>>,
+    ) -> Result<ObjectStream> {
+        self.storage.get(file_name, range).await
+    }
+
+    pub async fn create_multipart(&self, file_name: &str) -> Result<String> {
+        self.storage.create_multipart(file_name).await
     }
 
-    pub async fn download(&self, file_name: &str) -> Result<(ResponseDataStream, usize)> {
-        // We get the head of the object to be able to access the size of it
-        let (head, _) = self.bucket.head_object(file_name).await?;
+    pub async fn upload_part(
+        &self,
+        file_name: &str,
+        upload_id: &str,
+        number: u32,
+        data: Bytes,
+    ) -> Result<UploadedPart> {
+        self.storage
+            .upload_part(file_name, upload_id, number, data)
+            .await
+    }
+
+    pub async fn complete_multipart(
+        &self,
+        file_name: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()> {
+        self.storage
+            .complete_multipart(file_name, upload_id, parts)
+            .await
+    }
+
+    /// Lists every object of the storage.
+    pub async fn list_files(&self) -> Result<Vec<StoredObject>> {
+        self.storage.list().await
+    }
+
+    pub async fn abort_multipart(&self, file_name: &str, upload_id: &str) -> Result<()> {
+        self.storage.abort_multipart(file_name, upload_id).await
+    }
+
+    /// Checks that the storage answers, for S3 with a HEAD of the bucket.
+    pub async fn check_storage(&self) -> Result<()> {
+        self.storage.ping().await
+    }
 
-        // We get the object stream
-        let r = self.bucket.get_object_stream(file_name).await?;
-        // We return the stream and the content length
-        Ok((r, head.content_length.unwrap_or_default() as usize))
+    /// Checks that the metadata store answers.
+    pub async fn check_store(&self) -> Result<()> {
+        self.store.ping().await
     }
 
-    pub fn db(&self) -> &SurrealConnection {
-        &self.db
+    pub fn store(&self) -> &dyn MetadataStore {
+        self.store.as_ref()
     }
 
     pub async fn delete_files(&self, file_names: Vec<String>) -> Result<()> {
         for name in file_names.iter() {
-            self.bucket.delete_object(name).await?;
+            self.storage.delete(name).await?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn with_memo_id_retries_on_conflict() {
+        let drawn = RefCell::new(Vec::new());
+
+        let res = with_memo_id(|memo_id| {
+            drawn.borrow_mut().push(memo_id.clone());
+            let attempt = drawn.borrow().len();
+            async move {
+                match attempt {
+                    1 | 2 => Err(ModelManagerError::MemoIdConflict),
+                    _ => Ok(memo_id),
+                }
+            }
+        })
+        .await;
+
+        // The id of the third attempt is kept, every attempt drew a new one
+        let drawn = drawn.into_inner();
+        assert_eq!(drawn.len(), 3);
+        assert_eq!(res.unwrap(), drawn[2]);
+        assert!(drawn.iter().all(|memo_id| !memo_id.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn with_memo_id_gives_up() {
+        let mut attempts = 0;
+
+        let res: Result<()> = with_memo_id(|_| {
+            attempts += 1;
+            async { Err(ModelManagerError::MemoIdConflict) }
+        })
+        .await;
+
+        assert!(matches!(res, Err(ModelManagerError::MemoIdConflict)));
+        assert_eq!(attempts, MEMO_ID_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn with_memo_id_only_retries_conflicts() {
+        let mut attempts = 0;
+
+        let res: Result<()> = with_memo_id(|_| {
+            attempts += 1;
+            async { Err(ModelManagerError::AssetNotFound) }
+        })
+        .await;
+
+        assert!(matches!(res, Err(ModelManagerError::AssetNotFound)));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,499 @@
+use std::{
+    io::{self, SeekFrom},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use rand::distr::{Alphanumeric, SampleString};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+
+use super::{ObjectMeta, ObjectStream, Storage, StoredObject, UploadedPart};
+use crate::model::error::{ModelManagerError, Result};
+
+/// Directory, inside the root, where the files are written before being moved into place.
+const TMP_DIR: &str = ".tmp";
+
+/// Age after which a temporary file is left over from a crash, a file being written is modified
+/// far more often.
+const STALE_TMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A file being written in the temporary directory, removed when dropped unless it was moved into
+/// place. A failed or cancelled write leaves nothing behind.
+struct TmpFile {
+    path: PathBuf,
+    moved: bool,
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if !self.moved {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Storage that keeps the files in a local directory.
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    root: PathBuf,
+}
+
+impl FsStorage {
+    /// Creates the storage, making sure the root and temporary directories exist, and removes the
+    /// stale temporary files.
+    pub async fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let tmp_dir = root.join(TMP_DIR);
+        fs::create_dir_all(&tmp_dir).await?;
+
+        // The directories of the multipart uploads are removed along with their upload
+        let stale = SystemTime::now() - STALE_TMP_AGE;
+        let mut entries = fs::read_dir(&tmp_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() && metadata.modified()? < stale {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        Ok(FsStorage { root })
+    }
+
+    /// Returns the final path of an object, refusing keys that could escape the root.
+    fn object_path(&self, key: &str) -> Option<PathBuf> {
+        if key.is_empty() || key.starts_with('.') || key.contains(['/', '\\']) {
+            return None;
+        }
+
+        Some(self.root.join(key))
+    }
+
+    /// Returns the directory holding the parts of a multipart upload.
+    fn multipart_dir(&self, upload_id: &str) -> Option<PathBuf> {
+        if upload_id.is_empty() || !upload_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        Some(
+            self.root
+                .join(TMP_DIR)
+                .join(format!("multipart-{upload_id}")),
+        )
+    }
+
+    /// Returns a new temporary file for an object, so a half written object never appears under
+    /// its real name.
+    fn tmp_file(&self, key: &str) -> TmpFile {
+        let suffix = Alphanumeric.sample_string(&mut rand::rng(), 8);
+
+        TmpFile {
+            path: self.root.join(TMP_DIR).join(format!("{key}.{suffix}")),
+            moved: false,
+        }
+    }
+
+    /// Moves a fully written temporary file into place.
+    async fn move_into_place(mut tmp: TmpFile, path: &Path) -> Result<()> {
+        // Renaming within the same filesystem is atomic
+        fs::rename(&tmp.path, path).await?;
+        tmp.moved = true;
+
+        Ok(())
+    }
+
+    /// Copies the parts of a multipart upload one after the other to the given path, and flushes
+    /// it to disk.
+    async fn write_parts(path: &Path, dir: &Path, parts: &[UploadedPart]) -> Result<()> {
+        let mut file = File::create(path).await?;
+        for part in parts {
+            let mut part = File::open(dir.join(part.number.to_string()))
+                .await
+                .map_err(not_found)?;
+            tokio::io::copy(&mut part, &mut file).await?;
+        }
+        file.flush().await?;
+        file.sync_all().await?;
+
+        Ok(())
+    }
+
+    /// Writes the reader to the given path and flushes it to disk.
+    async fn write_file(path: &Path, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64> {
+        let mut file = File::create(path).await?;
+        let written = tokio::io::copy(reader, &mut file).await?;
+        file.flush().await?;
+        file.sync_all().await?;
+
+        Ok(written)
+    }
+}
+
+/// Maps a missing file to a missing object.
+fn not_found(err: io::Error) -> ModelManagerError {
+    match err.kind() {
+        io::ErrorKind::NotFound => ModelManagerError::ObjectNotFound,
+        _ => err.into(),
+    }
+}
+
+#[async_trait]
+impl Storage for FsStorage {
+    async fn put(&self, key: &str, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64> {
+        let path = self
+            .object_path(key)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(key.to_string()))?;
+
+        let tmp = self.tmp_file(key);
+        let written = FsStorage::write_file(&tmp.path, reader).await?;
+        FsStorage::move_into_place(tmp, &path).await?;
+
+        Ok(written)
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectMeta> {
+        let path = self
+            .object_path(key)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(key.to_string()))?;
+
+        let metadata = fs::metadata(&path).await.map_err(not_found)?;
+        let modified = metadata.modified()?;
+
+        // Objects are only ever replaced as a whole, the size and modification time identify them
+        let nanos = modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+
+        Ok(ObjectMeta {
+            size: metadata.len(),
+            etag: format!("\"{:x}-{nanos:x}\"", metadata.len()),
+            last_modified: Some(DateTime::<Utc>::from(modified)),
+        })
+    }
+
+    async fn get(&self, key: &str, range: Option<RangeInclusive<u64>>) -> Result<ObjectStream> {
+        let path = self
+            .object_path(key)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(key.to_string()))?;
+
+        let mut file = File::open(&path).await.map_err(not_found)?;
+
+        match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(*range.start())).await?;
+                let len = range.end() - range.start() + 1;
+
+                Ok(Box::pin(ReaderStream::new(file.take(len))))
+            }
+            None => Ok(Box::pin(ReaderStream::new(file))),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self
+            .object_path(key)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(key.to_string()))?;
+
+        match fs::remove_file(path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+
+        let mut entries = fs::read_dir(&self.root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // The temporary directory and whatever is not a valid key are not objects
+            let Some(key) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if self.object_path(&key).is_none() {
+                continue;
+            }
+
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            objects.push(StoredObject {
+                key,
+                size: metadata.len(),
+                last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+
+        Ok(objects)
+    }
+
+    async fn create_multipart(&self, key: &str) -> Result<String> {
+        self.object_path(key)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(key.to_string()))?;
+
+        // The parts are kept in their own directory until the upload completes
+        let upload_id = Alphanumeric.sample_string(&mut rand::rng(), 16);
+        let dir = self
+            .multipart_dir(&upload_id)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(upload_id.clone()))?;
+        fs::create_dir_all(dir).await?;
+
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        number: u32,
+        data: Bytes,
+    ) -> Result<UploadedPart> {
+        let dir = self
+            .multipart_dir(upload_id)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(upload_id.to_string()))?;
+        if !fs::try_exists(&dir).await? {
+            return Err(ModelManagerError::ObjectNotFound);
+        }
+
+        FsStorage::write_file(&dir.join(number.to_string()), &mut data.as_ref()).await?;
+
+        Ok(UploadedPart {
+            number,
+            etag: number.to_string(),
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()> {
+        let path = self
+            .object_path(key)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(key.to_string()))?;
+        let dir = self
+            .multipart_dir(upload_id)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(upload_id.to_string()))?;
+
+        let tmp = self.tmp_file(key);
+        FsStorage::write_parts(&tmp.path, &dir, &parts).await?;
+        FsStorage::move_into_place(tmp, &path).await?;
+
+        fs::remove_dir_all(dir).await?;
+
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<()> {
+        let dir = self
+            .multipart_dir(upload_id)
+            .ok_or_else(|| ModelManagerError::InvalidObjectKey(upload_id.to_string()))?;
+
+        match fs::remove_dir_all(dir).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn ping(&self) -> Result<()> {
+        fs::metadata(self.root.join(TMP_DIR)).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::io::StreamReader;
+
+    use super::*;
+
+    async fn storage() -> (tempfile::TempDir, FsStorage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(dir.path()).await.unwrap();
+
+        (dir, storage)
+    }
+
+    async fn read(storage: &FsStorage, key: &str, range: Option<RangeInclusive<u64>>) -> Vec<u8> {
+        let chunks: Vec<Bytes> = storage
+            .get(key, range)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        chunks.concat()
+    }
+
+    /// Names of the files left in the temporary directory.
+    fn tmp_entries(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir.join(TMP_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn keys_cannot_escape_the_root() {
+        let (_dir, storage) = storage().await;
+
+        for key in ["", ".", "..", ".tmp", "../a", "a/b", "a\\b", "/etc/passwd"] {
+            assert!(storage.object_path(key).is_none(), "{key}");
+            assert!(matches!(
+                storage.put(key, &mut &b"data"[..]).await,
+                Err(ModelManagerError::InvalidObjectKey(_))
+            ));
+        }
+        assert!(storage.multipart_dir("../a").is_none());
+        assert!(storage.multipart_dir("").is_none());
+    }
+
+    #[tokio::test]
+    async fn put_moves_the_file_into_place() {
+        let (dir, storage) = storage().await;
+
+        assert_eq!(storage.put("key", &mut &b"hello"[..]).await.unwrap(), 5);
+
+        assert_eq!(std::fs::read(dir.path().join("key")).unwrap(), b"hello");
+        assert!(tmp_entries(dir.path()).is_empty());
+        let keys: Vec<String> = storage
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+        assert_eq!(keys, vec!["key".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn failed_put_leaves_nothing() {
+        let (dir, storage) = storage().await;
+
+        let chunks = [
+            Ok(Bytes::from_static(b"hello")),
+            Err(io::Error::other("the client is gone")),
+        ];
+        let mut reader = StreamReader::new(futures::stream::iter(chunks));
+        assert!(storage.put("key", &mut reader).await.is_err());
+
+        assert!(!dir.path().join("key").exists());
+        assert!(tmp_entries(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_put_leaves_nothing() {
+        let (dir, storage) = storage().await;
+
+        // The writer is kept open, the put waits for more data until it is cancelled
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        writer.write_all(b"hello").await.unwrap();
+        let put = storage.put("key", &mut reader);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), put)
+                .await
+                .is_err()
+        );
+
+        assert!(!dir.path().join("key").exists());
+        assert!(tmp_entries(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn stale_tmp_files_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp_dir = dir.path().join(TMP_DIR);
+        std::fs::create_dir_all(tmp_dir.join("multipart-upload")).unwrap();
+        let stale = std::fs::File::create(tmp_dir.join("stale.aaaaaaaa")).unwrap();
+        stale
+            .set_modified(SystemTime::now() - 2 * STALE_TMP_AGE)
+            .unwrap();
+        std::fs::File::create(tmp_dir.join("writing.bbbbbbbb")).unwrap();
+
+        FsStorage::new(dir.path()).await.unwrap();
+
+        let mut entries = tmp_entries(dir.path());
+        entries.sort();
+        assert_eq!(entries, vec!["multipart-upload", "writing.bbbbbbbb"]);
+    }
+
+    #[tokio::test]
+    async fn ranged_reads() {
+        let (_dir, storage) = storage().await;
+        storage.put("key", &mut &b"0123456789"[..]).await.unwrap();
+
+        assert_eq!(read(&storage, "key", None).await, b"0123456789");
+        assert_eq!(read(&storage, "key", Some(0..=0)).await, b"0");
+        assert_eq!(read(&storage, "key", Some(2..=5)).await, b"2345");
+        assert_eq!(read(&storage, "key", Some(7..=9)).await, b"789");
+        assert!(matches!(
+            storage.get("missing", None).await,
+            Err(ModelManagerError::ObjectNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn head_gives_the_size() {
+        let (_dir, storage) = storage().await;
+        storage.put("key", &mut &b"0123456789"[..]).await.unwrap();
+
+        let meta = storage.head("key").await.unwrap();
+        assert_eq!(meta.size, 10);
+        assert!(meta.etag.starts_with("\"a-"));
+        assert!(meta.last_modified.is_some());
+
+        // Replacing the file changes its etag
+        storage.put("key", &mut &b"012345678"[..]).await.unwrap();
+        let replaced = storage.head("key").await.unwrap();
+        assert_eq!(replaced.size, 9);
+        assert_ne!(replaced.etag, meta.etag);
+
+        assert!(matches!(
+            storage.head("missing").await,
+            Err(ModelManagerError::ObjectNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn multipart_upload() {
+        let (dir, storage) = storage().await;
+
+        let upload_id = storage.create_multipart("key").await.unwrap();
+        let mut parts = Vec::new();
+        for (number, data) in [(1, "hello "), (2, "world")] {
+            let part = storage
+                .upload_part(
+                    "key",
+                    &upload_id,
+                    number,
+                    Bytes::from_static(data.as_bytes()),
+                )
+                .await
+                .unwrap();
+            parts.push(part);
+        }
+        storage
+            .complete_multipart("key", &upload_id, parts)
+            .await
+            .unwrap();
+
+        assert_eq!(read(&storage, "key", None).await, b"hello world");
+        assert!(tmp_entries(dir.path()).is_empty());
+    }
+}
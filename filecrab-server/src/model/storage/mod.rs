@@ -0,0 +1,110 @@
+mod fs;
+mod s3;
+
+use std::{fmt::Debug, io, ops::RangeInclusive, sync::Arc};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
+
+use super::error::Result;
+use crate::config::{StorageConfig, config};
+
+pub use self::{fs::FsStorage, s3::S3Storage};
+
+/// Stream of bytes returned when reading an object from the storage.
+pub type ObjectStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Metadata of a stored object, used to answer conditional and ranged requests.
+#[derive(Debug, Clone)]
+pub struct ObjectMeta {
+    /// Size of the object in bytes.
+    pub size: u64,
+    /// Quoted entity tag, changes whenever the content does.
+    pub etag: String,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// An object found when listing the storage.
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Part of a multipart upload already sent to the storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedPart {
+    /// Position of the part, starting at 1.
+    pub number: u32,
+    pub etag: String,
+}
+
+/// Abstraction over the place where the files are stored.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    /// Streams the content of the reader into the object identified by `key` and returns the
+    /// number of bytes written.
+    async fn put(&self, key: &str, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64>;
+
+    /// Returns the metadata of the object identified by `key`.
+    async fn head(&self, key: &str) -> Result<ObjectMeta>;
+
+    /// Returns a stream over the content of the object, only over the given inclusive byte range
+    /// if any. The range must be within the object.
+    async fn get(&self, key: &str, range: Option<RangeInclusive<u64>>) -> Result<ObjectStream>;
+
+    /// Deletes the object identified by `key`, deleting a missing object is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Lists every object, the unfinished uploads are not objects yet.
+    async fn list(&self) -> Result<Vec<StoredObject>>;
+
+    // Multipart uploads
+    /// Starts a multipart upload of the object identified by `key` and returns its id.
+    async fn create_multipart(&self, key: &str) -> Result<String>;
+
+    /// Uploads a part of a multipart upload, every part but the last must be at least 5 MiB as
+    /// required by S3. Uploading the same number again replaces the part.
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        number: u32,
+        data: Bytes,
+    ) -> Result<UploadedPart>;
+
+    /// Assembles the parts, in order, into the object.
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()>;
+
+    /// Drops a multipart upload and its parts, dropping a missing upload is not an error.
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()>;
+
+    /// Checks that the storage is reachable and usable.
+    async fn ping(&self) -> Result<()>;
+}
+
+/// Builds the storage backend selected in the config.
+pub async fn connect() -> Result<Arc<dyn Storage>> {
+    let storage: Arc<dyn Storage> = match &config().STORAGE {
+        StorageConfig::S3 {
+            bucket_name,
+            region,
+            endpoint,
+            access_key,
+            secret_key,
+        } => Arc::new(S3Storage::new(bucket_name, region, endpoint, access_key, secret_key).await?),
+        StorageConfig::Fs { path } => Arc::new(FsStorage::new(path).await?),
+    };
+
+    Ok(storage)
+}
@@ -0,0 +1,193 @@
+use std::{io, ops::RangeInclusive};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::DateTime;
+use futures::TryStreamExt;
+use s3::{
+    Bucket, BucketConfiguration, Region,
+    command::Command,
+    creds::Credentials,
+    error::S3Error,
+    request::{Request, tokio_backend::HyperRequest},
+    serde_types::Part,
+};
+use tokio::io::AsyncRead;
+
+use super::{ObjectMeta, ObjectStream, Storage, StoredObject, UploadedPart};
+use crate::model::error::Result;
+
+/// Content type of the objects, they are only ever served as raw bytes.
+const CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Storage backed by an S3 compatible bucket (ex. MinIO).
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    //Bucket is cloneable as its references are behind an arc
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    /// Function that tries to connect to the bucket and creates it
+    pub async fn new(
+        bucket_name: &str,
+        region: &str,
+        endpoint: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self> {
+        let region = Region::Custom {
+            region: region.to_string(),
+            endpoint: endpoint.to_string(),
+        };
+
+        //Init credentials, unwrap if cannot create default
+        let creds = Credentials::new(Some(access_key), Some(secret_key), None, None, None)?;
+
+        let mut bucket = Bucket::new(bucket_name, region.clone(), creds.clone())?.with_path_style();
+
+        if !bucket.exists().await? {
+            bucket = Bucket::create_with_path_style(
+                bucket_name,
+                region,
+                creds,
+                BucketConfiguration::default(),
+            )
+            .await?
+            .bucket;
+        }
+
+        Ok(S3Storage { bucket })
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<u64> {
+        let res = self.bucket.put_object_stream(reader, key).await?;
+
+        Ok(res.uploaded_bytes() as u64)
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectMeta> {
+        let (head, _) = self.bucket.head_object(key).await?;
+
+        Ok(ObjectMeta {
+            size: head.content_length.unwrap_or_default() as u64,
+            etag: head.e_tag.unwrap_or_default(),
+            // S3 sends HTTP dates
+            last_modified: head
+                .last_modified
+                .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                .map(|date| date.to_utc()),
+        })
+    }
+
+    async fn get(&self, key: &str, range: Option<RangeInclusive<u64>>) -> Result<ObjectStream> {
+        let stream = match range {
+            // The bucket only has a buffered ranged GET, the request is built directly to stream
+            // the response
+            Some(range) => {
+                let command = Command::GetObjectRange {
+                    start: *range.start(),
+                    end: Some(*range.end()),
+                };
+                HyperRequest::new(&self.bucket, key, command)
+                    .await?
+                    .response_data_to_stream()
+                    .await?
+            }
+            None => self.bucket.get_object_stream(key).await?,
+        };
+
+        Ok(Box::pin(stream.bytes.map_err(io::Error::other)))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.bucket.delete_object(key).await?;
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<StoredObject>> {
+        let pages = self.bucket.list(String::new(), None).await?;
+
+        let objects = pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .map(|object| StoredObject {
+                // Listings send RFC3339 dates
+                last_modified: DateTime::parse_from_rfc3339(&object.last_modified)
+                    .ok()
+                    .map(|date| date.to_utc()),
+                key: object.key,
+                size: object.size,
+            })
+            .collect();
+
+        Ok(objects)
+    }
+
+    async fn create_multipart(&self, key: &str) -> Result<String> {
+        let res = self
+            .bucket
+            .initiate_multipart_upload(key, CONTENT_TYPE)
+            .await?;
+
+        Ok(res.upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        number: u32,
+        data: Bytes,
+    ) -> Result<UploadedPart> {
+        let part = self
+            .bucket
+            .put_multipart_chunk(data.to_vec(), key, number, upload_id, CONTENT_TYPE)
+            .await?;
+
+        Ok(UploadedPart {
+            number: part.part_number,
+            etag: part.etag,
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<()> {
+        let parts = parts
+            .into_iter()
+            .map(|part| Part {
+                part_number: part.number,
+                etag: part.etag,
+            })
+            .collect();
+        self.bucket
+            .complete_multipart_upload(key, upload_id, parts)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()> {
+        match self.bucket.abort_upload(key, upload_id).await {
+            Ok(()) => Ok(()),
+            // Already aborted, or completed
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn ping(&self) -> Result<()> {
+        // A HEAD of the bucket itself, it fails if the bucket is gone or the credentials refused
+        self.bucket.head_object("/").await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+mod sqlite;
+mod surreal;
+
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{
+    api_key::{ApiKey, Usage},
+    asset::{Asset, AssetEntry, AssetToCreate},
+    error::Result,
+    job::{JobRun, ObjectFailure},
+    text::{Text, TextToCreate},
+    upload::Upload,
+};
+use crate::config::{DbConfig, config};
+
+pub use self::{sqlite::SqliteStore, surreal::SurrealStore};
+
+/// Counts of what the database holds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub assets: u64,
+    pub texts: u64,
+    /// Total size of the files, those stored before sizes were recorded count as 0.
+    pub asset_bytes: u64,
+}
+
+/// Abstraction over the database holding the metadata of the assets and texts, and the api keys.
+#[async_trait]
+pub trait MetadataStore: Debug + Send + Sync {
+    // Assets
+    /// Stores a new asset under the given id, the id is the key of the file in the storage.
+    async fn create_asset(&self, id: &str, data: AssetToCreate) -> Result<Asset>;
+    /// Saves the final metadata of a pending asset and makes it available, fails with
+    /// `AssetNotFound` if it is not pending anymore.
+    async fn commit_asset(&self, id: &str, data: AssetToCreate) -> Result<()>;
+    /// Reads an asset which is committed and has not expired yet.
+    async fn read_asset_by_memo_id(&self, memo_id: &str) -> Result<Asset>;
+    /// Atomically counts a download and returns the remaining ones, `None` when unlimited.
+    /// Fails with `AssetNotFound` when the asset has no downloads left.
+    async fn register_download(&self, id: &str) -> Result<Option<u32>>;
+    async fn delete_asset(&self, id: &str) -> Result<()>;
+    /// Returns every committed asset, expired or not.
+    async fn list_assets(&self) -> Result<Vec<AssetEntry>>;
+    /// Returns the ids of the assets reserved but not committed yet.
+    async fn list_pending_assets(&self) -> Result<Vec<String>>;
+    /// Returns the ids of the assets expired at `now`, they are deleted once their file is gone.
+    async fn expired_assets(&self, now: DateTime<Utc>) -> Result<Vec<String>>;
+
+    // Texts
+    async fn create_text(&self, data: TextToCreate) -> Result<Text>;
+    async fn read_text_by_memo_id(&self, memo_id: &str) -> Result<Text>;
+    async fn delete_text(&self, id: &str) -> Result<()>;
+    /// Deletes every text expired at `now` and returns how many were deleted.
+    async fn clean_texts(&self, now: DateTime<Utc>) -> Result<u64>;
+
+    // Uploads
+    async fn create_upload(&self, upload: &Upload) -> Result<()>;
+    async fn read_upload(&self, id: &str) -> Result<Upload>;
+    /// Saves the progress of the upload (length, offset, parts and memo id). Fails with
+    /// `UploadConflict` if the stored offset is not `previous_offset` anymore.
+    async fn update_upload(&self, upload: &Upload, previous_offset: u64) -> Result<()>;
+    async fn delete_upload(&self, id: &str) -> Result<()>;
+    /// Returns the object keys of the uploads whose file has been assembled.
+    async fn stored_upload_keys(&self) -> Result<Vec<String>>;
+    /// Returns the uploads expired at `now`, they are deleted once their parts are gone.
+    async fn expired_uploads(&self, now: DateTime<Utc>) -> Result<Vec<Upload>>;
+
+    // Signed links
+    async fn create_link_nonce(&self, nonce: &str, expire: DateTime<Utc>) -> Result<()>;
+    /// Removes a nonce and returns whether it existed, a nonce can only be consumed once.
+    async fn consume_link_nonce(&self, nonce: &str) -> Result<bool>;
+    /// Deletes every nonce expired at `now`.
+    async fn clean_link_nonces(&self, now: DateTime<Utc>) -> Result<()>;
+
+    // Api keys
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<()>;
+    async fn read_api_key(&self, id: &str) -> Result<ApiKey>;
+    async fn read_api_key_by_hash(&self, key_hash: &str) -> Result<ApiKey>;
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>>;
+    /// Marks a key as revoked, fails with `ApiKeyNotFound` if it does not exist.
+    async fn revoke_api_key(&self, id: &str) -> Result<()>;
+    /// Sums the size and number of the assets created by a key, `None` for the assets created
+    /// before the keys existed.
+    async fn usage(&self, created_by: Option<&str>) -> Result<Usage>;
+
+    // Jobs
+    async fn create_job_run(&self, run: &JobRun) -> Result<()>;
+    /// Lists the last runs of a job, the most recent first.
+    async fn list_job_runs(&self, job: &str, limit: u32) -> Result<Vec<JobRun>>;
+    /// Deletes every run started before `before`.
+    async fn clean_job_runs(&self, before: DateTime<Utc>) -> Result<()>;
+    /// Records a failed attempt on an object, adding to the attempts already recorded.
+    async fn record_object_failure(
+        &self,
+        kind: &str,
+        key: &str,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+    async fn clear_object_failure(&self, kind: &str, key: &str) -> Result<()>;
+    /// Lists the objects still failing, the most recent failures first.
+    async fn list_object_failures(&self) -> Result<Vec<ObjectFailure>>;
+    /// Deletes every failure which did not happen again since `before`.
+    async fn clean_object_failures(&self, before: DateTime<Utc>) -> Result<()>;
+
+    // Stats
+    async fn stats(&self) -> Result<Stats>;
+
+    /// Checks that the database answers.
+    async fn ping(&self) -> Result<()>;
+}
+
+/// Builds the metadata store selected in the config.
+pub async fn connect() -> Result<Arc<dyn MetadataStore>> {
+    let store: Arc<dyn MetadataStore> = match &config().DB {
+        DbConfig::Surreal(surreal) => Arc::new(SurrealStore::new(surreal).await?),
+        DbConfig::Sqlite { path } => Arc::new(SqliteStore::new(path).await?),
+    };
+
+    Ok(store)
+}
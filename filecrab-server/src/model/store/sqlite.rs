@@ -0,0 +1,1152 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::distr::{Alphanumeric, SampleString};
+use rusqlite::{Connection, OptionalExtension, ffi, params};
+use tokio::task;
+
+use super::MetadataStore;
+use crate::model::{
+    api_key::{ApiKey, Scope, Usage},
+    asset::{Asset, AssetEntry, AssetToCreate},
+    error::{ModelManagerError, Result},
+    job::{JobRun, ObjectFailure, RunStatus},
+    storage::UploadedPart,
+    store::Stats,
+    text::{Text, TextToCreate},
+    upload::Upload,
+};
+
+/// Schema migrations, applied in order and tracked with the `user_version` pragma.
+const MIGRATIONS: &[&str] = &[
+    // v1: assets and texts
+    "CREATE TABLE asset (
+        id TEXT PRIMARY KEY NOT NULL,
+        file_name TEXT NOT NULL,
+        encrypted INTEGER NOT NULL,
+        expire INTEGER,
+        memo_id TEXT UNIQUE
+    );
+    CREATE INDEX asset_expire ON asset (expire);
+    CREATE TABLE text (
+        id TEXT PRIMARY KEY NOT NULL,
+        content TEXT NOT NULL,
+        memo_id TEXT NOT NULL,
+        expire INTEGER NOT NULL
+    );
+    CREATE INDEX text_memo_id ON text (memo_id);
+    CREATE INDEX text_expire ON text (expire);",
+    // v2: download limits
+    "ALTER TABLE asset ADD COLUMN max_downloads INTEGER;
+    ALTER TABLE asset ADD COLUMN downloads INTEGER NOT NULL DEFAULT 0;",
+    // v3: deletion tokens
+    "ALTER TABLE asset ADD COLUMN delete_token TEXT;
+    ALTER TABLE text ADD COLUMN delete_token TEXT;",
+    // v4: resumable uploads, parts and asset are stored as JSON
+    "CREATE TABLE upload (
+        id TEXT PRIMARY KEY NOT NULL,
+        object_key TEXT NOT NULL,
+        multipart_id TEXT NOT NULL,
+        upload_length INTEGER,
+        upload_offset INTEGER NOT NULL,
+        parts TEXT NOT NULL,
+        asset TEXT NOT NULL,
+        memo_id TEXT,
+        expire INTEGER NOT NULL
+    );
+    CREATE INDEX upload_expire ON upload (expire);",
+    // v5: api keys, scopes are stored comma separated
+    "CREATE TABLE api_key (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        key_hash TEXT NOT NULL UNIQUE,
+        scopes TEXT NOT NULL,
+        expire INTEGER,
+        revoked INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL
+    );
+    ALTER TABLE asset ADD COLUMN created_by TEXT;",
+    // v6: quotas, sizes are summed per key
+    "ALTER TABLE asset ADD COLUMN size INTEGER;
+    CREATE INDEX asset_created_by ON asset (created_by);
+    ALTER TABLE api_key ADD COLUMN quota_size INTEGER;
+    ALTER TABLE api_key ADD COLUMN quota_files INTEGER;",
+    // v7: signed links
+    "ALTER TABLE asset ADD COLUMN signed_only INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE link_nonce (
+        nonce TEXT PRIMARY KEY NOT NULL,
+        expire INTEGER NOT NULL
+    );
+    CREATE INDEX link_nonce_expire ON link_nonce (expire);",
+    // v8: job runs and the objects the jobs failed to handle, counts are stored as JSON
+    "CREATE TABLE job_run (
+        id TEXT PRIMARY KEY NOT NULL,
+        job TEXT NOT NULL,
+        manual INTEGER NOT NULL,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        counts TEXT NOT NULL,
+        failures INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX job_run_job ON job_run (job, started_at);
+    CREATE TABLE object_failure (
+        kind TEXT NOT NULL,
+        key TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        error TEXT NOT NULL,
+        first_failed_at INTEGER NOT NULL,
+        last_failed_at INTEGER NOT NULL,
+        PRIMARY KEY (kind, key)
+    );",
+    // v9: pending assets, and unique memo ids for the texts (duplicates get their id appended)
+    "ALTER TABLE asset ADD COLUMN pending INTEGER NOT NULL DEFAULT 0;
+    UPDATE text SET memo_id = memo_id || '_' || id
+    WHERE rowid NOT IN (SELECT MIN(rowid) FROM text GROUP BY memo_id);
+    DROP INDEX text_memo_id;
+    CREATE UNIQUE INDEX text_memo_id ON text (memo_id);",
+    // v10: creation time of the assets
+    "ALTER TABLE asset ADD COLUMN created_at INTEGER;",
+    // v11: uploads whose file is assembled but whose asset is not created yet
+    "ALTER TABLE upload ADD COLUMN stored INTEGER NOT NULL DEFAULT 0;",
+];
+
+/// Columns of the asset table, in the order read by `asset_from_row`.
+const ASSET_COLUMNS: &str = "id, file_name, memo_id, delete_token, signed_only, encrypted, size, \
+     created_at, expire, max_downloads, downloads, created_by";
+
+fn asset_from_row(row: &rusqlite::Row) -> rusqlite::Result<Asset> {
+    let created_at: Option<i64> = row.get(7)?;
+    let expire: Option<i64> = row.get(8)?;
+    let max_downloads: Option<u32> = row.get(9)?;
+    let downloads: u32 = row.get(10)?;
+
+    Ok(Asset {
+        id: row.get(0)?,
+        file_name: row.get(1)?,
+        memo_id: row.get(2)?,
+        delete_token: row.get(3)?,
+        signed_only: row.get(4)?,
+        encrypted: row.get(5)?,
+        size: row.get(6)?,
+        created_at: created_at.map(from_timestamp),
+        expire: expire.map(from_timestamp),
+        remaining_downloads: max_downloads.map(|max| max.saturating_sub(downloads)),
+        created_by: row.get(11)?,
+    })
+}
+
+/// Columns of the api key table, in the order read by `api_key_from_row`.
+const API_KEY_COLUMNS: &str =
+    "id, name, key_hash, scopes, expire, revoked, created_at, quota_size, quota_files";
+
+fn api_key_from_row(row: &rusqlite::Row) -> rusqlite::Result<ApiKey> {
+    let scopes: String = row.get(3)?;
+    let expire: Option<i64> = row.get(4)?;
+
+    Ok(ApiKey {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        key_hash: row.get(2)?,
+        scopes: scopes.split(',').filter_map(Scope::parse).collect(),
+        expire: expire.map(from_timestamp),
+        revoked: row.get(5)?,
+        created_at: from_timestamp(row.get(6)?),
+        quota_size: row.get(7)?,
+        quota_files: row.get(8)?,
+    })
+}
+
+/// Upload row with its JSON columns not parsed yet, parsing happens out of the blocking closure.
+struct UploadRow {
+    id: String,
+    object_key: String,
+    multipart_id: String,
+    length: Option<u64>,
+    offset: u64,
+    parts: String,
+    asset: String,
+    stored: bool,
+    memo_id: Option<String>,
+    expire: i64,
+}
+
+impl UploadRow {
+    const COLUMNS: &str = "id, object_key, multipart_id, upload_length, upload_offset, parts, \
+                           asset, memo_id, expire, stored";
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<UploadRow> {
+        Ok(UploadRow {
+            id: row.get(0)?,
+            object_key: row.get(1)?,
+            multipart_id: row.get(2)?,
+            length: row.get(3)?,
+            offset: row.get(4)?,
+            parts: row.get(5)?,
+            asset: row.get(6)?,
+            memo_id: row.get(7)?,
+            expire: row.get(8)?,
+            stored: row.get(9)?,
+        })
+    }
+
+    fn into_upload(self) -> serde_json::Result<Upload> {
+        Ok(Upload {
+            id: self.id,
+            object_key: self.object_key,
+            multipart_id: self.multipart_id,
+            length: self.length,
+            offset: self.offset,
+            parts: serde_json::from_str::<Vec<UploadedPart>>(&self.parts)?,
+            asset: serde_json::from_str(&self.asset)?,
+            stored: self.stored,
+            memo_id: self.memo_id,
+            expire: from_timestamp(self.expire),
+        })
+    }
+}
+
+/// Job run row with its counts not parsed yet.
+struct JobRunRow {
+    id: String,
+    job: String,
+    manual: bool,
+    started_at: i64,
+    finished_at: i64,
+    status: String,
+    attempts: u32,
+    counts: String,
+    failures: u64,
+    error: Option<String>,
+}
+
+impl JobRunRow {
+    const COLUMNS: &str =
+        "id, job, manual, started_at, finished_at, status, attempts, counts, failures, error";
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<JobRunRow> {
+        Ok(JobRunRow {
+            id: row.get(0)?,
+            job: row.get(1)?,
+            manual: row.get(2)?,
+            started_at: row.get(3)?,
+            finished_at: row.get(4)?,
+            status: row.get(5)?,
+            attempts: row.get(6)?,
+            counts: row.get(7)?,
+            failures: row.get(8)?,
+            error: row.get(9)?,
+        })
+    }
+
+    fn into_job_run(self) -> serde_json::Result<JobRun> {
+        Ok(JobRun {
+            id: self.id,
+            job: self.job,
+            manual: self.manual,
+            started_at: from_timestamp(self.started_at),
+            finished_at: from_timestamp(self.finished_at),
+            status: RunStatus::parse(&self.status).unwrap_or(RunStatus::Failed),
+            attempts: self.attempts,
+            counts: serde_json::from_str(&self.counts)?,
+            failures: self.failures,
+            error: self.error,
+        })
+    }
+}
+
+/// Metadata store backed by an embedded SQLite database.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    // rusqlite connections are not Sync, every query goes through the mutex on a blocking thread
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database file and runs the pending migrations.
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let conn = task::spawn_blocking(move || -> rusqlite::Result<Connection> {
+            let mut conn = Connection::open(path)?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            SqliteStore::migrate(&mut conn)?;
+            Ok(conn)
+        })
+        .await??;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Applies the migrations the database has not seen yet.
+    fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the given closure with the connection on a blocking thread.
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        task::spawn_blocking(move || {
+            // A panic while holding the lock does not leave SQLite in a bad state
+            let mut conn = conn.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&mut conn)
+        })
+        .await?
+        .map_err(map_error)
+    }
+}
+
+/// Columns whose unique constraint failing means a memo id was drawn twice.
+const MEMO_ID_COLUMNS: [&str; 2] = ["asset.memo_id", "text.memo_id"];
+
+/// Maps unique constraint violations on the memo ids to a memo id conflict, other violations (ex.
+/// the hash of an api key) are left as they are.
+fn map_error(err: rusqlite::Error) -> ModelManagerError {
+    match err {
+        rusqlite::Error::SqliteFailure(ref e, Some(ref message))
+            if e.extended_code == ffi::SQLITE_CONSTRAINT_UNIQUE
+                && MEMO_ID_COLUMNS
+                    .iter()
+                    .any(|column| message == &format!("UNIQUE constraint failed: {column}")) =>
+        {
+            ModelManagerError::MemoIdConflict
+        }
+        _ => err.into(),
+    }
+}
+
+fn from_timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+#[async_trait]
+impl MetadataStore for SqliteStore {
+    async fn create_asset(&self, id: &str, data: AssetToCreate) -> Result<Asset> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO asset
+                 (id, file_name, encrypted, expire, memo_id, max_downloads, delete_token,
+                 created_by, size, signed_only, pending, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    id,
+                    data.file_name,
+                    data.encrypted,
+                    data.expire.map(|exp| exp.timestamp()),
+                    data.memo_id,
+                    data.max_downloads,
+                    data.delete_token,
+                    data.created_by,
+                    data.size,
+                    data.signed_only,
+                    data.pending,
+                    data.created_at.map(|created_at| created_at.timestamp()),
+                ],
+            )?;
+
+            Ok(Asset {
+                id,
+                file_name: data.file_name,
+                memo_id: data.memo_id.unwrap_or_default(),
+                delete_token: data.delete_token,
+                signed_only: data.signed_only,
+                encrypted: data.encrypted,
+                size: data.size,
+                created_at: data.created_at,
+                expire: data.expire,
+                remaining_downloads: data.max_downloads,
+                created_by: data.created_by,
+            })
+        })
+        .await
+    }
+
+    async fn commit_asset(&self, id: &str, data: AssetToCreate) -> Result<()> {
+        let id = id.to_string();
+
+        let updated = self
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE asset SET file_name = ?2, encrypted = ?3, expire = ?4,
+                     max_downloads = ?5, delete_token = ?6, size = ?7, signed_only = ?8,
+                     created_at = ?9, pending = 0
+                     WHERE id = ?1 AND pending = 1",
+                    params![
+                        id,
+                        data.file_name,
+                        data.encrypted,
+                        data.expire.map(|exp| exp.timestamp()),
+                        data.max_downloads,
+                        data.delete_token,
+                        data.size,
+                        data.signed_only,
+                        data.created_at.map(|created_at| created_at.timestamp()),
+                    ],
+                )
+            })
+            .await?;
+
+        if updated == 0 {
+            return Err(ModelManagerError::AssetNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn read_asset_by_memo_id(&self, memo_id: &str) -> Result<Asset> {
+        let memo_id = memo_id.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                &format!(
+                    "SELECT {ASSET_COLUMNS} FROM asset WHERE memo_id = ?1 AND pending = 0
+                     AND (expire IS NULL OR expire > ?2) LIMIT 1"
+                ),
+                params![memo_id, Utc::now().timestamp()],
+                asset_from_row,
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(ModelManagerError::AssetNotFound)
+    }
+
+    async fn register_download(&self, id: &str) -> Result<Option<u32>> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                "UPDATE asset SET downloads = downloads + 1
+                 WHERE id = ?1 AND (max_downloads IS NULL OR downloads < max_downloads)
+                 RETURNING downloads, max_downloads",
+                params![id],
+                |row| {
+                    let downloads: u32 = row.get(0)?;
+                    let max_downloads: Option<u32> = row.get(1)?;
+                    Ok(max_downloads.map(|max| max.saturating_sub(downloads)))
+                },
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(ModelManagerError::AssetNotFound)
+    }
+
+    async fn delete_asset(&self, id: &str) -> Result<()> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.execute("DELETE FROM asset WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_assets(&self) -> Result<Vec<AssetEntry>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT id, created_at FROM asset WHERE pending = 0")?;
+            let assets = stmt
+                .query_map([], |row| {
+                    Ok(AssetEntry {
+                        id: row.get(0)?,
+                        created_at: row.get::<_, Option<i64>>(1)?.map(from_timestamp),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(assets)
+        })
+        .await
+    }
+
+    async fn list_pending_assets(&self) -> Result<Vec<String>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT id FROM asset WHERE pending = 1")?;
+            let ids = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn expired_assets(&self, now: DateTime<Utc>) -> Result<Vec<String>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT id FROM asset WHERE expire <= ?1")?;
+            let ids = stmt
+                .query_map(params![now.timestamp()], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn create_text(&self, data: TextToCreate) -> Result<Text> {
+        let id = Alphanumeric.sample_string(&mut rand::rng(), 20);
+
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO text (id, content, memo_id, expire, delete_token)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id,
+                    data.content,
+                    data.memo_id,
+                    data.expire.timestamp(),
+                    data.delete_token,
+                ],
+            )?;
+
+            Ok(Text {
+                id,
+                content: data.content,
+                memo_id: data.memo_id,
+                expire: data.expire,
+                delete_token: data.delete_token,
+            })
+        })
+        .await
+    }
+
+    async fn read_text_by_memo_id(&self, memo_id: &str) -> Result<Text> {
+        let memo_id = memo_id.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT id, content, memo_id, expire, delete_token
+                 FROM text WHERE memo_id = ?1 LIMIT 1",
+                params![memo_id],
+                |row| {
+                    Ok(Text {
+                        id: row.get(0)?,
+                        content: row.get(1)?,
+                        memo_id: row.get(2)?,
+                        expire: from_timestamp(row.get(3)?),
+                        delete_token: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(ModelManagerError::TextNotFound)
+    }
+
+    async fn delete_text(&self, id: &str) -> Result<()> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.execute("DELETE FROM text WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clean_texts(&self, now: DateTime<Utc>) -> Result<u64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM text WHERE expire <= ?1",
+                params![now.timestamp()],
+            )?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn create_upload(&self, upload: &Upload) -> Result<()> {
+        let upload = upload.clone();
+        let parts = serde_json::to_string(&upload.parts)?;
+        let asset = serde_json::to_string(&upload.asset)?;
+
+        self.call(move |conn| {
+            conn.execute(
+                &format!(
+                    "INSERT INTO upload ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    UploadRow::COLUMNS
+                ),
+                params![
+                    upload.id,
+                    upload.object_key,
+                    upload.multipart_id,
+                    upload.length,
+                    upload.offset,
+                    parts,
+                    asset,
+                    upload.memo_id,
+                    upload.expire.timestamp(),
+                    upload.stored,
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn read_upload(&self, id: &str) -> Result<Upload> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                &format!("SELECT {} FROM upload WHERE id = ?1", UploadRow::COLUMNS),
+                params![id],
+                UploadRow::from_row,
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(ModelManagerError::UploadNotFound)?
+        .into_upload()
+        .map_err(Into::into)
+    }
+
+    async fn update_upload(&self, upload: &Upload, previous_offset: u64) -> Result<()> {
+        let upload = upload.clone();
+        let parts = serde_json::to_string(&upload.parts)?;
+
+        let updated = self
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE upload SET upload_length = ?1, upload_offset = ?2, parts = ?3,
+                     stored = ?4, memo_id = ?5 WHERE id = ?6 AND upload_offset = ?7",
+                    params![
+                        upload.length,
+                        upload.offset,
+                        parts,
+                        upload.stored,
+                        upload.memo_id,
+                        upload.id,
+                        previous_offset,
+                    ],
+                )
+            })
+            .await?;
+
+        match updated {
+            0 => Err(ModelManagerError::UploadConflict),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_upload(&self, id: &str) -> Result<()> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.execute("DELETE FROM upload WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stored_upload_keys(&self) -> Result<Vec<String>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT object_key FROM upload WHERE stored = 1")?;
+            let keys = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(keys)
+        })
+        .await
+    }
+
+    async fn expired_uploads(&self, now: DateTime<Utc>) -> Result<Vec<Upload>> {
+        let rows = self
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {} FROM upload WHERE expire <= ?1",
+                    UploadRow::COLUMNS
+                ))?;
+                let rows = stmt
+                    .query_map(params![now.timestamp()], UploadRow::from_row)?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+                Ok(rows)
+            })
+            .await?;
+
+        let uploads = rows
+            .into_iter()
+            .map(UploadRow::into_upload)
+            .collect::<serde_json::Result<_>>()?;
+
+        Ok(uploads)
+    }
+
+    async fn create_link_nonce(&self, nonce: &str, expire: DateTime<Utc>) -> Result<()> {
+        let nonce = nonce.to_string();
+
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO link_nonce (nonce, expire) VALUES (?1, ?2)",
+                params![nonce, expire.timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn consume_link_nonce(&self, nonce: &str) -> Result<bool> {
+        let nonce = nonce.to_string();
+
+        let deleted = self
+            .call(move |conn| {
+                conn.execute("DELETE FROM link_nonce WHERE nonce = ?1", params![nonce])
+            })
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn clean_link_nonces(&self, now: DateTime<Utc>) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM link_nonce WHERE expire <= ?1",
+                params![now.timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        let api_key = api_key.clone();
+        let scopes = api_key
+            .scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        self.call(move |conn| {
+            conn.execute(
+                &format!(
+                    "INSERT INTO api_key ({API_KEY_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+                ),
+                params![
+                    api_key.id,
+                    api_key.name,
+                    api_key.key_hash,
+                    scopes,
+                    api_key.expire.map(|exp| exp.timestamp()),
+                    api_key.revoked,
+                    api_key.created_at.timestamp(),
+                    api_key.quota_size,
+                    api_key.quota_files,
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn read_api_key(&self, id: &str) -> Result<ApiKey> {
+        let id = id.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                &format!("SELECT {API_KEY_COLUMNS} FROM api_key WHERE id = ?1"),
+                params![id],
+                api_key_from_row,
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(ModelManagerError::ApiKeyNotFound)
+    }
+
+    async fn read_api_key_by_hash(&self, key_hash: &str) -> Result<ApiKey> {
+        let key_hash = key_hash.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                &format!("SELECT {API_KEY_COLUMNS} FROM api_key WHERE key_hash = ?1"),
+                params![key_hash],
+                api_key_from_row,
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(ModelManagerError::ApiKeyNotFound)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {API_KEY_COLUMNS} FROM api_key ORDER BY created_at"
+            ))?;
+            let keys = stmt
+                .query_map([], api_key_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(keys)
+        })
+        .await
+    }
+
+    async fn revoke_api_key(&self, id: &str) -> Result<()> {
+        let id = id.to_string();
+
+        let updated = self
+            .call(move |conn| {
+                conn.execute("UPDATE api_key SET revoked = 1 WHERE id = ?1", params![id])
+            })
+            .await?;
+
+        match updated {
+            0 => Err(ModelManagerError::ApiKeyNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn usage(&self, created_by: Option<&str>) -> Result<Usage> {
+        let created_by = created_by.map(str::to_string);
+
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT COALESCE(SUM(size), 0), COUNT(*) FROM asset
+                 WHERE created_by IS ?1 AND pending = 0",
+                params![created_by],
+                |row| {
+                    Ok(Usage {
+                        size: row.get(0)?,
+                        files: row.get(1)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn create_job_run(&self, run: &JobRun) -> Result<()> {
+        let run = run.clone();
+        let counts = serde_json::to_string(&run.counts)?;
+
+        self.call(move |conn| {
+            conn.execute(
+                &format!(
+                    "INSERT INTO job_run ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    JobRunRow::COLUMNS
+                ),
+                params![
+                    run.id,
+                    run.job,
+                    run.manual,
+                    run.started_at.timestamp(),
+                    run.finished_at.timestamp(),
+                    run.status.as_str(),
+                    run.attempts,
+                    counts,
+                    run.failures,
+                    run.error,
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_job_runs(&self, job: &str, limit: u32) -> Result<Vec<JobRun>> {
+        let job = job.to_string();
+
+        let rows = self
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {} FROM job_run WHERE job = ?1 ORDER BY started_at DESC, rowid DESC
+                     LIMIT ?2",
+                    JobRunRow::COLUMNS
+                ))?;
+                let rows = stmt
+                    .query_map(params![job, limit], JobRunRow::from_row)?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+                Ok(rows)
+            })
+            .await?;
+
+        let runs = rows
+            .into_iter()
+            .map(JobRunRow::into_job_run)
+            .collect::<serde_json::Result<_>>()?;
+
+        Ok(runs)
+    }
+
+    async fn clean_job_runs(&self, before: DateTime<Utc>) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM job_run WHERE started_at < ?1",
+                params![before.timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn record_object_failure(
+        &self,
+        kind: &str,
+        key: &str,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let kind = kind.to_string();
+        let key = key.to_string();
+        let error = error.to_string();
+
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO object_failure
+                 (kind, key, attempts, error, first_failed_at, last_failed_at)
+                 VALUES (?1, ?2, 1, ?3, ?4, ?4)
+                 ON CONFLICT (kind, key) DO UPDATE SET
+                 attempts = attempts + 1, error = excluded.error,
+                 last_failed_at = excluded.last_failed_at",
+                params![kind, key, error, now.timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear_object_failure(&self, kind: &str, key: &str) -> Result<()> {
+        let kind = kind.to_string();
+        let key = key.to_string();
+
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM object_failure WHERE kind = ?1 AND key = ?2",
+                params![kind, key],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_object_failures(&self) -> Result<Vec<ObjectFailure>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT kind, key, attempts, error, first_failed_at, last_failed_at
+                 FROM object_failure ORDER BY last_failed_at DESC",
+            )?;
+            let failures = stmt
+                .query_map([], |row| {
+                    Ok(ObjectFailure {
+                        kind: row.get(0)?,
+                        key: row.get(1)?,
+                        attempts: row.get(2)?,
+                        error: row.get(3)?,
+                        first_failed_at: from_timestamp(row.get(4)?),
+                        last_failed_at: from_timestamp(row.get(5)?),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(failures)
+        })
+        .await
+    }
+
+    async fn clean_object_failures(&self, before: DateTime<Utc>) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM object_failure WHERE last_failed_at < ?1",
+                params![before.timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stats(&self) -> Result<Stats> {
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT (SELECT COUNT(*) FROM asset WHERE pending = 0), (SELECT COUNT(*) FROM text),
+                 (SELECT COALESCE(SUM(size), 0) FROM asset WHERE pending = 0)",
+                [],
+                |row| {
+                    Ok(Stats {
+                        assets: row.get(0)?,
+                        texts: row.get(1)?,
+                        asset_bytes: row.get(2)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.call(|conn| conn.query_row("SELECT 1", [], |_| Ok(())))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens a database in memory with only the first `version` migrations applied.
+    fn database_at(version: usize) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..version] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+
+        conn
+    }
+
+    fn user_version(conn: &Connection) -> usize {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    fn store(conn: Connection) -> SqliteStore {
+        SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+
+    #[test]
+    fn migrate_from_every_version() {
+        for version in 0..=MIGRATIONS.len() {
+            let mut conn = database_at(version);
+            SqliteStore::migrate(&mut conn).unwrap();
+            assert_eq!(user_version(&conn), MIGRATIONS.len(), "from v{version}");
+
+            // Migrating an up to date database does nothing
+            SqliteStore::migrate(&mut conn).unwrap();
+            assert_eq!(user_version(&conn), MIGRATIONS.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn migrate_keeps_the_rows_of_the_previous_version() {
+        let mut conn = database_at(MIGRATIONS.len() - 1);
+        conn.execute_batch(
+            "INSERT INTO asset (id, file_name, encrypted, memo_id, created_by, size)
+             VALUES ('file', 'a.txt', 0, 'some_memo_id', 'root', 5);
+             INSERT INTO upload (id, object_key, multipart_id, upload_length, upload_offset, parts,
+                                 asset, expire)
+             VALUES ('upload', 'key', 'multipart', 10, 5, '[{\"number\":1,\"etag\":\"1\"}]',
+                     '{\"encrypted\":false,\"file_name\":\"b.txt\",\"expire\":null,
+                       \"memo_id\":null,\"max_downloads\":null,\"delete_token\":null,
+                       \"created_by\":\"root\",\"size\":10}', 4102444800);",
+        )
+        .unwrap();
+        SqliteStore::migrate(&mut conn).unwrap();
+        let store = store(conn);
+
+        let asset = store.read_asset_by_memo_id("some_memo_id").await.unwrap();
+        assert_eq!(asset.id, "file");
+        assert_eq!(asset.size, Some(5));
+
+        // Uploads from before have not stored their file yet
+        let upload = store.read_upload("upload").await.unwrap();
+        assert_eq!(upload.offset, 5);
+        assert_eq!(upload.parts.len(), 1);
+        assert!(!upload.stored);
+        assert!(!upload.is_complete());
+    }
+
+    #[tokio::test]
+    async fn migrate_renames_duplicate_text_memo_ids() {
+        let mut conn = database_at(8);
+        conn.execute_batch(
+            "INSERT INTO text (id, content, memo_id, expire) VALUES ('a', 'first', 'same', 4102444800);
+             INSERT INTO text (id, content, memo_id, expire) VALUES ('b', 'second', 'same', 4102444800);
+             INSERT INTO text (id, content, memo_id, expire) VALUES ('c', 'other', 'other', 4102444800);",
+        )
+        .unwrap();
+        SqliteStore::migrate(&mut conn).unwrap();
+        let store = store(conn);
+
+        // The first text keeps its memo id, the duplicate gets its id appended
+        assert_eq!(store.read_text_by_memo_id("same").await.unwrap().id, "a");
+        assert_eq!(store.read_text_by_memo_id("same_b").await.unwrap().id, "b");
+        assert_eq!(store.read_text_by_memo_id("other").await.unwrap().id, "c");
+
+        // The memo ids of the texts are unique from now on
+        let text = TextToCreate {
+            content: "third".to_string(),
+            memo_id: "same".to_string(),
+            expire: Utc::now() + chrono::TimeDelta::hours(1),
+            delete_token: None,
+        };
+        assert!(matches!(
+            store.create_text(text).await,
+            Err(ModelManagerError::MemoIdConflict)
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_memo_id_violations_are_conflicts() {
+        let store = store(database_at(MIGRATIONS.len()));
+
+        let asset = AssetToCreate {
+            encrypted: false,
+            file_name: "a.txt".to_string(),
+            expire: None,
+            memo_id: Some("same".to_string()),
+            max_downloads: None,
+            delete_token: None,
+            created_by: None,
+            size: None,
+            created_at: None,
+            signed_only: false,
+            pending: false,
+        };
+        store.create_asset("a", asset.clone()).await.unwrap();
+        assert!(matches!(
+            store.create_asset("b", asset).await,
+            Err(ModelManagerError::MemoIdConflict)
+        ));
+
+        let api_key = ApiKey {
+            id: "first".to_string(),
+            name: "first".to_string(),
+            key_hash: "hash".to_string(),
+            scopes: Vec::new(),
+            expire: None,
+            revoked: false,
+            created_at: Utc::now(),
+            quota_size: None,
+            quota_files: None,
+        };
+        store.create_api_key(&api_key).await.unwrap();
+        let api_key = ApiKey {
+            id: "second".to_string(),
+            ..api_key
+        };
+        assert!(matches!(
+            store.create_api_key(&api_key).await,
+            Err(ModelManagerError::Sqlite(_))
+        ));
+    }
+}
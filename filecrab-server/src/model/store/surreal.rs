@@ -0,0 +1,1101 @@
+use std::collections::{BTreeMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "rocksdb"))]
+use surrealdb::opt::auth::Namespace;
+#[cfg(not(feature = "rocksdb"))]
+use surrealdb::opt::auth::Root;
+
+use surrealdb::{
+    Surreal,
+    engine::any::{self, Any},
+    error::{Api, Db},
+    sql::{Datetime, Id, Thing},
+};
+
+use super::MetadataStore;
+use crate::{
+    config::SurrealConfig,
+    model::{
+        api_key::{ApiKey, Scope, Usage},
+        asset::{Asset, AssetEntry, AssetToCreate},
+        error::{ModelManagerError, Result},
+        job::{JobRun, ObjectFailure, RunStatus},
+        storage::UploadedPart,
+        store::Stats,
+        text::{Text, TextToCreate},
+        upload::Upload,
+    },
+};
+
+type SurrealConnection = Surreal<Any>;
+
+/// Metadata store backed by SurrealDB, either remote or embedded with rocksdb.
+#[derive(Debug, Clone)]
+pub struct SurrealStore {
+    //Surrealdb is cloneable
+    db: SurrealConnection,
+}
+
+/// Asset as stored in SurrealDB.
+#[derive(Deserialize)]
+struct AssetRecord {
+    id: Thing,
+    file_name: String,
+    memo_id: String,
+    delete_token: Option<String>,
+    #[serde(default)]
+    signed_only: bool,
+    encrypted: bool,
+    size: Option<u64>,
+    created_at: Option<Datetime>,
+    expire: Option<Datetime>,
+    max_downloads: Option<u32>,
+    #[serde(default)]
+    downloads: u32,
+    created_by: Option<String>,
+}
+
+impl From<AssetRecord> for Asset {
+    fn from(record: AssetRecord) -> Self {
+        Asset {
+            id: raw_id(record.id),
+            file_name: record.file_name,
+            memo_id: record.memo_id,
+            delete_token: record.delete_token,
+            signed_only: record.signed_only,
+            encrypted: record.encrypted,
+            size: record.size,
+            created_at: record.created_at.map(Into::into),
+            expire: record.expire.map(Into::into),
+            remaining_downloads: record
+                .max_downloads
+                .map(|max| max.saturating_sub(record.downloads)),
+            created_by: record.created_by,
+        }
+    }
+}
+
+/// Asset as listed to be compared with the storage.
+#[derive(Deserialize)]
+struct AssetEntryRecord {
+    id: Thing,
+    created_at: Option<Datetime>,
+}
+
+impl From<AssetEntryRecord> for AssetEntry {
+    fn from(record: AssetEntryRecord) -> Self {
+        AssetEntry {
+            id: raw_id(record.id),
+            created_at: record.created_at.map(Into::into),
+        }
+    }
+}
+
+/// Content of an asset to create in SurrealDB.
+#[derive(Serialize)]
+struct AssetContent {
+    encrypted: bool,
+    file_name: String,
+    expire: Option<Datetime>,
+    memo_id: Option<String>,
+    max_downloads: Option<u32>,
+    downloads: u32,
+    delete_token: Option<String>,
+    created_by: Option<String>,
+    size: Option<u64>,
+    signed_only: bool,
+    created_at: Option<Datetime>,
+    pending: bool,
+}
+
+/// Final metadata of a pending asset.
+#[derive(Serialize)]
+struct AssetCommit {
+    encrypted: bool,
+    file_name: String,
+    expire: Option<Datetime>,
+    max_downloads: Option<u32>,
+    delete_token: Option<String>,
+    size: Option<u64>,
+    signed_only: bool,
+    created_at: Option<Datetime>,
+    pending: bool,
+}
+
+/// Download counters of an asset.
+#[derive(Deserialize)]
+struct DownloadCount {
+    max_downloads: Option<u32>,
+    downloads: u32,
+}
+
+/// Text as stored in SurrealDB.
+#[derive(Deserialize)]
+struct TextRecord {
+    id: Thing,
+    content: String,
+    memo_id: String,
+    expire: Datetime,
+    delete_token: Option<String>,
+}
+
+impl From<TextRecord> for Text {
+    fn from(record: TextRecord) -> Self {
+        Text {
+            id: raw_id(record.id),
+            content: record.content,
+            memo_id: record.memo_id,
+            expire: record.expire.into(),
+            delete_token: record.delete_token,
+        }
+    }
+}
+
+/// Memo id of a text, read to rename the duplicates before indexing them.
+#[derive(Deserialize)]
+struct TextMemoIdRecord {
+    id: Thing,
+    memo_id: String,
+}
+
+/// Content of a text to create in SurrealDB.
+#[derive(Serialize)]
+struct TextContent {
+    content: String,
+    memo_id: String,
+    expire: Datetime,
+    delete_token: Option<String>,
+}
+
+/// Upload as stored in SurrealDB.
+#[derive(Deserialize)]
+struct UploadRecord {
+    id: Thing,
+    object_key: String,
+    multipart_id: String,
+    upload_length: Option<u64>,
+    upload_offset: u64,
+    parts: Vec<UploadedPart>,
+    asset: AssetToCreate,
+    #[serde(default)]
+    stored: bool,
+    memo_id: Option<String>,
+    expire: Datetime,
+}
+
+impl From<UploadRecord> for Upload {
+    fn from(record: UploadRecord) -> Self {
+        Upload {
+            id: raw_id(record.id),
+            object_key: record.object_key,
+            multipart_id: record.multipart_id,
+            length: record.upload_length,
+            offset: record.upload_offset,
+            parts: record.parts,
+            asset: record.asset,
+            stored: record.stored,
+            memo_id: record.memo_id,
+            expire: record.expire.into(),
+        }
+    }
+}
+
+/// Content of an upload to create in SurrealDB.
+#[derive(Serialize)]
+struct UploadContent {
+    object_key: String,
+    multipart_id: String,
+    upload_length: Option<u64>,
+    upload_offset: u64,
+    parts: Vec<UploadedPart>,
+    asset: AssetToCreate,
+    stored: bool,
+    memo_id: Option<String>,
+    expire: Datetime,
+}
+
+/// Number and total size of the assets.
+#[derive(Default, Deserialize)]
+struct AssetStats {
+    count: u64,
+    bytes: u64,
+}
+
+/// Nonce of a single use link as stored in SurrealDB.
+#[derive(Serialize, Deserialize)]
+struct LinkNonceContent {
+    expire: Datetime,
+}
+
+/// Api key as stored in SurrealDB.
+#[derive(Deserialize)]
+struct ApiKeyRecord {
+    id: Thing,
+    name: String,
+    key_hash: String,
+    scopes: Vec<Scope>,
+    expire: Option<Datetime>,
+    revoked: bool,
+    created_at: Datetime,
+    quota_size: Option<u64>,
+    quota_files: Option<u64>,
+}
+
+impl From<ApiKeyRecord> for ApiKey {
+    fn from(record: ApiKeyRecord) -> Self {
+        ApiKey {
+            id: raw_id(record.id),
+            name: record.name,
+            key_hash: record.key_hash,
+            scopes: record.scopes,
+            expire: record.expire.map(Into::into),
+            revoked: record.revoked,
+            created_at: record.created_at.into(),
+            quota_size: record.quota_size,
+            quota_files: record.quota_files,
+        }
+    }
+}
+
+/// Content of an api key to create in SurrealDB.
+#[derive(Serialize)]
+struct ApiKeyContent {
+    name: String,
+    key_hash: String,
+    scopes: Vec<Scope>,
+    expire: Option<Datetime>,
+    revoked: bool,
+    created_at: Datetime,
+    quota_size: Option<u64>,
+    quota_files: Option<u64>,
+}
+
+/// Job run as stored in SurrealDB.
+#[derive(Deserialize)]
+struct JobRunRecord {
+    id: Thing,
+    job: String,
+    manual: bool,
+    started_at: Datetime,
+    finished_at: Datetime,
+    status: RunStatus,
+    attempts: u32,
+    counts: BTreeMap<String, u64>,
+    failures: u64,
+    error: Option<String>,
+}
+
+impl From<JobRunRecord> for JobRun {
+    fn from(record: JobRunRecord) -> Self {
+        JobRun {
+            id: raw_id(record.id),
+            job: record.job,
+            manual: record.manual,
+            started_at: record.started_at.into(),
+            finished_at: record.finished_at.into(),
+            status: record.status,
+            attempts: record.attempts,
+            counts: record.counts,
+            failures: record.failures,
+            error: record.error,
+        }
+    }
+}
+
+/// Content of a job run to create in SurrealDB.
+#[derive(Serialize)]
+struct JobRunContent {
+    job: String,
+    manual: bool,
+    started_at: Datetime,
+    finished_at: Datetime,
+    status: RunStatus,
+    attempts: u32,
+    counts: BTreeMap<String, u64>,
+    failures: u64,
+    error: Option<String>,
+}
+
+/// Object failure as stored in SurrealDB, its id is made of the kind and the key.
+#[derive(Deserialize)]
+struct ObjectFailureRecord {
+    kind: String,
+    key: String,
+    attempts: u32,
+    error: String,
+    first_failed_at: Datetime,
+    last_failed_at: Datetime,
+}
+
+impl From<ObjectFailureRecord> for ObjectFailure {
+    fn from(record: ObjectFailureRecord) -> Self {
+        ObjectFailure {
+            kind: record.kind,
+            key: record.key,
+            attempts: record.attempts,
+            error: record.error,
+            first_failed_at: record.first_failed_at.into(),
+            last_failed_at: record.last_failed_at.into(),
+        }
+    }
+}
+
+/// Unique indexes on the memo ids of the assets and texts.
+const MEMO_ID_INDEXES: [&str; 2] = ["fileMemoIdUnique", "textMemoIdUnique"];
+
+/// Maps a violation of the unique memo id indexes to a memo id conflict, and any other error with
+/// `other`.
+fn memo_id_conflict(
+    err: surrealdb::Error,
+    other: fn(surrealdb::Error) -> ModelManagerError,
+) -> ModelManagerError {
+    let conflict = match &err {
+        surrealdb::Error::Db(Db::IndexExists { index, .. }) => {
+            MEMO_ID_INDEXES.contains(&index.as_str())
+        }
+        // The remote engines only send back the message of the same error
+        surrealdb::Error::Api(Api::Query(message)) => MEMO_ID_INDEXES.iter().any(|index| {
+            message.starts_with(&format!("Database index `{index}` already contains"))
+        }),
+        _ => false,
+    };
+
+    if conflict {
+        ModelManagerError::MemoIdConflict
+    } else {
+        other(err)
+    }
+}
+
+/// Returns the texts sharing their memo id with an earlier text along with their new memo id, the
+/// id of the text appended to the one they had.
+fn renamed_memo_ids(texts: Vec<TextMemoIdRecord>) -> Vec<(Thing, String)> {
+    let mut seen = HashSet::new();
+    texts
+        .into_iter()
+        .filter(|text| !seen.insert(text.memo_id.clone()))
+        .map(|text| {
+            let memo_id = format!("{}_{}", text.memo_id, raw_id(text.id.clone()));
+            (text.id, memo_id)
+        })
+        .collect()
+}
+
+/// Returns the id of a record, without the escaping its `Display` adds to numeric ids.
+fn raw_id(thing: Thing) -> String {
+    match thing.id {
+        Id::String(id) => id,
+        id => id.to_string(),
+    }
+}
+
+impl SurrealStore {
+    /// Function that tries to connect to the SurrealDB instance and prepares the tables
+    pub async fn new(conf: &SurrealConfig) -> Result<Self> {
+        //SurrealDB
+        let db = any::connect(&conf.host_or_path)
+            .await
+            .map_err(ModelManagerError::NewDB)?;
+
+        // Sign in when not on rocksdb
+        #[cfg(not(feature = "rocksdb"))]
+        if &conf.user == "root" {
+            db.signin(Root {
+                username: &conf.user,
+                password: &conf.password,
+            })
+            .await
+            .map_err(ModelManagerError::SignIn)?;
+        } else {
+            db.signin(Namespace {
+                namespace: &conf.ns,
+                username: &conf.user,
+                password: &conf.password,
+            })
+            .await
+            .map_err(ModelManagerError::SignIn)?;
+        }
+
+        //Set DB from config
+        db.use_ns(&conf.ns)
+            .use_db(&conf.dbname)
+            .await
+            .map_err(|err| ModelManagerError::SetUseNSandDb {
+                ns: conf.ns.to_string(),
+                db: conf.dbname.to_string(),
+                source: err,
+            })?;
+
+        // Create the assets table
+        db.query("DEFINE TABLE IF NOT EXISTS asset")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+
+        // Create the uploads table
+        db.query("DEFINE TABLE IF NOT EXISTS upload")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+
+        // Create the single use link nonces table
+        db.query("DEFINE TABLE IF NOT EXISTS link_nonce")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+
+        // Create the job runs and object failures tables
+        db.query("DEFINE TABLE IF NOT EXISTS job_run")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+        db.query("DEFINE TABLE IF NOT EXISTS object_failure")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+
+        // Create the api keys table
+        db.query("DEFINE TABLE IF NOT EXISTS api_key")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+
+        // Keys are looked up by the hash of their secret
+        db.query(
+            "DEFINE INDEX IF NOT EXISTS apiKeyHashUnique ON TABLE api_key COLUMNS key_hash UNIQUE",
+        )
+        .await
+        .map_err(ModelManagerError::CouldNotSetTableIndex)?;
+
+        // Set the search index in memo_id asset column
+        db.query(
+            "DEFINE INDEX IF NOT EXISTS fileMemoIdUnique ON TABLE asset COLUMNS memo_id UNIQUE",
+        )
+        .await
+        .map_err(ModelManagerError::CouldNotSetTableIndex)?;
+
+        // Same for the texts
+        db.query("DEFINE TABLE IF NOT EXISTS text")
+            .await
+            .map_err(ModelManagerError::CouldNotDefineTable)?;
+        // Texts created before the index existed may share a memo id, all but one get their id
+        // appended
+        let texts: Vec<TextMemoIdRecord> = db
+            .query("SELECT id, memo_id FROM text ORDER BY id")
+            .await
+            .map_err(ModelManagerError::CouldNotSetTableIndex)?
+            .take(0)
+            .map_err(ModelManagerError::CouldNotSetTableIndex)?;
+        for (id, memo_id) in renamed_memo_ids(texts) {
+            db.query("UPDATE $id SET memo_id = $memo_id")
+                .bind(("id", id))
+                .bind(("memo_id", memo_id))
+                .await
+                .map_err(ModelManagerError::CouldNotSetTableIndex)?
+                .check()
+                .map_err(ModelManagerError::CouldNotSetTableIndex)?;
+        }
+        db.query(
+            "DEFINE INDEX IF NOT EXISTS textMemoIdUnique ON TABLE text COLUMNS memo_id UNIQUE",
+        )
+        .await
+        .map_err(ModelManagerError::CouldNotSetTableIndex)?;
+
+        Ok(SurrealStore { db })
+    }
+}
+
+#[async_trait]
+impl MetadataStore for SurrealStore {
+    async fn create_asset(&self, id: &str, data: AssetToCreate) -> Result<Asset> {
+        let content = AssetContent {
+            encrypted: data.encrypted,
+            file_name: data.file_name,
+            expire: data.expire.map(Datetime::from),
+            memo_id: data.memo_id,
+            max_downloads: data.max_downloads,
+            downloads: 0,
+            delete_token: data.delete_token,
+            created_by: data.created_by,
+            size: data.size,
+            signed_only: data.signed_only,
+            created_at: data.created_at.map(Datetime::from),
+            pending: data.pending,
+        };
+
+        let res: Option<AssetRecord> = self
+            .db
+            .create(("asset", id))
+            .content(content)
+            .await
+            .map_err(|err| memo_id_conflict(err, ModelManagerError::CreateAsset))?;
+
+        res.map(Asset::from)
+            .ok_or_else(|| ModelManagerError::AssetNotFound)
+    }
+
+    async fn commit_asset(&self, id: &str, data: AssetToCreate) -> Result<()> {
+        let content = AssetCommit {
+            encrypted: data.encrypted,
+            file_name: data.file_name,
+            expire: data.expire.map(Datetime::from),
+            max_downloads: data.max_downloads,
+            delete_token: data.delete_token,
+            size: data.size,
+            signed_only: data.signed_only,
+            created_at: data.created_at.map(Datetime::from),
+            pending: false,
+        };
+
+        // Nothing is returned when the asset is not pending anymore
+        let res: Option<AssetRecord> = self
+            .db
+            .query(
+                "UPDATE type::thing('asset', $id) MERGE $content WHERE pending = true RETURN AFTER",
+            )
+            .bind(("id", id.to_string()))
+            .bind(("content", content))
+            .await
+            .map_err(ModelManagerError::CreateAsset)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        res.map(|_| ())
+            .ok_or_else(|| ModelManagerError::AssetNotFound)
+    }
+
+    async fn read_asset_by_memo_id(&self, memo_id: &str) -> Result<Asset> {
+        let res: Option<AssetRecord> = self
+            .db
+            .query(
+                "SELECT * FROM asset WHERE memo_id = $memo_id AND pending != true \
+                 AND (expire = NONE OR expire > time::now()) LIMIT 1",
+            )
+            .bind(("memo_id", memo_id.to_string()))
+            .await
+            .map_err(ModelManagerError::SearchAsset)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        res.map(Asset::from)
+            .ok_or_else(|| ModelManagerError::AssetNotFound)
+    }
+
+    async fn register_download(&self, id: &str) -> Result<Option<u32>> {
+        // A single statement is atomic, the condition prevents going over the limit
+        let res: Option<DownloadCount> = self
+            .db
+            .query(
+                "UPDATE type::thing('asset', $id) SET downloads += 1 \
+                 WHERE max_downloads = NONE OR downloads < max_downloads RETURN AFTER",
+            )
+            .bind(("id", id.to_string()))
+            .await
+            .map_err(ModelManagerError::SearchAsset)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        let count = res.ok_or(ModelManagerError::AssetNotFound)?;
+
+        Ok(count
+            .max_downloads
+            .map(|max| max.saturating_sub(count.downloads)))
+    }
+
+    async fn delete_asset(&self, id: &str) -> Result<()> {
+        let _: Option<AssetRecord> = self
+            .db
+            .delete(("asset", id))
+            .await
+            .map_err(ModelManagerError::DeleteAsset)?;
+
+        Ok(())
+    }
+
+    async fn list_assets(&self) -> Result<Vec<AssetEntry>> {
+        let res: Vec<AssetEntryRecord> = self
+            .db
+            .query("SELECT id, created_at FROM asset WHERE pending != true")
+            .await
+            .map_err(ModelManagerError::SearchAsset)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.into_iter().map(AssetEntry::from).collect())
+    }
+
+    async fn list_pending_assets(&self) -> Result<Vec<String>> {
+        let res: Vec<Thing> = self
+            .db
+            .query("SELECT id FROM asset WHERE pending = true")
+            .await
+            .map_err(ModelManagerError::SearchAsset)?
+            .take((0, "id"))
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.into_iter().map(raw_id).collect())
+    }
+
+    async fn expired_assets(&self, now: DateTime<Utc>) -> Result<Vec<String>> {
+        let res: Vec<Thing> = self
+            .db
+            .query("SELECT id FROM asset WHERE expire <= $now")
+            .bind(("now", Datetime::from(now)))
+            .await
+            .map_err(ModelManagerError::SearchAsset)?
+            .take((0, "id"))
+            .map_err(ModelManagerError::TakeError)?;
+
+        // Collect only the id out of the things
+        Ok(res.into_iter().map(raw_id).collect())
+    }
+
+    async fn create_text(&self, data: TextToCreate) -> Result<Text> {
+        let content = TextContent {
+            content: data.content,
+            memo_id: data.memo_id,
+            expire: data.expire.into(),
+            delete_token: data.delete_token,
+        };
+
+        let res: Option<TextRecord> = self
+            .db
+            .create("text")
+            .content(content)
+            .await
+            .map_err(|err| memo_id_conflict(err, ModelManagerError::CreateText))?;
+
+        res.map(Text::from)
+            .ok_or_else(|| ModelManagerError::TextNotFound)
+    }
+
+    async fn read_text_by_memo_id(&self, memo_id: &str) -> Result<Text> {
+        let res: Option<TextRecord> = self
+            .db
+            .query("SELECT * FROM text WHERE memo_id == $memo_id LIMIT 1")
+            .bind(("memo_id", memo_id.to_string()))
+            .await
+            .map_err(ModelManagerError::SearchText)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        res.map(Text::from)
+            .ok_or_else(|| ModelManagerError::TextNotFound)
+    }
+
+    async fn delete_text(&self, id: &str) -> Result<()> {
+        let _: Option<TextRecord> = self
+            .db
+            .delete(("text", id))
+            .await
+            .map_err(ModelManagerError::DeleteText)?;
+
+        Ok(())
+    }
+
+    async fn clean_texts(&self, now: DateTime<Utc>) -> Result<u64> {
+        let now: Datetime = now.into();
+
+        let res: Vec<Thing> = self
+            .db
+            .query("DELETE text WHERE expire <= $now RETURN BEFORE")
+            .bind(("now", now))
+            .await
+            .map_err(ModelManagerError::DeleteText)?
+            .take((0, "id"))
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.len() as u64)
+    }
+
+    async fn create_upload(&self, upload: &Upload) -> Result<()> {
+        let content = UploadContent {
+            object_key: upload.object_key.clone(),
+            multipart_id: upload.multipart_id.clone(),
+            upload_length: upload.length,
+            upload_offset: upload.offset,
+            parts: upload.parts.clone(),
+            asset: upload.asset.clone(),
+            stored: upload.stored,
+            memo_id: upload.memo_id.clone(),
+            expire: upload.expire.into(),
+        };
+
+        let _: Option<UploadRecord> = self
+            .db
+            .create(("upload", upload.id.as_str()))
+            .content(content)
+            .await
+            .map_err(ModelManagerError::CreateUpload)?;
+
+        Ok(())
+    }
+
+    async fn read_upload(&self, id: &str) -> Result<Upload> {
+        let res: Option<UploadRecord> = self
+            .db
+            .select(("upload", id))
+            .await
+            .map_err(ModelManagerError::SearchUpload)?;
+
+        res.map(Upload::from)
+            .ok_or_else(|| ModelManagerError::UploadNotFound)
+    }
+
+    async fn update_upload(&self, upload: &Upload, previous_offset: u64) -> Result<()> {
+        // The condition makes the update a compare and swap on the offset
+        let res: Option<UploadRecord> = self
+            .db
+            .query(
+                "UPDATE type::thing('upload', $id) SET upload_length = $length, \
+                 upload_offset = $offset, parts = $parts, stored = $stored, memo_id = $memo_id \
+                 WHERE upload_offset = $previous_offset RETURN AFTER",
+            )
+            .bind(("id", upload.id.clone()))
+            .bind(("length", upload.length))
+            .bind(("offset", upload.offset))
+            .bind(("parts", upload.parts.clone()))
+            .bind(("stored", upload.stored))
+            .bind(("memo_id", upload.memo_id.clone()))
+            .bind(("previous_offset", previous_offset))
+            .await
+            .map_err(ModelManagerError::UpdateUpload)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        res.map(|_| ())
+            .ok_or_else(|| ModelManagerError::UploadConflict)
+    }
+
+    async fn delete_upload(&self, id: &str) -> Result<()> {
+        let _: Option<UploadRecord> = self
+            .db
+            .delete(("upload", id))
+            .await
+            .map_err(ModelManagerError::DeleteUpload)?;
+
+        Ok(())
+    }
+
+    async fn stored_upload_keys(&self) -> Result<Vec<String>> {
+        let res: Vec<String> = self
+            .db
+            .query("SELECT object_key FROM upload WHERE stored = true")
+            .await
+            .map_err(ModelManagerError::SearchUpload)?
+            .take((0, "object_key"))
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res)
+    }
+
+    async fn expired_uploads(&self, now: DateTime<Utc>) -> Result<Vec<Upload>> {
+        let res: Vec<UploadRecord> = self
+            .db
+            .query("SELECT * FROM upload WHERE expire <= $now")
+            .bind(("now", Datetime::from(now)))
+            .await
+            .map_err(ModelManagerError::SearchUpload)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.into_iter().map(Upload::from).collect())
+    }
+
+    async fn create_link_nonce(&self, nonce: &str, expire: DateTime<Utc>) -> Result<()> {
+        let _: Option<LinkNonceContent> = self
+            .db
+            .create(("link_nonce", nonce))
+            .content(LinkNonceContent {
+                expire: expire.into(),
+            })
+            .await
+            .map_err(ModelManagerError::CreateLinkNonce)?;
+
+        Ok(())
+    }
+
+    async fn consume_link_nonce(&self, nonce: &str) -> Result<bool> {
+        // Deleting is atomic, only one request gets the nonce back
+        let res: Option<LinkNonceContent> = self
+            .db
+            .delete(("link_nonce", nonce))
+            .await
+            .map_err(ModelManagerError::DeleteLinkNonce)?;
+
+        Ok(res.is_some())
+    }
+
+    async fn clean_link_nonces(&self, now: DateTime<Utc>) -> Result<()> {
+        let _ = self
+            .db
+            .query("DELETE link_nonce WHERE expire <= $now")
+            .bind(("now", Datetime::from(now)))
+            .await
+            .map_err(ModelManagerError::DeleteLinkNonce)?;
+
+        Ok(())
+    }
+
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        let content = ApiKeyContent {
+            name: api_key.name.clone(),
+            key_hash: api_key.key_hash.clone(),
+            scopes: api_key.scopes.clone(),
+            expire: api_key.expire.map(Datetime::from),
+            revoked: api_key.revoked,
+            created_at: api_key.created_at.into(),
+            quota_size: api_key.quota_size,
+            quota_files: api_key.quota_files,
+        };
+
+        let _: Option<ApiKeyRecord> = self
+            .db
+            .create(("api_key", api_key.id.as_str()))
+            .content(content)
+            .await
+            .map_err(ModelManagerError::CreateApiKey)?;
+
+        Ok(())
+    }
+
+    async fn read_api_key(&self, id: &str) -> Result<ApiKey> {
+        let res: Option<ApiKeyRecord> = self
+            .db
+            .select(("api_key", id))
+            .await
+            .map_err(ModelManagerError::SearchApiKey)?;
+
+        res.map(ApiKey::from)
+            .ok_or_else(|| ModelManagerError::ApiKeyNotFound)
+    }
+
+    async fn read_api_key_by_hash(&self, key_hash: &str) -> Result<ApiKey> {
+        let res: Option<ApiKeyRecord> = self
+            .db
+            .query("SELECT * FROM api_key WHERE key_hash = $key_hash LIMIT 1")
+            .bind(("key_hash", key_hash.to_string()))
+            .await
+            .map_err(ModelManagerError::SearchApiKey)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        res.map(ApiKey::from)
+            .ok_or_else(|| ModelManagerError::ApiKeyNotFound)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let res: Vec<ApiKeyRecord> = self
+            .db
+            .query("SELECT * FROM api_key ORDER BY created_at")
+            .await
+            .map_err(ModelManagerError::SearchApiKey)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.into_iter().map(ApiKey::from).collect())
+    }
+
+    async fn revoke_api_key(&self, id: &str) -> Result<()> {
+        // UPDATE does not create missing records, nothing is returned for an unknown key
+        let res: Option<ApiKeyRecord> = self
+            .db
+            .query("UPDATE type::thing('api_key', $id) SET revoked = true RETURN AFTER")
+            .bind(("id", id.to_string()))
+            .await
+            .map_err(ModelManagerError::UpdateApiKey)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        res.map(|_| ())
+            .ok_or_else(|| ModelManagerError::ApiKeyNotFound)
+    }
+
+    async fn usage(&self, created_by: Option<&str>) -> Result<Usage> {
+        // Without any matching asset there is no group, hence no row
+        let res: Option<Usage> = self
+            .db
+            .query(
+                "SELECT math::sum(size ?? 0) AS size, count() AS files FROM asset \
+                 WHERE created_by = $created_by AND pending != true GROUP ALL",
+            )
+            .bind(("created_by", created_by.map(str::to_string)))
+            .await
+            .map_err(ModelManagerError::SearchAsset)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.unwrap_or_default())
+    }
+
+    async fn create_job_run(&self, run: &JobRun) -> Result<()> {
+        let content = JobRunContent {
+            job: run.job.clone(),
+            manual: run.manual,
+            started_at: run.started_at.into(),
+            finished_at: run.finished_at.into(),
+            status: run.status,
+            attempts: run.attempts,
+            counts: run.counts.clone(),
+            failures: run.failures,
+            error: run.error.clone(),
+        };
+
+        let _: Option<JobRunRecord> = self
+            .db
+            .create(("job_run", run.id.as_str()))
+            .content(content)
+            .await
+            .map_err(ModelManagerError::CreateJobRun)?;
+
+        Ok(())
+    }
+
+    async fn list_job_runs(&self, job: &str, limit: u32) -> Result<Vec<JobRun>> {
+        let res: Vec<JobRunRecord> = self
+            .db
+            .query("SELECT * FROM job_run WHERE job = $job ORDER BY started_at DESC LIMIT $limit")
+            .bind(("job", job.to_string()))
+            .bind(("limit", limit))
+            .await
+            .map_err(ModelManagerError::SearchJobRun)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.into_iter().map(JobRun::from).collect())
+    }
+
+    async fn clean_job_runs(&self, before: DateTime<Utc>) -> Result<()> {
+        let _ = self
+            .db
+            .query("DELETE job_run WHERE started_at < $before")
+            .bind(("before", Datetime::from(before)))
+            .await
+            .map_err(ModelManagerError::DeleteJobRun)?;
+
+        Ok(())
+    }
+
+    async fn record_object_failure(
+        &self,
+        kind: &str,
+        key: &str,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        // Creates the failure on the first attempt, counts the attempt otherwise
+        let _ = self
+            .db
+            .query(
+                "UPSERT type::thing('object_failure', [$kind, $key]) SET kind = $kind, \
+                 key = $key, attempts = (attempts ?? 0) + 1, error = $error, \
+                 first_failed_at = first_failed_at ?? $now, last_failed_at = $now",
+            )
+            .bind(("kind", kind.to_string()))
+            .bind(("key", key.to_string()))
+            .bind(("error", error.to_string()))
+            .bind(("now", Datetime::from(now)))
+            .await
+            .map_err(ModelManagerError::UpdateObjectFailure)?;
+
+        Ok(())
+    }
+
+    async fn clear_object_failure(&self, kind: &str, key: &str) -> Result<()> {
+        let _ = self
+            .db
+            .query("DELETE type::thing('object_failure', [$kind, $key])")
+            .bind(("kind", kind.to_string()))
+            .bind(("key", key.to_string()))
+            .await
+            .map_err(ModelManagerError::UpdateObjectFailure)?;
+
+        Ok(())
+    }
+
+    async fn list_object_failures(&self) -> Result<Vec<ObjectFailure>> {
+        let res: Vec<ObjectFailureRecord> = self
+            .db
+            .query("SELECT * FROM object_failure ORDER BY last_failed_at DESC")
+            .await
+            .map_err(ModelManagerError::SearchObjectFailure)?
+            .take(0)
+            .map_err(ModelManagerError::TakeError)?;
+
+        Ok(res.into_iter().map(ObjectFailure::from).collect())
+    }
+
+    async fn clean_object_failures(&self, before: DateTime<Utc>) -> Result<()> {
+        let _ = self
+            .db
+            .query("DELETE object_failure WHERE last_failed_at < $before")
+            .bind(("before", Datetime::from(before)))
+            .await
+            .map_err(ModelManagerError::UpdateObjectFailure)?;
+
+        Ok(())
+    }
+
+    async fn stats(&self) -> Result<Stats> {
+        // Counting on an empty table returns no group, hence no row
+        let mut res = self
+            .db
+            .query(
+                "SELECT count() AS count, math::sum(size ?? 0) AS bytes FROM asset \
+                 WHERE pending != true GROUP ALL",
+            )
+            .query("SELECT count() AS count FROM text GROUP ALL")
+            .await
+            .map_err(ModelManagerError::SearchAsset)?;
+
+        let assets: Option<AssetStats> = res.take(0).map_err(ModelManagerError::TakeError)?;
+        let texts: Option<u64> = res
+            .take((1, "count"))
+            .map_err(ModelManagerError::TakeError)?;
+
+        let assets = assets.unwrap_or_default();
+        Ok(Stats {
+            assets: assets.count,
+            texts: texts.unwrap_or_default(),
+            asset_bytes: assets.bytes,
+        })
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.db.health().await.map_err(ModelManagerError::DbHealth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(id: &str, memo_id: &str) -> TextMemoIdRecord {
+        TextMemoIdRecord {
+            id: Thing::from(("text", id)),
+            memo_id: memo_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn renamed_memo_ids_keep_the_first_text() {
+        let renamed = renamed_memo_ids(vec![
+            text("a", "same"),
+            text("b", "same"),
+            text("c", "other"),
+            text("d", "same"),
+        ]);
+
+        assert_eq!(
+            renamed,
+            vec![
+                (Thing::from(("text", "b")), "same_b".to_string()),
+                (Thing::from(("text", "d")), "same_d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_memo_ids_without_duplicates() {
+        assert!(renamed_memo_ids(vec![text("a", "one"), text("b", "two")]).is_empty());
+        assert!(renamed_memo_ids(Vec::new()).is_empty());
+    }
+}
@@ -1,82 +1,57 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use surrealdb::sql::{Datetime, Thing};
 
-use super::error::{ModelManagerError, Result};
+use super::{error::Result, with_memo_id};
 use crate::{config::config, model::ModelManager};
 
 #[derive(Serialize, Deserialize)]
 pub struct Text {
-    pub id: Thing,
+    pub id: String,
     pub content: String,
     pub memo_id: String,
-    expire: Datetime,
+    pub expire: DateTime<Utc>,
+    pub delete_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TextToCreate {
     pub content: String,
     #[serde(skip_deserializing)]
-    memo_id: String,
+    pub memo_id: String,
+    #[serde(skip_deserializing)]
+    pub expire: DateTime<Utc>,
+    /// Hash of the token allowing the owner to delete the text.
     #[serde(skip_deserializing)]
-    expire: Datetime,
+    pub delete_token: Option<String>,
 }
 
 impl Text {
     pub async fn create(mm: ModelManager, mut data: TextToCreate) -> Result<Text> {
-        let db = mm.db();
-        // Set a memo_id
-        data.memo_id = memorable_wordlist::snake_case(40);
-
         // Set expire
-        let expire = Utc::now() + config().DEFAULT_EXPIRE_TIME;
-        data.expire = expire.into();
-
-        let res: Option<Text> = db
-            .create("text")
-            .content(data)
-            .await
-            .map_err(ModelManagerError::CreateText)?;
-
-        res.ok_or_else(|| ModelManagerError::TextNotFound)
+        data.expire = Utc::now() + config().DEFAULT_EXPIRE_TIME;
+
+        with_memo_id(|memo_id| {
+            let data = TextToCreate {
+                memo_id,
+                content: data.content.clone(),
+                expire: data.expire,
+                delete_token: data.delete_token.clone(),
+            };
+            mm.store().create_text(data)
+        })
+        .await
     }
 
     pub async fn read(mm: ModelManager, memo_id: String) -> Result<Text> {
-        let db = mm.db();
-
-        let res: Option<Text> = db
-            .query("SELECT * FROM text WHERE memo_id == $memo_id LIMIT 1")
-            .bind(("memo_id", memo_id))
-            .await
-            .map_err(ModelManagerError::SearchText)?
-            .take(0)
-            .map_err(ModelManagerError::TakeError)?;
-
-        res.ok_or_else(|| ModelManagerError::TextNotFound)
+        mm.store().read_text_by_memo_id(&memo_id).await
     }
 
     pub async fn delete(mm: ModelManager, id: String) -> Result<()> {
-        let db = mm.db();
-
-        let _: Option<Text> = db
-            .delete(("text", id))
-            .await
-            .map_err(ModelManagerError::DeleteText)?;
-
-        Ok(())
+        mm.store().delete_text(&id).await
     }
 
-    pub async fn clean_text(mm: &ModelManager) -> Result<()> {
-        let db = mm.db();
-
-        let now: Datetime = Utc::now().into();
-
-        let _ = db
-            .query("DELETE text WHERE expire <= $now")
-            .bind(("now", now))
-            .await
-            .map_err(ModelManagerError::DeleteText)?;
-
-        Ok(())
+    /// Deletes the expired texts and returns how many were deleted.
+    pub async fn clean_text(mm: &ModelManager) -> Result<u64> {
+        mm.store().clean_texts(Utc::now()).await
     }
 }
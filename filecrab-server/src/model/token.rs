@@ -0,0 +1,17 @@
+use rand::distr::{Alphanumeric, SampleString};
+use sha2::{Digest, Sha256};
+
+/// Generates a new secret deletion token, only its hash is stored.
+pub fn generate() -> String {
+    Alphanumeric.sample_string(&mut rand::rng(), 32)
+}
+
+/// Hashes a token so it can be stored and compared without keeping the secret.
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Checks a token against a stored hash, a missing hash never matches.
+pub fn verify(token: &str, hash: Option<&str>) -> bool {
+    hash.is_some_and(|hash| self::hash(token) == hash)
+}
@@ -0,0 +1,146 @@
+use axum::body::Bytes;
+use chrono::{TimeDelta, prelude::*};
+use rand::distr::{Alphanumeric, SampleString};
+
+use super::{
+    asset::{AssetToCreate, PendingAsset},
+    error::Result,
+    storage::UploadedPart,
+};
+use crate::model::{ModelManager, api_key::ROOT_KEY_ID};
+
+/// Size of the parts sent to the storage, every part but the last has exactly this size.
+pub const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// How long an upload can stay unfinished before it is dropped.
+const UPLOAD_EXPIRE_TIME: TimeDelta = TimeDelta::days(1);
+
+/// A resumable upload, the file is sent in chunks and assembled in the storage once complete.
+#[derive(Clone, Debug)]
+pub struct Upload {
+    pub id: String,
+    /// Key of the file in the storage, and id of the asset once complete.
+    pub object_key: String,
+    /// Id of the multipart upload in the storage.
+    pub multipart_id: String,
+    /// Total size in bytes, unknown until the last chunk when the client deferred it.
+    pub length: Option<u64>,
+    /// Number of bytes safely stored.
+    pub offset: u64,
+    pub parts: Vec<UploadedPart>,
+    /// The asset to create once the upload is complete.
+    pub asset: AssetToCreate,
+    /// The file has been assembled in the storage, the parts are gone.
+    pub stored: bool,
+    /// Memorable id of the asset, set once the upload is complete.
+    pub memo_id: Option<String>,
+    /// The upload is dropped after this date, complete or not.
+    pub expire: DateTime<Utc>,
+}
+
+impl Upload {
+    pub async fn create(
+        mm: ModelManager,
+        length: Option<u64>,
+        asset: AssetToCreate,
+    ) -> Result<Upload> {
+        let object_key = Alphanumeric.sample_string(&mut rand::rng(), 16);
+        let multipart_id = mm.create_multipart(&object_key).await?;
+
+        let upload = Upload {
+            id: Alphanumeric.sample_string(&mut rand::rng(), 32),
+            object_key,
+            multipart_id,
+            length,
+            offset: 0,
+            parts: Vec::new(),
+            asset,
+            stored: false,
+            memo_id: None,
+            expire: Utc::now() + UPLOAD_EXPIRE_TIME,
+        };
+
+        mm.store().create_upload(&upload).await?;
+
+        Ok(upload)
+    }
+
+    pub async fn read(mm: ModelManager, id: &str) -> Result<Upload> {
+        mm.store().read_upload(id).await
+    }
+
+    /// Id of the api key which started the upload.
+    pub fn owner(&self) -> &str {
+        self.asset.created_by.as_deref().unwrap_or(ROOT_KEY_ID)
+    }
+
+    /// Whether every byte has been received and the asset created.
+    pub fn is_complete(&self) -> bool {
+        self.memo_id.is_some()
+    }
+
+    /// Sends a part to the storage and records it, failing with `UploadConflict` if another
+    /// request moved the upload forward meanwhile.
+    pub async fn write_part(&mut self, mm: ModelManager, data: Bytes) -> Result<()> {
+        let previous_offset = self.offset;
+        let len = data.len() as u64;
+        let number = self.parts.len() as u32 + 1;
+
+        let part = mm
+            .upload_part(&self.object_key, &self.multipart_id, number, data)
+            .await?;
+
+        self.parts.push(part);
+        self.offset += len;
+
+        mm.store().update_upload(self, previous_offset).await
+    }
+
+    /// Assembles the file and creates its asset, reserved beforehand like the one of a direct
+    /// upload. Once assembled the file is kept, a retry only creates the asset.
+    pub async fn complete(&mut self, mm: ModelManager) -> Result<()> {
+        let mut pending =
+            PendingAsset::reserve(mm.clone(), &self.object_key, self.asset.clone()).await?;
+
+        if !self.stored {
+            mm.complete_multipart(&self.object_key, &self.multipart_id, self.parts.clone())
+                .await?;
+
+            self.stored = true;
+            mm.store().update_upload(self, self.offset).await?;
+        }
+        // A failed commit leaves the file for the next attempt
+        pending.keep_file();
+
+        let asset = pending.commit(self.asset.clone()).await?;
+
+        self.memo_id = Some(asset.memo_id);
+
+        mm.store().update_upload(self, self.offset).await
+    }
+
+    /// Drops the upload, what was already sent is deleted unless the upload is complete.
+    pub async fn delete(mm: ModelManager, upload: &Upload) -> Result<()> {
+        if !upload.is_complete() {
+            if upload.stored {
+                mm.delete_files(vec![upload.object_key.clone()]).await?;
+            } else {
+                mm.abort_multipart(&upload.object_key, &upload.multipart_id)
+                    .await?;
+            }
+        }
+
+        mm.store().delete_upload(&upload.id).await
+    }
+
+    /// Returns the keys of the files assembled by the uploads, their asset may not be committed
+    /// yet.
+    pub async fn stored_keys(mm: &ModelManager) -> Result<Vec<String>> {
+        mm.store().stored_upload_keys().await
+    }
+
+    /// Returns the expired uploads, complete or not.
+    pub async fn expired(mm: &ModelManager) -> Result<Vec<Upload>> {
+        mm.store().expired_uploads(Utc::now()).await
+    }
+}
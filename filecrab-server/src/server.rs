@@ -0,0 +1,304 @@
+// Listeners of the server, on tcp addresses (optionally with TLS) and unix sockets.
+//
+// The TLS certificate and key are read again on SIGHUP, so a renewed certificate is picked up
+// without a restart. Handshakes run in their own tasks, a slow client never holds the others.
+
+use std::{
+    fs::File,
+    future::pending,
+    io::{self, BufReader},
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+};
+use rustls::{ServerConfig, crypto::ring};
+use tokio::{
+    net::{TcpListener, TcpStream, UnixListener},
+    signal,
+    sync::mpsc,
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tracing::{debug, error, info};
+
+use crate::{
+    Error, Result,
+    config::{BindAddr, TlsConfig, config},
+};
+
+/// Time given to a client to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Address of the client of a request, whichever listener it came through. The peers of the unix
+/// sockets have none.
+#[derive(Clone, Copy, Debug)]
+pub struct PeerAddr(pub Option<SocketAddr>);
+
+impl Connected<IncomingStream<'_, TcpListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        PeerAddr(Some(*stream.remote_addr()))
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        PeerAddr(Some(*stream.remote_addr()))
+    }
+}
+
+impl Connected<IncomingStream<'_, UnixListener>> for PeerAddr {
+    fn connect_info(_: IncomingStream<'_, UnixListener>) -> Self {
+        PeerAddr(None)
+    }
+}
+
+/// TLS settings of the listeners, replaced when the files are reloaded.
+#[derive(Clone)]
+struct ReloadableTls {
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+}
+
+impl ReloadableTls {
+    fn load(conf: &TlsConfig) -> io::Result<ReloadableTls> {
+        Ok(ReloadableTls {
+            acceptor: Arc::new(RwLock::new(tls_acceptor(conf)?)),
+        })
+    }
+
+    /// Reads the files again, the previous settings are kept if they are invalid.
+    fn reload(&self, conf: &TlsConfig) -> io::Result<()> {
+        let acceptor = tls_acceptor(conf)?;
+        *self
+            .acceptor
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = acceptor;
+
+        Ok(())
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        self.acceptor
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+fn tls_acceptor(conf: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&conf.cert)?))
+        .collect::<io::Result<Vec<_>>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&conf.key)?))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key found"))?;
+
+    let mut server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(io::Error::other)?;
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Checks that the TLS files can be loaded.
+pub fn check_tls(conf: &TlsConfig) -> io::Result<()> {
+    tls_acceptor(conf).map(|_| ())
+}
+
+/// A tcp listener terminating TLS, it only yields the connections whose handshake succeeded.
+struct TlsListener {
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    fn new(mut listener: TcpListener, tls: ReloadableTls) -> io::Result<TlsListener> {
+        let local_addr = listener.local_addr()?;
+        let (tx, connections) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = tokio::select! {
+                    accepted = Listener::accept(&mut listener) => accepted,
+                    // The server is gone
+                    _ = tx.closed() => break,
+                };
+
+                let acceptor = tls.acceptor();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send((stream, addr)).await;
+                        }
+                        Ok(Err(err)) => debug!("TLS handshake with {addr} failed: {err}"),
+                        Err(_) => debug!("TLS handshake with {addr} timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(TlsListener {
+            connections,
+            local_addr,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accepting task only stops once the listener is dropped
+            None => pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Binds a unix socket, replacing the socket a previous run may have left.
+fn bind_unix(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the path exists and is not a socket",
+            ));
+        }
+        Err(_) => {}
+    }
+
+    UnixListener::bind(path)
+}
+
+/// Serves the router on every configured address until the server is asked to stop.
+pub async fn serve(router: Router) -> Result<()> {
+    let app = router.into_make_service_with_connect_info::<PeerAddr>();
+
+    let tls = match &config().TLS {
+        Some(conf) => {
+            let tls =
+                ReloadableTls::load(conf).map_err(|err| Error::CouldNotInitTls(err.to_string()))?;
+            tokio::spawn(reload_on_hangup(tls.clone(), conf));
+            Some(tls)
+        }
+        None => None,
+    };
+
+    let mut servers = JoinSet::new();
+    for bind in &config().BIND {
+        let bind_error = |err: io::Error| Error::CouldNotBind(format!("{bind}: {err}"));
+
+        match bind {
+            BindAddr::Tcp(addr) => {
+                let listener = TcpListener::bind(addr).await.map_err(bind_error)?;
+
+                match &tls {
+                    Some(tls) => {
+                        let listener =
+                            TlsListener::new(listener, tls.clone()).map_err(bind_error)?;
+                        servers.spawn(
+                            axum::serve(listener, app.clone())
+                                .with_graceful_shutdown(shutdown_signal())
+                                .into_future(),
+                        );
+                    }
+                    None => {
+                        servers.spawn(
+                            axum::serve(listener, app.clone())
+                                .with_graceful_shutdown(shutdown_signal())
+                                .into_future(),
+                        );
+                    }
+                }
+            }
+            BindAddr::Unix(path) => {
+                let listener = bind_unix(path).map_err(bind_error)?;
+                servers.spawn(
+                    axum::serve(listener, app.clone())
+                        .with_graceful_shutdown(shutdown_signal())
+                        .into_future(),
+                );
+            }
+        }
+
+        let scheme = match (bind, &tls) {
+            (BindAddr::Tcp(_), Some(_)) => "https",
+            (BindAddr::Tcp(_), None) => "http",
+            (BindAddr::Unix(_), _) => "http+unix",
+        };
+        info!("{:12} - {bind} ({scheme})", "LISTENING");
+    }
+
+    while let Some(res) = servers.join_next().await {
+        match res {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("a listener stopped: {err}"),
+            Err(err) => error!("a listener panicked: {err}"),
+        }
+    }
+
+    // Do not leave the sockets behind
+    for bind in &config().BIND {
+        if let BindAddr::Unix(path) = bind {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reloads the TLS files whenever the process receives SIGHUP.
+async fn reload_on_hangup(tls: ReloadableTls, conf: &'static TlsConfig) {
+    let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            error!("could not listen to SIGHUP, the TLS files will not be reloaded: {err}");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        match tls.reload(conf) {
+            Ok(()) => info!("reloaded the TLS certificate and key"),
+            Err(err) => error!("could not reload the TLS files, keeping the previous ones: {err}"),
+        }
+    }
+}
+
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+c handler")
+    };
+
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
@@ -0,0 +1,105 @@
+// Prometheus metrics of the server, served on `/metrics` when `METRICS_TOKEN` or `METRICS_BIND`
+// is set.
+//
+// The counters are recorded where things happen, the gauges describing what is stored are read
+// from the database at every scrape. Nothing is recorded when the metrics are disabled.
+
+use std::{
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::{Error, Result, model::Stats};
+
+/// Buckets of the durations, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the Prometheus recorder, must be called once before anything is recorded.
+pub fn install() -> Result<()> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_string()),
+            DURATION_BUCKETS,
+        )
+        .and_then(|builder| builder.install_recorder())
+        .map_err(|err| Error::CouldNotInitMetrics(err.to_string()))?;
+
+    HANDLE
+        .set(handle)
+        .map_err(|_| Error::CouldNotInitMetrics("already installed".to_string()))
+}
+
+/// Renders the metrics in the Prometheus text format, with the current content of the database.
+pub fn render(stats: Stats) -> String {
+    let Some(handle) = HANDLE.get() else {
+        return String::new();
+    };
+
+    gauge!("filecrab_assets").set(stats.assets as f64);
+    gauge!("filecrab_texts").set(stats.texts as f64);
+    gauge!("filecrab_storage_bytes").set(stats.asset_bytes as f64);
+
+    handle.run_upkeep();
+    handle.render()
+}
+
+/// Records a request answered by a route.
+pub fn record_request(method: &str, route: &str, status: u16, latency: Duration) {
+    let labels = [
+        ("method", method.to_string()),
+        ("route", route.to_string()),
+        ("status", status.to_string()),
+    ];
+    counter!("filecrab_http_requests_total", &labels).increment(1);
+    histogram!("filecrab_http_request_duration_seconds", &labels[..2]).record(latency);
+}
+
+pub fn record_uploaded(bytes: u64) {
+    counter!("filecrab_uploaded_bytes_total").increment(bytes);
+}
+
+pub fn record_downloaded(bytes: u64) {
+    counter!("filecrab_downloaded_bytes_total").increment(bytes);
+}
+
+/// Records a request refused because of its key, `reason` is one of missing, invalid, revoked,
+/// expired or scope.
+pub fn record_auth_failure(reason: &'static str) {
+    counter!("filecrab_auth_failures_total", "reason" => reason).increment(1);
+}
+
+/// Records what a cleanup deleted, `kind` is one of asset, text or upload.
+pub fn record_cleaned(kind: &'static str, count: u64) {
+    counter!("filecrab_cleanup_deleted_total", "kind" => kind).increment(count);
+}
+
+/// Records an object a job could not handle, `kind` is one of asset or upload.
+pub fn record_object_failure(job: &'static str, kind: &'static str) {
+    counter!("filecrab_job_object_failures_total", "job" => job, "kind" => kind).increment(1);
+}
+
+/// Records how often a job is scheduled.
+pub fn record_job_interval(job: &'static str, interval: Duration) {
+    gauge!("filecrab_job_interval_seconds", "job" => job).set(interval.as_secs_f64());
+}
+
+/// Records a run of a job, `status` is one of success, partial or failed. The time of the last
+/// successful run is what to alert on.
+pub fn record_job_run(job: &'static str, status: &'static str, duration: Duration) {
+    counter!("filecrab_job_runs_total", "job" => job, "status" => status).increment(1);
+    histogram!("filecrab_job_duration_seconds", "job" => job).record(duration);
+
+    if status == "success" {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        gauge!("filecrab_job_last_success_timestamp_seconds", "job" => job).set(now.as_secs_f64());
+    }
+}
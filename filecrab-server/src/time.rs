@@ -0,0 +1,79 @@
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// Parses a human friendly duration made of a number and a unit, ex. `30s`, `15m`, `2h`, `7d` or
+/// `1w`.
+pub fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().ok()?;
+
+    match unit.trim() {
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Parses an expiration, either a duration relative to `now` or an RFC3339 timestamp.
+pub fn parse_expire(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if let Some(delta) = parse_duration(value) {
+        return now.checked_add_signed(delta);
+    }
+
+    DateTime::parse_from_rfc3339(value.trim())
+        .ok()
+        .map(|date| date.to_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("30s"), Some(TimeDelta::seconds(30)));
+        assert_eq!(parse_duration("15m"), Some(TimeDelta::minutes(15)));
+        assert_eq!(parse_duration(" 2 h "), Some(TimeDelta::hours(2)));
+        assert_eq!(parse_duration("7d"), Some(TimeDelta::days(7)));
+        assert_eq!(parse_duration("1w"), Some(TimeDelta::weeks(1)));
+        assert_eq!(parse_duration("0s"), Some(TimeDelta::zero()));
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        for value in [
+            "",
+            "10",
+            "h",
+            "-5m",
+            "1.5h",
+            "5x",
+            "5H",
+            "9999999999999999w",
+        ] {
+            assert_eq!(parse_duration(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn parse_expire_duration_or_date() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        assert_eq!(parse_expire("1h", now), Some(now + TimeDelta::hours(1)));
+        assert_eq!(
+            parse_expire("2024-02-01T12:00:00+02:00", now),
+            Some(
+                DateTime::parse_from_rfc3339("2024-02-01T10:00:00Z")
+                    .unwrap()
+                    .to_utc()
+            )
+        );
+        assert_eq!(parse_expire("tomorrow", now), None);
+    }
+}
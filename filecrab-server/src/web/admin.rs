@@ -0,0 +1,188 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    jobs::{self, JobInfo},
+    model::{
+        ModelManager,
+        api_key::{ApiKey, Scope},
+        job::{JobRun, ObjectFailure},
+    },
+    time,
+    web::{Error, Result},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateKeyParams {
+    name: String,
+    scopes: Vec<Scope>,
+    /// A duration (ex. 30d) or an RFC3339 date, the key never expires when not set.
+    expire: Option<String>,
+    /// Bytes the key can store, defaults to the configured quota.
+    quota_size: Option<u64>,
+    /// Files the key can store, defaults to the configured quota.
+    quota_files: Option<u64>,
+}
+
+/// A key as shown to the admins, without its hash.
+#[derive(Debug, Serialize)]
+pub struct KeyResponse {
+    id: String,
+    name: String,
+    scopes: Vec<Scope>,
+    expire: Option<DateTime<Utc>>,
+    revoked: bool,
+    created_at: DateTime<Utc>,
+    quota_size: Option<u64>,
+    quota_files: Option<u64>,
+}
+
+impl From<ApiKey> for KeyResponse {
+    fn from(api_key: ApiKey) -> Self {
+        KeyResponse {
+            id: api_key.id,
+            name: api_key.name,
+            scopes: api_key.scopes,
+            expire: api_key.expire,
+            revoked: api_key.revoked,
+            created_at: api_key.created_at,
+            quota_size: api_key.quota_size,
+            quota_files: api_key.quota_files,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateKeyResponse {
+    #[serde(flatten)]
+    info: KeyResponse,
+    /// The secret to send in the `filecrab-key` header, it can not be retrieved later.
+    key: String,
+}
+
+#[debug_handler]
+pub async fn create_key_handler(
+    State(mm): State<ModelManager>,
+    Json(params): Json<CreateKeyParams>,
+) -> Result<(StatusCode, Json<CreateKeyResponse>)> {
+    let name = params.name.trim().to_string();
+    if name.is_empty() {
+        return Err(Error::InvalidApiKey("the name can not be empty"));
+    }
+    if params.scopes.is_empty() {
+        return Err(Error::InvalidApiKey("at least one scope is required"));
+    }
+
+    let mut scopes = Vec::new();
+    for scope in params.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    let now = Utc::now();
+    let expire = match params.expire {
+        Some(value) => Some(
+            time::parse_expire(&value, now)
+                .filter(|expire| *expire > now)
+                .ok_or(Error::InvalidExpire(value))?,
+        ),
+        None => None,
+    };
+
+    let (api_key, key) = ApiKey::create(
+        mm,
+        name,
+        scopes,
+        expire,
+        params.quota_size,
+        params.quota_files,
+    )
+    .await?;
+
+    let res = CreateKeyResponse {
+        info: api_key.into(),
+        key,
+    };
+
+    Ok((StatusCode::CREATED, Json(res)))
+}
+
+#[debug_handler]
+pub async fn list_keys_handler(State(mm): State<ModelManager>) -> Result<Json<Vec<KeyResponse>>> {
+    let keys = ApiKey::list(mm).await?;
+
+    Ok(Json(keys.into_iter().map(KeyResponse::from).collect()))
+}
+
+#[debug_handler]
+pub async fn revoke_key_handler(
+    State(mm): State<ModelManager>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    ApiKey::revoke(mm, &id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Runs listed when no limit is given.
+const DEFAULT_RUNS_LIMIT: u32 = 20;
+
+/// A job with its last run.
+#[derive(Debug, Serialize)]
+pub struct JobResponse {
+    #[serde(flatten)]
+    info: JobInfo,
+    last_run: Option<JobRun>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunsParams {
+    limit: Option<u32>,
+}
+
+#[debug_handler]
+pub async fn list_jobs_handler(State(mm): State<ModelManager>) -> Result<Json<Vec<JobResponse>>> {
+    let mut res = Vec::new();
+    for info in jobs::list() {
+        let last_run = JobRun::list(&mm, info.name, 1).await?.pop();
+        res.push(JobResponse { info, last_run });
+    }
+
+    Ok(Json(res))
+}
+
+/// Starts a run of the job, it goes on in the background.
+#[debug_handler]
+pub async fn run_job_handler(Path(name): Path<String>) -> Result<StatusCode> {
+    jobs::trigger(&name)?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[debug_handler]
+pub async fn list_runs_handler(
+    State(mm): State<ModelManager>,
+    Path(name): Path<String>,
+    Query(params): Query<RunsParams>,
+) -> Result<Json<Vec<JobRun>>> {
+    let info = jobs::info(&name)?;
+    let runs = JobRun::list(&mm, info.name, params.limit.unwrap_or(DEFAULT_RUNS_LIMIT)).await?;
+
+    Ok(Json(runs))
+}
+
+/// Lists the objects the jobs could not handle yet.
+#[debug_handler]
+pub async fn list_failures_handler(
+    State(mm): State<ModelManager>,
+) -> Result<Json<Vec<ObjectFailure>>> {
+    let failures = ObjectFailure::list(&mm).await?;
+
+    Ok(Json(failures))
+}
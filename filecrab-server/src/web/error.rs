@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
-use axum::{extract::multipart::MultipartError, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::multipart::MultipartError,
+    http::{HeaderValue, StatusCode, header},
+    response::IntoResponse,
+};
 use thiserror::Error;
 use tracing::error;
 
-use crate::model::ModelManagerError;
+use crate::{jobs::JobError, model::ModelManagerError};
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -13,6 +17,54 @@ pub enum Error {
     #[error("the filename is not set in the request")]
     MissingFileName,
 
+    #[error("invalid expire value `{0}`, expected a duration (ex. 2h, 7d) or an RFC3339 date")]
+    InvalidExpire(String),
+
+    #[error("invalid max_downloads value `{0}`, expected a number greater than 0")]
+    InvalidMaxDownloads(String),
+
+    #[error("the deletion token is not set in the request")]
+    MissingDeleteToken,
+
+    #[error("invalid deletion token")]
+    InvalidDeleteToken,
+
+    #[error("invalid api key: {0}")]
+    InvalidApiKey(&'static str),
+
+    #[error("invalid link signature")]
+    InvalidSignature,
+
+    #[error("the link has expired")]
+    LinkExpired,
+
+    #[error("too many failed requests, retry in {0} seconds")]
+    TooManyFailures(u64),
+
+    #[error("unsupported tus version, expected 1.0.0")]
+    TusVersionMismatch,
+
+    #[error("missing or invalid {0} header")]
+    InvalidTusHeader(&'static str),
+
+    #[error("chunks must be sent as application/offset+octet-stream")]
+    InvalidTusContentType,
+
+    #[error("the upload is larger than the maximum file size")]
+    UploadTooLarge,
+
+    #[error("chunks must be a multiple of {0} bytes, only the last one can be shorter")]
+    InvalidChunkSize(usize),
+
+    #[error("the offset does not match the upload")]
+    UploadOffsetMismatch,
+
+    #[error("the upload is already receiving a chunk")]
+    UploadLocked,
+
+    #[error(transparent)]
+    Job(#[from] JobError),
+
     #[error(transparent)]
     ModelManager(#[from] ModelManagerError),
 
@@ -28,21 +80,88 @@ impl IntoResponse for Error {
         error!("-->> {:12} - {self:?}", "INTO_RES");
 
         match self {
-            Self::MissingFileName => {
+            Self::MissingFileName
+            | Self::InvalidExpire(_)
+            | Self::InvalidMaxDownloads(_)
+            | Self::MissingDeleteToken
+            | Self::InvalidApiKey(_) => {
                 let mut response = (StatusCode::BAD_REQUEST, self.to_string()).into_response();
 
                 response.extensions_mut().insert(Arc::new(self));
                 response
             }
+            Self::InvalidDeleteToken | Self::InvalidSignature => {
+                let mut response = (StatusCode::FORBIDDEN, self.to_string()).into_response();
+
+                response.extensions_mut().insert(Arc::new(self));
+                response
+            }
+            Self::LinkExpired => {
+                let mut response = (StatusCode::GONE, self.to_string()).into_response();
+
+                response.extensions_mut().insert(Arc::new(self));
+                response
+            }
+            Self::TooManyFailures(retry_after) => {
+                let mut response =
+                    (StatusCode::TOO_MANY_REQUESTS, self.to_string()).into_response();
+
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                response.extensions_mut().insert(Arc::new(self));
+                response
+            }
+            Self::Job(ref job_err) => {
+                let code = match job_err {
+                    JobError::NotFound(_) => StatusCode::NOT_FOUND,
+                    JobError::AlreadyRunning(_) => StatusCode::CONFLICT,
+                };
+
+                let mut response = (code, self.to_string()).into_response();
+                response.extensions_mut().insert(Arc::new(self));
+                response
+            }
+            Self::TusVersionMismatch
+            | Self::InvalidTusHeader(_)
+            | Self::InvalidTusContentType
+            | Self::InvalidChunkSize(_)
+            | Self::UploadTooLarge
+            | Self::UploadOffsetMismatch
+            | Self::UploadLocked => {
+                let code = match self {
+                    Self::TusVersionMismatch => StatusCode::PRECONDITION_FAILED,
+                    Self::InvalidTusContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    Self::UploadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+                    Self::UploadOffsetMismatch => StatusCode::CONFLICT,
+                    Self::UploadLocked => StatusCode::LOCKED,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+
+                let mut response = (code, self.to_string()).into_response();
+                let headers = response.headers_mut();
+                headers.insert("tus-resumable", HeaderValue::from_static("1.0.0"));
+                headers.insert("tus-version", HeaderValue::from_static("1.0.0"));
+                response.extensions_mut().insert(Arc::new(self));
+                response
+            }
             Self::ModelManager(ref mm_err) => {
                 let code = match mm_err {
                     ModelManagerError::CreateAsset(_) => StatusCode::CONFLICT,
                     ModelManagerError::SearchAsset(_) => StatusCode::BAD_REQUEST,
                     ModelManagerError::DeleteAsset(_) => StatusCode::BAD_REQUEST,
                     ModelManagerError::AssetNotFound => StatusCode::NOT_FOUND,
+                    ModelManagerError::MemoIdConflict => StatusCode::CONFLICT,
                     ModelManagerError::CreateText(_) => StatusCode::CONFLICT,
                     ModelManagerError::SearchText(_) => StatusCode::BAD_REQUEST,
                     ModelManagerError::TextNotFound => StatusCode::NOT_FOUND,
+                    ModelManagerError::ObjectNotFound => StatusCode::NOT_FOUND,
+                    ModelManagerError::UploadNotFound => StatusCode::NOT_FOUND,
+                    ModelManagerError::UploadConflict => StatusCode::CONFLICT,
+                    ModelManagerError::ApiKeyNotFound => StatusCode::NOT_FOUND,
+                    ModelManagerError::LinkAlreadyUsed => StatusCode::GONE,
+                    ModelManagerError::QuotaExhausted => StatusCode::INSUFFICIENT_STORAGE,
+                    ModelManagerError::QuotaExceeded { .. } => StatusCode::PAYLOAD_TOO_LARGE,
                     ModelManagerError::S3Error(e) => {
                         if let s3::error::S3Error::HttpFailWithBody(status_code, _body) = e {
                             //Try and return the status code form the inner S3 error, otherwise
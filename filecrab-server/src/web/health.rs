@@ -0,0 +1,107 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use axum::{Json, debug_handler, extract::State, http::StatusCode};
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::{
+    config::{DbConfig, StorageConfig, config},
+    model::{ModelManager, Result},
+};
+
+/// Time given to each dependency to answer.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    status: &'static str,
+}
+
+/// State of a dependency of the server.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    status: Status,
+    backend: &'static str,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+    status: &'static str,
+    database: Check,
+    storage: Check,
+}
+
+/// The process is alive and answers.
+#[debug_handler]
+pub async fn healthz_handler() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok" })
+}
+
+/// The server can serve requests, the database and the storage both answer.
+#[debug_handler]
+pub async fn readyz_handler(State(mm): State<ModelManager>) -> (StatusCode, Json<ReadyResponse>) {
+    let database_backend = match config().DB {
+        DbConfig::Surreal(_) => "surreal",
+        DbConfig::Sqlite { .. } => "sqlite",
+    };
+    let storage_backend = match config().STORAGE {
+        StorageConfig::S3 { .. } => "s3",
+        StorageConfig::Fs { .. } => "fs",
+    };
+
+    let (database, storage) = tokio::join!(
+        check_dependency(database_backend, mm.check_store()),
+        check_dependency(storage_backend, mm.check_storage()),
+    );
+
+    let ready = matches!(database.status, Status::Up) && matches!(storage.status, Status::Up);
+    let (code, status) = if ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    (
+        code,
+        Json(ReadyResponse {
+            status,
+            database,
+            storage,
+        }),
+    )
+}
+
+/// Runs the check of a dependency, a dependency not answering in time is down.
+async fn check_dependency(backend: &'static str, ping: impl Future<Output = Result<()>>) -> Check {
+    let start = Instant::now();
+
+    let error = match timeout(CHECK_TIMEOUT, ping).await {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(_) => Some(format!("no answer after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+
+    Check {
+        status: if error.is_none() {
+            Status::Up
+        } else {
+            Status::Down
+        },
+        backend,
+        latency_ms: start.elapsed().as_millis(),
+        error,
+    }
+}
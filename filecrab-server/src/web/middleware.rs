@@ -1,36 +1,138 @@
+use std::time::Instant;
+
 use axum::{
-    extract::Request,
-    http::{HeaderMap, StatusCode},
+    extract::{MatchedPath, Request, State},
+    http::{HeaderMap, StatusCode, header},
     middleware::Next,
     response::Response,
 };
+use chrono::Utc;
+use tracing::{error, warn};
 
-use crate::config::config;
+use crate::{
+    config::config,
+    model::{
+        ModelManager, ModelManagerError,
+        api_key::{ApiKey, Scope},
+        token,
+    },
+    telemetry,
+};
 
+/// Checks the `filecrab-key` header and adds the matching `ApiKey` to the request extensions.
 pub async fn api_key_mw(
+    State(mm): State<ModelManager>,
     // run the headers map extractor
     headers: HeaderMap,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    let key = headers.get("filecrab-key");
-    match key {
-        Some(token) => {
-            if token != &config().API_KEY {
-                tracing::warn!(
-                    "someone tried to request the api with an invalid key {:?}",
-                    token
-                );
-                return Err(StatusCode::UNAUTHORIZED);
-            }
-
-            //If the token matches we let through the request
-            let response = next.run(request).await;
-            Ok(response)
+    let Some(secret) = headers
+        .get("filecrab-key")
+        .and_then(|key| key.to_str().ok())
+    else {
+        warn!("someone tried to request the api without a key");
+        telemetry::record_auth_failure("missing");
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let api_key = match ApiKey::read_by_secret(mm, secret).await {
+        Ok(api_key) => api_key,
+        Err(ModelManagerError::ApiKeyNotFound) => {
+            warn!("someone tried to request the api with an invalid key");
+            telemetry::record_auth_failure("invalid");
+            return Err(StatusCode::UNAUTHORIZED);
         }
-        _ => {
-            tracing::warn!("someone tried to request the api without a key");
-            Err(StatusCode::UNAUTHORIZED)
+        Err(err) => {
+            error!("could not read the api key: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    if api_key.revoked {
+        warn!(
+            "someone tried to request the api with the revoked key {}",
+            api_key.id
+        );
+        telemetry::record_auth_failure("revoked");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if api_key.is_expired(Utc::now()) {
+        warn!(
+            "someone tried to request the api with the expired key {}",
+            api_key.id
+        );
+        telemetry::record_auth_failure("expired");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    //If the key is valid we let through the request
+    request.extensions_mut().insert(api_key);
+    Ok(next.run(request).await)
+}
+
+/// Only lets through requests whose key has the given scope, must run after `api_key_mw`.
+pub async fn scope_mw(
+    State(scope): State<Scope>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let Some(api_key) = request.extensions().get::<ApiKey>() else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    if !api_key.has_scope(scope) {
+        warn!(
+            "the key {} tried to request the api without the {} scope",
+            api_key.id,
+            scope.as_str()
+        );
+        telemetry::record_auth_failure("scope");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Records the count and latency of the requests of every matched route.
+pub async fn metrics_mw(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+
+    let response = next.run(request).await;
+
+    telemetry::record_request(
+        method.as_str(),
+        &route,
+        response.status().as_u16(),
+        start.elapsed(),
+    );
+
+    response
+}
+
+/// Checks the `Authorization: Bearer` header against `METRICS_TOKEN`, when set.
+pub async fn metrics_token_mw(
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if let Some(expected) = &config().METRICS.token {
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        if given.is_none_or(|given| token::hash(given) != token::hash(expected)) {
+            warn!("someone tried to scrape the metrics without a valid token");
+            return Err(StatusCode::UNAUTHORIZED);
         }
     }
+
+    Ok(next.run(request).await)
 }
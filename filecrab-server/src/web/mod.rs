@@ -1,5 +1,10 @@
+mod admin;
 mod error;
+mod health;
 pub mod middleware;
+mod range;
+mod rate_limit;
 pub mod routes;
+pub mod tus;
 
 pub use self::error::{Error, Result};
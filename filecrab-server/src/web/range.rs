@@ -0,0 +1,159 @@
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, Utc};
+
+use crate::model::ObjectMeta;
+
+/// Format of the dates in HTTP headers, always in GMT.
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// What a `Range` header asks for, once checked against the size of the object.
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    /// The whole object, also used for ranges we do not support such as multiple ranges.
+    Full,
+    /// A single inclusive byte range within the object.
+    Partial(RangeInclusive<u64>),
+    /// The range starts after the end of the object.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header against the size of the object. Only single byte ranges are honoured,
+/// anything else is answered with the whole object as allowed by RFC 9110.
+pub fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        // Suffix range, the last bytes of the object
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(suffix) => size.saturating_sub(suffix)..=size.saturating_sub(1),
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => start..=size.saturating_sub(1),
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => start..=end.min(size.saturating_sub(1)),
+            _ => return RangeRequest::Full,
+        },
+    };
+
+    if size == 0 || *range.start() >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    RangeRequest::Partial(range)
+}
+
+/// Checks an `If-Range` header, the range only applies if the object has not changed since.
+pub fn if_range_matches(value: &str, meta: &ObjectMeta) -> bool {
+    let value = value.trim();
+
+    // Entity tag, weak tags never match
+    if value.starts_with('"') {
+        return value == meta.etag;
+    }
+    if value.starts_with("W/") {
+        return false;
+    }
+
+    // Date, it only matches the exact last modification date
+    match (DateTime::parse_from_rfc2822(value), meta.last_modified) {
+        (Ok(date), Some(last_modified)) => date.timestamp() == last_modified.timestamp(),
+        _ => false,
+    }
+}
+
+/// Formats a date for HTTP headers.
+pub fn http_date(date: DateTime<Utc>) -> String {
+    date.format(HTTP_DATE_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_bounded() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Partial(0..=99)
+        );
+        assert_eq!(
+            parse_range(" bytes= 10 - 19 ", 1000),
+            RangeRequest::Partial(10..=19)
+        );
+        // The end is capped to the last byte
+        assert_eq!(
+            parse_range("bytes=900-2000", 1000),
+            RangeRequest::Partial(900..=999)
+        );
+    }
+
+    #[test]
+    fn parse_range_open_ended() {
+        assert_eq!(
+            parse_range("bytes=100-", 1000),
+            RangeRequest::Partial(100..=999)
+        );
+        assert_eq!(
+            parse_range("bytes=999-", 1000),
+            RangeRequest::Partial(999..=999)
+        );
+    }
+
+    #[test]
+    fn parse_range_suffix() {
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Partial(900..=999)
+        );
+        // A suffix longer than the object is the whole object
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(0..=999)
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_past_end() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range("bytes=1000-1999", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_malformed() {
+        for value in [
+            "",
+            "bytes",
+            "items=0-99",
+            "bytes=0",
+            "bytes=-",
+            "bytes=a-b",
+            "bytes=99-0",
+            "bytes=-1-2",
+            "bytes=0-9,20-29",
+        ] {
+            assert_eq!(parse_range(value, 1000), RangeRequest::Full, "{value}");
+        }
+    }
+}
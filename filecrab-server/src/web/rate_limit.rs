@@ -0,0 +1,329 @@
+// Brute force protection, memorable ids and api keys can be guessed by trying many of them.
+//
+// Every client has a bucket of failures (unknown ids, invalid keys) refilled over time. A client
+// failing once its bucket is empty is locked out and gets `429 Too Many Requests` until the
+// lockout ends. Successful requests are never limited.
+//
+// Only the routes looking up a memorable id and the check of the api keys count failures, a
+// missing upload or a wrong deletion token cannot be used to guess anything.
+//
+// The unix sockets can only be reached from the machine, by a reverse proxy. Their clients are
+// told apart by its `X-Forwarded-For` header, and are not limited without one.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tracing::warn;
+
+use crate::{
+    config::{RateLimitConfig, config},
+    server::PeerAddr,
+    web::{Error, Result},
+};
+
+/// Number of clients tracked before the ones back to a full bucket are forgotten.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+static LIMITER: LazyLock<Mutex<HashMap<IpAddr, Bucket>>> = LazyLock::new(Default::default);
+
+/// Failures left to a client.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(conf: &RateLimitConfig, now: Instant) -> Bucket {
+        Bucket {
+            tokens: conf.burst as f64,
+            updated: now,
+            locked_until: None,
+        }
+    }
+
+    /// Gives back the failures earned since the last update.
+    fn refill(&mut self, conf: &RateLimitConfig, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * conf.per_minute as f64 / 60.0).min(conf.burst as f64);
+        self.updated = now;
+    }
+
+    /// Time left before the client can try again, if locked out.
+    fn lockout(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+}
+
+/// What the routes guarded by the limiter count as failures.
+#[derive(Clone, Copy, Debug)]
+pub enum Guarded {
+    /// Routes looking up a file or text by its memorable id, unknown ids are failures.
+    Lookups,
+    /// Routes behind `api_key_mw`, only the invalid keys are failures.
+    ApiKeys,
+}
+
+impl Guarded {
+    /// Whether a response means the client looked for something which does not exist.
+    fn is_failure(self, status: StatusCode) -> bool {
+        match self {
+            Guarded::Lookups => status == StatusCode::NOT_FOUND,
+            Guarded::ApiKeys => status == StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// Finds the address of the client, `peer` being `None` on the unix sockets. Behind trusted
+/// proxies, and the unix sockets, it is the last address of the `X-Forwarded-For` header which is
+/// not a trusted proxy itself. `None` when the client of a unix socket cannot be told apart.
+fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, conf: &RateLimitConfig) -> Option<IpAddr> {
+    let trusted = |ip: IpAddr| conf.trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+
+    if let Some(peer) = peer.filter(|peer| !trusted(*peer)) {
+        return Some(peer.to_canonical());
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect();
+
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !trusted(**ip))
+        .or(forwarded.first())
+        .copied()
+        .or(peer)
+        .map(|ip| ip.to_canonical())
+}
+
+/// Returns the address the failures of a client are counted under. An IPv6 client usually has a
+/// whole /64 to pick addresses from, it is counted as one.
+fn bucket_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & (u128::MAX << 64))),
+    }
+}
+
+/// Refuses locked out clients and counts the failed requests of the others.
+pub async fn rate_limit_mw(
+    State(guarded): State<Guarded>,
+    ConnectInfo(PeerAddr(peer)): ConnectInfo<PeerAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    let conf = &config().RATE_LIMIT;
+    if conf.burst == 0 {
+        return Ok(next.run(request).await);
+    }
+
+    let Some(ip) = client_ip(peer.map(|peer| peer.ip()), request.headers(), conf) else {
+        return Ok(next.run(request).await);
+    };
+    let ip = bucket_key(ip);
+
+    let locked = LIMITER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&ip)
+        .and_then(|bucket| bucket.lockout(Instant::now()));
+    if let Some(retry_after) = locked {
+        return Err(Error::TooManyFailures(
+            retry_after.as_secs_f64().ceil() as u64
+        ));
+    }
+
+    let response = next.run(request).await;
+
+    if guarded.is_failure(response.status()) {
+        record_failure(ip, conf);
+    }
+
+    Ok(response)
+}
+
+/// Takes a failure from the bucket of the client, locking it out when there is none left.
+fn record_failure(ip: IpAddr, conf: &RateLimitConfig) {
+    let now = Instant::now();
+    let mut buckets = LIMITER.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if buckets.len() >= PRUNE_THRESHOLD {
+        buckets.retain(|_, bucket| {
+            bucket.refill(conf, now);
+            bucket.lockout(now).is_some() || bucket.tokens < conf.burst as f64
+        });
+    }
+
+    let bucket = buckets.entry(ip).or_insert_with(|| Bucket::new(conf, now));
+    bucket.refill(conf, now);
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+    } else {
+        warn!("locking out {ip} after too many failed requests");
+        bucket.locked_until = Some(now + conf.lockout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::config::IpRange;
+
+    fn trusting(trusted_proxies: &[&str]) -> RateLimitConfig {
+        RateLimitConfig {
+            burst: 10,
+            per_minute: 10,
+            lockout: Duration::from_secs(60),
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|range| IpRange::parse(range).unwrap())
+                .collect(),
+        }
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn guarded_failures() {
+        assert!(Guarded::Lookups.is_failure(StatusCode::NOT_FOUND));
+        assert!(!Guarded::Lookups.is_failure(StatusCode::OK));
+        assert!(Guarded::ApiKeys.is_failure(StatusCode::UNAUTHORIZED));
+
+        // Missing uploads or assets behind a valid key are not guesses
+        assert!(!Guarded::ApiKeys.is_failure(StatusCode::NOT_FOUND));
+        assert!(!Guarded::ApiKeys.is_failure(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn client_ip_untrusted_peer() {
+        // The header of a peer which is not a trusted proxy is ignored
+        let conf = trusting(&["10.0.0.0/8"]);
+        let headers = forwarded_for(&["1.2.3.4"]);
+
+        assert_eq!(
+            client_ip(Some(ip("5.6.7.8")), &headers, &conf),
+            Some(ip("5.6.7.8"))
+        );
+
+        // Without trusted proxies the header is never read
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &trusting(&[])),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn client_ip_trusted_peer() {
+        let conf = trusting(&["10.0.0.0/8"]);
+
+        let headers = forwarded_for(&["1.2.3.4"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &conf),
+            Some(ip("1.2.3.4"))
+        );
+
+        // Without a usable header, the proxy is the client
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &HeaderMap::new(), &conf),
+            Some(ip("10.0.0.1"))
+        );
+        let headers = forwarded_for(&["unknown"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &conf),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn client_ip_multiple_hops() {
+        let conf = trusting(&["10.0.0.0/8", "192.168.1.1"]);
+
+        // The last address which is not a trusted proxy, the first ones can be forged
+        let headers = forwarded_for(&["9.9.9.9, 1.2.3.4, 192.168.1.1, 10.0.0.2"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &conf),
+            Some(ip("1.2.3.4"))
+        );
+
+        // Several headers are read in order
+        let headers = forwarded_for(&["9.9.9.9", "1.2.3.4, 10.0.0.2"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &conf),
+            Some(ip("1.2.3.4"))
+        );
+
+        // When every hop is trusted, the first one is the client
+        let headers = forwarded_for(&["10.0.0.3, 10.0.0.2"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &conf),
+            Some(ip("10.0.0.3"))
+        );
+    }
+
+    #[test]
+    fn client_ip_unix_socket() {
+        // The proxy in front of the socket tells its clients apart
+        let conf = trusting(&["10.0.0.0/8"]);
+        let headers = forwarded_for(&["9.9.9.9, 1.2.3.4, 10.0.0.2"]);
+        assert_eq!(client_ip(None, &headers, &conf), Some(ip("1.2.3.4")));
+
+        // Without it, the clients cannot be told apart
+        assert_eq!(client_ip(None, &HeaderMap::new(), &conf), None);
+        let headers = forwarded_for(&["unknown"]);
+        assert_eq!(client_ip(None, &headers, &conf), None);
+    }
+
+    #[test]
+    fn bucket_key_groups_ipv6_networks() {
+        assert_eq!(bucket_key(ip("1.2.3.4")), ip("1.2.3.4"));
+        assert_eq!(
+            bucket_key(ip("2001:db8:1:2:aaaa:bbbb:cccc:dddd")),
+            ip("2001:db8:1:2::")
+        );
+        assert_eq!(bucket_key(ip("2001:db8:1:2::1")), ip("2001:db8:1:2::"));
+        assert_ne!(bucket_key(ip("2001:db8:1:3::1")), ip("2001:db8:1:2::"));
+    }
+
+    #[test]
+    fn client_ip_mapped_ipv4() {
+        let conf = trusting(&["10.0.0.0/8"]);
+        let headers = forwarded_for(&["::ffff:1.2.3.4"]);
+
+        assert_eq!(
+            client_ip(Some(ip("::ffff:10.0.0.1")), &headers, &conf),
+            Some(ip("1.2.3.4"))
+        );
+    }
+}
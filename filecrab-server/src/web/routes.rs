@@ -2,49 +2,140 @@ use axum::{
     Json, Router,
     body::Body,
     debug_handler,
-    extract::{DefaultBodyLimit, Multipart, Query, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Extension, Multipart, Query, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::{from_fn, from_fn_with_state},
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, head, post},
 };
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use rand::distr::{Alphanumeric, SampleString};
 use serde::{Deserialize, Serialize};
+use std::mem;
 use tower_http::limit::RequestBodyLimitLayer;
+use tracing::error;
 
 use crate::{
     config::config,
     model::{
-        ModelManager,
-        asset::{Asset, AssetToCreate},
+        ModelManager, ModelManagerError,
+        api_key::{ApiKey, Scope},
+        asset::{Asset, AssetToCreate, PendingAsset},
+        link::SignedLink,
         text::{Text, TextToCreate},
+        token,
+    },
+    telemetry, time,
+    web::{
+        Error, Result, admin, health,
+        middleware::{api_key_mw, metrics_mw, metrics_token_mw, scope_mw},
+        range::{self, RangeRequest},
+        rate_limit::{Guarded, rate_limit_mw},
+        tus,
     },
-    web::{Error, Result, middleware::api_key_mw},
 };
 
 pub fn routes(mm: ModelManager) -> Router {
     Router::new()
-        .route("/api/upload", post(upload_handler))
-        .route("/api/paste", post(paste_handler))
-        .route("/api/copy", get(copy_handler))
+        .merge(
+            Router::new()
+                .route("/api/upload", post(upload_handler))
+                .route("/api/link", post(link_handler))
+                .route("/api/tus", post(tus::create_handler))
+                .route(
+                    "/api/tus/{id}",
+                    head(tus::head_handler)
+                        .patch(tus::patch_handler)
+                        .delete(tus::delete_handler),
+                )
+                .route_layer(from_fn_with_state(Scope::Upload, scope_mw)),
+        )
+        .merge(
+            Router::new()
+                .route("/api/paste", post(paste_handler))
+                .route_layer(from_fn_with_state(Scope::Paste, scope_mw)),
+        )
+        .merge(
+            Router::new()
+                .route("/api/copy", get(copy_handler))
+                .route_layer(from_fn_with_state(Scope::Copy, scope_mw))
+                .route_layer(from_fn_with_state(Guarded::Lookups, rate_limit_mw)),
+        )
+        .merge(
+            Router::new()
+                .route(
+                    "/api/admin/keys",
+                    post(admin::create_key_handler).get(admin::list_keys_handler),
+                )
+                .route("/api/admin/keys/{id}", delete(admin::revoke_key_handler))
+                .route("/api/admin/jobs", get(admin::list_jobs_handler))
+                .route(
+                    "/api/admin/jobs/failures",
+                    get(admin::list_failures_handler),
+                )
+                .route("/api/admin/jobs/{name}/run", post(admin::run_job_handler))
+                .route("/api/admin/jobs/{name}/runs", get(admin::list_runs_handler))
+                .route_layer(from_fn_with_state(Scope::Admin, scope_mw)),
+        )
+        // Deleting only needs a valid key, the deletion token proves the ownership
+        .route("/api/asset", delete(delete_asset_handler))
+        .route("/api/text", delete(delete_text_handler))
+        .route("/api/usage", get(usage_handler))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(
-            config().MAXIMUM_FILE_SIZE * 1024 * 1024, /* in mb */
+            config().MAXIMUM_FILE_SIZE as usize,
         ))
-        .route_layer(axum::middleware::from_fn(api_key_mw))
-        // This route is specifically here after the route_layer so that the middleware is not
-        // applied to it, downloading endpoint is open.
-        .route("/api/download", get(download_handler))
+        .route_layer(from_fn_with_state(mm.clone(), api_key_mw))
+        .route_layer(from_fn_with_state(Guarded::ApiKeys, rate_limit_mw))
+        // These routes are specifically here after the route_layer so that the middleware is not
+        // applied to them, downloading endpoints are open.
+        .merge(
+            Router::new()
+                .route("/api/download", get(download_handler))
+                .route("/api/asset/info", get(asset_info_handler))
+                .route_layer(from_fn_with_state(Guarded::Lookups, rate_limit_mw)),
+        )
+        // Probes of the orchestrators and reverse proxies
+        .route("/healthz", get(health::healthz_handler))
+        .route("/readyz", get(health::readyz_handler))
+        .route_layer(from_fn(metrics_mw))
+        .with_state(mm)
+}
+
+/// The Prometheus metrics, only guarded by `METRICS_TOKEN` so they can be served on their own
+/// listener.
+pub fn metrics_routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route_layer(from_fn(metrics_token_mw))
         .with_state(mm)
 }
 
+#[debug_handler]
+async fn metrics_handler(State(mm): State<ModelManager>) -> Result<Response> {
+    let stats = mm.store().stats().await?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(telemetry::render(stats)))
+        .map_err(Error::Http)
+}
+
+/// Header carrying the deletion token, kept out of the query so it never shows in the logs.
+pub const DELETE_TOKEN_HEADER: &str = "filecrab-delete-token";
+
 #[derive(Serialize)]
 struct CreateResponse {
     pub id: String,
+    /// Secret allowing the owner to delete what has been created.
+    pub delete_token: String,
 }
 
 #[debug_handler]
 async fn upload_handler(
     State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
     mut multipart: Multipart,
 ) -> Result<Json<CreateResponse>> {
     //First we generate an id which will be used for the file and the db
@@ -56,82 +147,381 @@ async fn upload_handler(
         encrypted: false,
         expire: None,
         memo_id: None,
+        max_downloads: None,
+        delete_token: None,
+        created_by: Some(api_key.id.clone()),
+        size: None,
+        created_at: None,
+        signed_only: false,
+        pending: false,
     };
 
+    // Refuse early when nothing more can be stored
+    api_key.check_quota(mm.clone(), 0).await?;
+
     //Parse multipart
-    let mut has_file = false;
+    let mut pending = None;
     while let Some(field) = multipart.next_field().await? {
         let name = field.name().unwrap_or_default().to_string();
 
         match name.as_str() {
-            "file" => {
-                has_file = true;
+            // Only the first file is stored
+            "file" if pending.is_none() => {
                 asset_to_create.file_name =
                     field.file_name().ok_or(Error::MissingFileName)?.to_string();
 
+                // Reserve the asset first, its memo id is settled before any byte is stored and
+                // the file is deleted if the request does not go through
+                pending =
+                    Some(PendingAsset::reserve(mm.clone(), &token, asset_to_create.clone()).await?);
+
                 //Stream and upload the file
-                mm.upload(&token, field).await?;
+                let size = mm.upload(&token, field).await?;
+                telemetry::record_uploaded(size);
+                asset_to_create.size = Some(size);
             }
             "encrypted" => {
                 let encrypted_bytes = field.bytes().await?.to_vec();
                 let encrypted_string = String::from_utf8_lossy(&encrypted_bytes).to_string();
                 asset_to_create.encrypted = encrypted_string.to_lowercase().eq("true");
             }
+            "expire" => {
+                let value = field.text().await?;
+                let expire = parse_expire(&value).ok_or(Error::InvalidExpire(value))?;
+
+                asset_to_create.expire = Some(expire);
+            }
+            "signed_only" => {
+                let value = field.text().await?;
+                asset_to_create.signed_only = value.eq_ignore_ascii_case("true");
+            }
+            "max_downloads" => {
+                let value = field.text().await?;
+                let max_downloads =
+                    parse_max_downloads(&value).ok_or(Error::InvalidMaxDownloads(value))?;
+
+                asset_to_create.max_downloads = Some(max_downloads);
+            }
             _ => {}
         }
     }
 
     //If we got a file, time to upload buddy
-    let mut resp = CreateResponse { id: String::new() };
+    let mut resp = CreateResponse {
+        id: String::new(),
+        delete_token: String::new(),
+    };
+
+    if let Some(pending) = pending {
+        // The size is only known once the file is stored, the upload is rolled back if it does
+        // not fit
+        let size = asset_to_create.size.unwrap_or_default();
+        api_key.check_quota(mm.clone(), size).await?;
 
-    if has_file {
-        //First we store the reference
-        let asset = Asset::create(mm.clone(), &token, asset_to_create).await?;
+        // Only the hash of the deletion token is stored
+        let delete_token = token::generate();
+        asset_to_create.delete_token = Some(token::hash(&delete_token));
 
-        //copy the id to the the response
-        resp.id = asset.memo_id
+        // Make the asset available
+        let asset = pending.commit(asset_to_create).await?;
+
+        //copy the id and the deletion token to the the response
+        resp.id = asset.memo_id;
+        resp.delete_token = delete_token;
     }
 
     Ok(Json(resp))
 }
 
+/// Parses the requested expiration of an asset, it must be in the future and is capped to the
+/// configured maximum.
+pub(super) fn parse_expire(value: &str) -> Option<DateTime<Utc>> {
+    let now = Utc::now();
+    let expire = time::parse_expire(value, now).filter(|expire| *expire > now)?;
+
+    // Never keep an asset longer than the configured maximum
+    Some(expire.min(now + config().MAXIMUM_EXPIRE_TIME))
+}
+
+/// Parses the download limit of an asset, it must be greater than 0.
+pub(super) fn parse_max_downloads(value: &str) -> Option<u32> {
+    value.trim().parse::<u32>().ok().filter(|max| *max > 0)
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkParams {
+    memo_id: String,
+    /// A duration (ex. 1h) or an RFC3339 date, capped to the maximum expire time.
+    expire: String,
+    #[serde(default)]
+    single_use: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkResponse {
+    /// Path and query of the signed download link.
+    url: String,
+    expire: DateTime<Utc>,
+}
+
+#[debug_handler]
+async fn link_handler(
+    State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
+    Json(params): Json<LinkParams>,
+) -> Result<Json<LinkResponse>> {
+    let asset = Asset::read_by_memo_id(mm.clone(), &params.memo_id).await?;
+
+    // Only the key which uploaded the file, or an admin, can share it. Others are not told it
+    // exists.
+    let owned = asset.created_by.as_deref() == Some(api_key.id.as_str());
+    if !owned && !api_key.has_scope(Scope::Admin) {
+        return Err(ModelManagerError::AssetNotFound.into());
+    }
+    let expire = parse_expire(&params.expire).ok_or(Error::InvalidExpire(params.expire))?;
+
+    let link = SignedLink::create(mm, asset.memo_id, expire, params.single_use).await?;
+
+    Ok(Json(LinkResponse {
+        url: link.path(),
+        expire,
+    }))
+}
+
 #[derive(Debug, Deserialize)]
 struct DownloadParams {
     file: Option<String>,
+    // Signed links
+    expires: Option<i64>,
+    nonce: Option<String>,
+    sig: Option<String>,
+}
+
+/// Reads the asset requested for a download, along with the signed link of the request if any.
+/// The signed link is checked before anything is read, it is not consumed.
+async fn read_requested_asset(
+    mm: ModelManager,
+    params: DownloadParams,
+) -> Result<(Asset, Option<SignedLink>)> {
+    let memo_id = params.file.unwrap_or_default();
+
+    let link = match params.sig {
+        Some(sig) => {
+            let link = SignedLink {
+                memo_id: memo_id.clone(),
+                expires: params.expires.unwrap_or_default(),
+                nonce: params.nonce,
+            };
+            if !link.verify(&sig) {
+                return Err(Error::InvalidSignature);
+            }
+            if link.is_expired(Utc::now()) {
+                return Err(Error::LinkExpired);
+            }
+            Some(link)
+        }
+        None => None,
+    };
+
+    // Read the asset from the database
+    let asset = Asset::read_by_memo_id(mm, &memo_id).await?;
+
+    // Without a signed link such an asset does not exist
+    if asset.signed_only && link.is_none() {
+        return Err(ModelManagerError::AssetNotFound.into());
+    }
+
+    Ok((asset, link))
+}
+
+#[derive(Debug, Serialize)]
+struct AssetInfoResponse {
+    file_name: String,
+    /// Bytes stored, the encrypted size for an encrypted file.
+    size: u64,
+    encrypted: bool,
+    /// `null` for the files uploaded before it was recorded.
+    created_at: Option<DateTime<Utc>>,
+    expire: Option<DateTime<Utc>>,
+    /// `null` when unlimited.
+    remaining_downloads: Option<u32>,
+}
+
+/// Describes a file without downloading it, nothing is counted and single use links stay
+/// usable.
+#[debug_handler]
+async fn asset_info_handler(
+    State(mm): State<ModelManager>,
+    Query(params): Query<DownloadParams>,
+) -> Result<Json<AssetInfoResponse>> {
+    let (asset, _) = read_requested_asset(mm.clone(), params).await?;
+
+    // The assets stored before their size was recorded are measured in the storage
+    let size = match asset.size {
+        Some(size) => size,
+        None => mm.file_meta(&asset.id).await?.size,
+    };
+
+    Ok(Json(AssetInfoResponse {
+        file_name: asset.file_name,
+        size,
+        encrypted: asset.encrypted,
+        created_at: asset.created_at,
+        expire: asset.expire,
+        remaining_downloads: asset.remaining_downloads,
+    }))
 }
 
 #[debug_handler]
 async fn download_handler(
     State(mm): State<ModelManager>,
     Query(params): Query<DownloadParams>,
-) -> Result<impl IntoResponse> {
-    // Read the asset from the database
-    let asset = Asset::read_by_memo_id(mm.clone(), &params.file.unwrap_or_default()).await?;
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let (asset, link) = read_requested_asset(mm.clone(), params).await?;
+
+    // Read the metadata of the file, needed for the range and caching headers
+    let meta = mm.file_meta(&asset.id).await?;
+
+    // Ranges are only honoured without a download limit, otherwise a limited file could be
+    // fetched piece by piece while being counted once
+    let accept_ranges = asset.remaining_downloads.is_none();
+    let range = match headers.get(header::RANGE).map(|value| value.to_str()) {
+        Some(Ok(value)) if accept_ranges => {
+            // If the file changed since the client started, it gets the whole new file
+            let unchanged = headers.get(header::IF_RANGE).is_none_or(|value| {
+                value
+                    .to_str()
+                    .is_ok_and(|value| range::if_range_matches(value, &meta))
+            });
 
-    // Read the data from minio based of the id
-    let data = mm.download(&asset.id.id.to_string()).await?;
-    let response = Response::builder()
-        .header("Content-Type", "application/octet-stream")
-        .header("Content-Length", data.1)
-        .header("filecrab-file-name", &asset.file_name)
-        .body(Body::from_stream(data.0.bytes))
-        .map_err(Error::Http)?;
+            if unchanged {
+                range::parse_range(value, meta.size)
+            } else {
+                RangeRequest::Full
+            }
+        }
+        _ => RangeRequest::Full,
+    };
+
+    let range = match range {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(range) => Some(range),
+        RangeRequest::Unsatisfiable => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", meta.size))
+                .header("filecrab-file-name", &asset.file_name)
+                .body(Body::empty())
+                .map_err(Error::Http);
+        }
+    };
 
-    Ok(response)
+    // A HEAD request only describes the file, it neither uses the link nor counts as a download
+    let body = if method == Method::HEAD {
+        Body::empty()
+    } else {
+        // Read the data from the storage based of the id, counting what is sent
+        let stream = mm
+            .download(&asset.id, range.clone())
+            .await?
+            .inspect(|chunk| {
+                if let Ok(chunk) = chunk {
+                    telemetry::record_downloaded(chunk.len() as u64);
+                }
+            });
+
+        // The link is only used up once the file could be opened
+        if let Some(link) = &link {
+            link.consume(mm.clone()).await?;
+        }
+
+        // Only a download which could start is counted, this fails if the asset has no downloads
+        // left
+        let remaining = Asset::register_download(mm.clone(), &asset.id).await?;
+
+        if remaining == Some(0) {
+            // This was the last download, nobody can find the asset anymore and the file is
+            // removed once it has been sent
+            Asset::delete(mm.clone(), &asset.id).await?;
+            let guard = DeleteFileOnDrop {
+                mm: mm.clone(),
+                id: asset.id.clone(),
+            };
+            Body::from_stream(stream.inspect(move |_| {
+                let _ = &guard;
+            }))
+        } else {
+            Body::from_stream(stream)
+        }
+    };
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(
+            header::ACCEPT_RANGES,
+            if accept_ranges { "bytes" } else { "none" },
+        )
+        .header(header::ETAG, &meta.etag)
+        .header("filecrab-file-name", &asset.file_name);
+
+    if let Some(last_modified) = meta.last_modified {
+        response = response.header(header::LAST_MODIFIED, range::http_date(last_modified));
+    }
+
+    let response = match range {
+        Some(range) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_LENGTH, range.end() - range.start() + 1)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start(), range.end(), meta.size),
+            ),
+        None => response.header(header::CONTENT_LENGTH, meta.size),
+    };
+
+    response.body(body).map_err(Error::Http)
+}
+
+/// Deletes a file from the storage when dropped, used to remove a file once its last allowed
+/// download has been streamed.
+struct DeleteFileOnDrop {
+    mm: ModelManager,
+    id: String,
+}
+
+impl Drop for DeleteFileOnDrop {
+    fn drop(&mut self) {
+        let mm = self.mm.clone();
+        let id = mem::take(&mut self.id);
+
+        tokio::spawn(async move {
+            if let Err(err) = mm.delete_files(vec![id]).await {
+                error!("could not delete file after its last download: {err}");
+            }
+        });
+    }
 }
 
 #[debug_handler]
 async fn paste_handler(
     State(mm): State<ModelManager>,
-    Json(body): Json<TextToCreate>,
+    Json(mut body): Json<TextToCreate>,
 ) -> Result<Response> {
     if body.content.is_empty() {
         return Ok(StatusCode::BAD_REQUEST.into_response());
     }
+
+    // Only the hash of the deletion token is stored
+    let delete_token = token::generate();
+    body.delete_token = Some(token::hash(&delete_token));
+
     let text = Text::create(mm.clone(), body).await?;
 
     let res = CreateResponse {
         id: text.memo_id.to_string(),
+        delete_token,
     };
 
     Ok(Json(res).into_response())
@@ -164,7 +554,177 @@ async fn copy_handler(
     };
 
     // Delete the text once it has been copied
-    Text::delete(mm.clone(), text.id.id.to_string()).await?;
+    Text::delete(mm.clone(), text.id).await?;
 
     Ok(Json(res).into_response())
 }
+
+#[derive(Debug, Serialize)]
+struct UsageResponse {
+    key: String,
+    name: String,
+    /// Bytes stored.
+    size: u64,
+    files: u64,
+    /// Bytes the key can store, `null` when unlimited.
+    quota_size: Option<u64>,
+    /// Files the key can store, `null` when unlimited.
+    quota_files: Option<u64>,
+}
+
+#[debug_handler]
+async fn usage_handler(
+    State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
+) -> Result<Json<UsageResponse>> {
+    let usage = api_key.usage(mm).await?;
+
+    Ok(Json(UsageResponse {
+        quota_size: api_key.size_limit(),
+        quota_files: api_key.files_limit(),
+        key: api_key.id,
+        name: api_key.name,
+        size: usage.size,
+        files: usage.files,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteParams {
+    memo_id: String,
+}
+
+/// Reads the deletion token from the request headers.
+fn delete_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(DELETE_TOKEN_HEADER)
+        .and_then(|token| token.to_str().ok())
+}
+
+#[debug_handler]
+async fn delete_asset_handler(
+    State(mm): State<ModelManager>,
+    Query(params): Query<DeleteParams>,
+    headers: HeaderMap,
+) -> Result<StatusCode> {
+    let token = delete_token(&headers).ok_or(Error::MissingDeleteToken)?;
+
+    let asset = Asset::read_by_memo_id(mm.clone(), &params.memo_id).await?;
+    if !token::verify(token, asset.delete_token.as_deref()) {
+        return Err(Error::InvalidDeleteToken);
+    }
+
+    // Remove the reference first so the file can't be downloaded anymore
+    Asset::delete(mm.clone(), &asset.id).await?;
+    mm.delete_files(vec![asset.id]).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[debug_handler]
+async fn delete_text_handler(
+    State(mm): State<ModelManager>,
+    Query(params): Query<DeleteParams>,
+    headers: HeaderMap,
+) -> Result<StatusCode> {
+    let token = delete_token(&headers).ok_or(Error::MissingDeleteToken)?;
+
+    let text = Text::read(mm.clone(), params.memo_id).await?;
+    if !token::verify(token, text.delete_token.as_deref()) {
+        return Err(Error::InvalidDeleteToken);
+    }
+
+    Text::delete(mm.clone(), text.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Bytes, to_bytes};
+
+    use super::*;
+
+    /// Stores a file of `content` downloadable `max_downloads` times under the `memo` memo id.
+    async fn limited_asset(mm: &ModelManager, content: &'static str, max_downloads: u32) {
+        let asset = AssetToCreate {
+            encrypted: false,
+            file_name: "a.txt".to_string(),
+            expire: None,
+            memo_id: Some("memo".to_string()),
+            max_downloads: Some(max_downloads),
+            delete_token: None,
+            created_by: None,
+            size: Some(content.len() as u64),
+            created_at: None,
+            signed_only: false,
+            pending: false,
+        };
+        mm.store().create_asset("file", asset).await.unwrap();
+
+        if !content.is_empty() {
+            let chunk = Ok::<_, std::io::Error>(Bytes::from_static(content.as_bytes()));
+            mm.upload("file", futures::stream::iter([chunk]))
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn download(mm: &ModelManager, method: Method) -> Result<Response> {
+        let params = DownloadParams {
+            file: Some("memo".to_string()),
+            expires: None,
+            nonce: None,
+            sig: None,
+        };
+
+        download_handler(State(mm.clone()), Query(params), method, HeaderMap::new()).await
+    }
+
+    async fn remaining_downloads(mm: &ModelManager) -> Option<u32> {
+        Asset::read_by_memo_id(mm.clone(), "memo")
+            .await
+            .unwrap()
+            .remaining_downloads
+    }
+
+    #[tokio::test]
+    async fn download_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mm = ModelManager::for_tests(dir.path()).await;
+        limited_asset(&mm, "hello", 2).await;
+
+        // Describing the file does not count
+        let response = download(&mm, Method::HEAD).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(remaining_downloads(&mm).await, Some(2));
+
+        let response = download(&mm, Method::GET).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "hello");
+        assert_eq!(remaining_downloads(&mm).await, Some(1));
+
+        // The last download still gets the file, the asset is gone afterwards
+        let response = download(&mm, Method::GET).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "hello");
+        assert!(matches!(
+            download(&mm, Method::GET).await,
+            Err(Error::ModelManager(ModelManagerError::AssetNotFound))
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_download_is_not_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mm = ModelManager::for_tests(dir.path()).await;
+        // The file is missing from the storage
+        limited_asset(&mm, "", 1).await;
+
+        assert!(matches!(
+            download(&mm, Method::GET).await,
+            Err(Error::ModelManager(ModelManagerError::ObjectNotFound))
+        ));
+        assert_eq!(remaining_downloads(&mm).await, Some(1));
+    }
+}
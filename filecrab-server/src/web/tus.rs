@@ -0,0 +1,375 @@
+// Resumable uploads following the tus 1.0 protocol (https://tus.io/protocols/resumable-upload),
+// with the creation, creation-defer-length and termination extensions.
+//
+// Chunks are cut into parts of `PART_SIZE` bytes sent to the storage as a multipart upload, so
+// every chunk but the last must be a multiple of that size. It is sent in the `filecrab-chunk-size`
+// header and other chunks are refused. A request cut in the middle of a part is acknowledged up to
+// the last whole part and the client sends the rest again, as allowed by the protocol.
+//
+// There is no discovery through OPTIONS, the CORS layer answers those requests.
+
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode, header, response::Builder},
+    response::Response,
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use futures::StreamExt;
+
+use super::routes::{DELETE_TOKEN_HEADER, parse_expire, parse_max_downloads};
+use crate::{
+    config::config,
+    model::{
+        ModelManager, ModelManagerError,
+        api_key::ApiKey,
+        asset::AssetToCreate,
+        token,
+        upload::{PART_SIZE, Upload},
+    },
+    telemetry,
+    web::{Error, Result},
+};
+
+/// Version of the protocol, sent and expected in the `Tus-Resumable` header.
+const TUS_VERSION: &str = "1.0.0";
+
+/// Header carrying the memorable id of the file once the upload is complete.
+pub const MEMO_ID_HEADER: &str = "filecrab-memo-id";
+
+/// Header carrying the size every chunk but the last must be a multiple of.
+pub const CHUNK_SIZE_HEADER: &str = "filecrab-chunk-size";
+
+/// Content type of the chunks.
+const CHUNK_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Uploads currently receiving a chunk, a single request may write to an upload at a time.
+static ACTIVE_UPLOADS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// Marks an upload as receiving a chunk until dropped.
+struct UploadLock(String);
+
+impl UploadLock {
+    fn acquire(id: &str) -> Option<UploadLock> {
+        let mut active = ACTIVE_UPLOADS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        active
+            .insert(id.to_string())
+            .then(|| UploadLock(id.to_string()))
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        ACTIVE_UPLOADS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.0);
+    }
+}
+
+/// Largest upload accepted, in bytes.
+fn max_size() -> u64 {
+    config().MAXIMUM_FILE_SIZE
+}
+
+/// Whether the client speaks our version of the protocol.
+fn supported_version(headers: &HeaderMap) -> bool {
+    headers
+        .get("tus-resumable")
+        .is_some_and(|version| version == TUS_VERSION)
+}
+
+/// Reads a numeric header, `Some(None)` when it is set but invalid.
+fn number_header(headers: &HeaderMap, name: &str) -> Option<Option<u64>> {
+    headers.get(name).map(|value| {
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+    })
+}
+
+/// Parses the `Upload-Metadata` header, comma separated keys each followed by an optional base64
+/// encoded value.
+fn parse_metadata(value: &str) -> Option<HashMap<String, String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+            let value = BASE64_STANDARD.decode(value.trim()).ok()?;
+
+            Some((key.to_string(), String::from_utf8(value).ok()?))
+        })
+        .collect()
+}
+
+/// Starts a response with the headers every tus response carries.
+fn tus_response(status: StatusCode) -> Builder {
+    Response::builder()
+        .status(status)
+        .header("tus-resumable", TUS_VERSION)
+        .header(CHUNK_SIZE_HEADER, PART_SIZE)
+        .header(header::CACHE_CONTROL, "no-store")
+}
+
+/// Adds the offset and, once complete, the memorable id of the upload to a response.
+fn with_progress(response: Builder, upload: &Upload) -> Builder {
+    let response = response.header("upload-offset", upload.offset);
+
+    match &upload.memo_id {
+        Some(memo_id) => response.header(MEMO_ID_HEADER, memo_id),
+        None => response,
+    }
+}
+
+pub async fn create_handler(
+    State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if !supported_version(&headers) {
+        return Err(Error::TusVersionMismatch);
+    }
+
+    // The length is either given now or deferred to a later chunk
+    let length = match number_header(&headers, "upload-length") {
+        Some(Some(length)) => Some(length),
+        Some(None) => return Err(Error::InvalidTusHeader("Upload-Length")),
+        None if headers
+            .get("upload-defer-length")
+            .is_some_and(|defer| defer == "1") =>
+        {
+            None
+        }
+        None => return Err(Error::InvalidTusHeader("Upload-Length")),
+    };
+    if length.is_some_and(|length| length > max_size()) {
+        return Err(Error::UploadTooLarge);
+    }
+
+    let metadata = match headers.get("upload-metadata") {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(parse_metadata)
+            .ok_or(Error::InvalidTusHeader("Upload-Metadata"))?,
+        None => HashMap::new(),
+    };
+
+    let file_name = metadata
+        .get("filename")
+        .filter(|file_name| !file_name.is_empty())
+        .ok_or(Error::MissingFileName)?;
+
+    // Only the hash of the deletion token is stored
+    let delete_token = token::generate();
+
+    let asset = AssetToCreate {
+        encrypted: metadata
+            .get("encrypted")
+            .is_some_and(|encrypted| encrypted.eq_ignore_ascii_case("true")),
+        file_name: file_name.to_string(),
+        expire: match metadata.get("expire") {
+            Some(value) => Some(parse_expire(value).ok_or(Error::InvalidExpire(value.clone()))?),
+            None => None,
+        },
+        memo_id: None,
+        max_downloads: match metadata.get("max_downloads") {
+            Some(value) => {
+                Some(parse_max_downloads(value).ok_or(Error::InvalidMaxDownloads(value.clone()))?)
+            }
+            None => None,
+        },
+        delete_token: Some(token::hash(&delete_token)),
+        created_by: Some(api_key.id.clone()),
+        size: length,
+        created_at: None,
+        signed_only: metadata
+            .get("signed_only")
+            .is_some_and(|signed_only| signed_only.eq_ignore_ascii_case("true")),
+        pending: false,
+    };
+
+    // A deferred length is checked once known
+    api_key
+        .check_quota(mm.clone(), length.unwrap_or_default())
+        .await?;
+
+    let upload = Upload::create(mm, length, asset).await?;
+
+    tus_response(StatusCode::CREATED)
+        .header(header::LOCATION, format!("/api/tus/{}", upload.id))
+        .header(DELETE_TOKEN_HEADER, delete_token)
+        .body(Body::empty())
+        .map_err(Error::Http)
+}
+
+/// Reads an upload started by `api_key`, the uploads of other keys are not found.
+async fn read_own_upload(mm: ModelManager, id: &str, api_key: &ApiKey) -> Result<Upload> {
+    let upload = Upload::read(mm, id).await?;
+    if upload.owner() != api_key.id {
+        return Err(ModelManagerError::UploadNotFound.into());
+    }
+
+    Ok(upload)
+}
+
+pub async fn head_handler(
+    State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if !supported_version(&headers) {
+        return Err(Error::TusVersionMismatch);
+    }
+
+    let upload = read_own_upload(mm, &id, &api_key).await?;
+
+    let response = match upload.length {
+        Some(length) => tus_response(StatusCode::OK).header("upload-length", length),
+        None => tus_response(StatusCode::OK).header("upload-defer-length", "1"),
+    };
+
+    with_progress(response, &upload)
+        .body(Body::empty())
+        .map_err(Error::Http)
+}
+
+pub async fn patch_handler(
+    State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response> {
+    if !supported_version(&headers) {
+        return Err(Error::TusVersionMismatch);
+    }
+    if headers
+        .get(header::CONTENT_TYPE)
+        .is_none_or(|content_type| content_type != CHUNK_CONTENT_TYPE)
+    {
+        return Err(Error::InvalidTusContentType);
+    }
+    let Some(Some(offset)) = number_header(&headers, "upload-offset") else {
+        return Err(Error::InvalidTusHeader("Upload-Offset"));
+    };
+
+    let _lock = UploadLock::acquire(&id).ok_or(Error::UploadLocked)?;
+    let mut upload = read_own_upload(mm.clone(), &id, &api_key).await?;
+
+    if upload.offset != offset {
+        return Err(Error::UploadOffsetMismatch);
+    }
+
+    // A deferred length is given with one of the chunks
+    match number_header(&headers, "upload-length") {
+        Some(Some(length)) if upload.length.is_none() => {
+            if length < upload.offset || length > max_size() {
+                return Err(Error::InvalidTusHeader("Upload-Length"));
+            }
+
+            upload.length = Some(length);
+            mm.store().update_upload(&upload, upload.offset).await?;
+        }
+        Some(Some(length)) if upload.length == Some(length) => {}
+        Some(_) => return Err(Error::InvalidTusHeader("Upload-Length")),
+        None => {}
+    }
+
+    // Only whole parts are stored, a shorter chunk has to be the last
+    if let Some(Some(len)) = number_header(&headers, header::CONTENT_LENGTH.as_str()) {
+        let last = upload.length == Some(upload.offset + len);
+        if !last && len % PART_SIZE as u64 != 0 {
+            return Err(Error::InvalidChunkSize(PART_SIZE));
+        }
+    }
+
+    if !upload.is_complete() {
+        let limit = upload.length.unwrap_or(max_size());
+
+        // Cuts the chunk into parts, a part is only sent once full
+        let mut stream = body.into_data_stream();
+        let mut buf = Vec::with_capacity(PART_SIZE);
+        let mut interrupted = false;
+
+        while let Some(chunk) = stream.next().await {
+            let Ok(mut chunk) = chunk else {
+                // The client is gone, what has been stored so far is kept
+                interrupted = true;
+                break;
+            };
+
+            if upload.offset + (buf.len() + chunk.len()) as u64 > limit {
+                return Err(Error::UploadTooLarge);
+            }
+            telemetry::record_uploaded(chunk.len() as u64);
+
+            while !chunk.is_empty() {
+                let len = chunk.len().min(PART_SIZE - buf.len());
+                buf.extend_from_slice(&chunk.split_to(len));
+
+                if buf.len() == PART_SIZE {
+                    let part = mem::replace(&mut buf, Vec::with_capacity(PART_SIZE));
+                    upload.write_part(mm.clone(), Bytes::from(part)).await?;
+                }
+            }
+        }
+
+        // The last part can be smaller, and a file needs at least one
+        let received = upload.offset + buf.len() as u64;
+        if !interrupted && upload.length == Some(received) {
+            // The quota may have been used meanwhile, the upload is dropped if it does not fit
+            let owner = ApiKey::read(mm.clone(), upload.owner()).await?;
+            if let Err(err) = owner.check_quota(mm.clone(), received).await {
+                Upload::delete(mm, &upload).await?;
+                return Err(err.into());
+            }
+
+            if !buf.is_empty() || upload.parts.is_empty() {
+                upload.write_part(mm.clone(), Bytes::from(buf)).await?;
+            }
+
+            upload.asset.size = Some(received);
+            upload.complete(mm).await?;
+        } else if !interrupted && !buf.is_empty() {
+            // Streamed without a length, the whole parts are kept but the rest is refused
+            return Err(Error::InvalidChunkSize(PART_SIZE));
+        }
+    }
+
+    with_progress(tus_response(StatusCode::NO_CONTENT), &upload)
+        .body(Body::empty())
+        .map_err(Error::Http)
+}
+
+pub async fn delete_handler(
+    State(mm): State<ModelManager>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if !supported_version(&headers) {
+        return Err(Error::TusVersionMismatch);
+    }
+
+    let _lock = UploadLock::acquire(&id).ok_or(Error::UploadLocked)?;
+    let upload = read_own_upload(mm.clone(), &id, &api_key).await?;
+    Upload::delete(mm, &upload).await?;
+
+    tus_response(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .map_err(Error::Http)
+}
@@ -0,0 +1,77 @@
+// Service worker answering the download requests of the page with the content it streams, so
+// that browsers without the File System Access API save a download without holding it in memory.
+
+// Prefix of the urls answered with a download, relative to the scope of the worker.
+const DOWNLOAD_PREFIX = "filecrab-download/";
+
+// Downloads announced by the page and not requested yet, by id.
+const downloads = new Map();
+
+self.addEventListener("install", () => self.skipWaiting());
+
+self.addEventListener("activate", (event) => event.waitUntil(self.clients.claim()));
+
+self.addEventListener("message", (event) => {
+    // The pings of the page only keep the worker alive
+    if (event.data.type !== "filecrab-download") {
+        return;
+    }
+
+    const { id, name } = event.data;
+    const port = event.ports[0];
+
+    // A chunk is asked to the page each time the browser took the previous one, only a chunk at
+    // a time is held here
+    const stream = new ReadableStream(
+        {
+            start(controller) {
+                port.onmessage = (message) => {
+                    const data = message.data;
+                    if (data.type === "chunk") {
+                        controller.enqueue(data.data);
+                    } else if (data.type === "end") {
+                        controller.close();
+                        port.close();
+                    } else if (data.type === "abort") {
+                        controller.error(new Error(data.reason));
+                        port.close();
+                    }
+                };
+            },
+            pull() {
+                port.postMessage({ type: "pull" });
+            },
+            cancel() {
+                port.postMessage({ type: "cancel" });
+                port.close();
+            },
+        },
+        { highWaterMark: 1 },
+    );
+
+    downloads.set(id, { name, stream });
+});
+
+self.addEventListener("fetch", (event) => {
+    const url = new URL(event.request.url);
+    const prefix = new URL(DOWNLOAD_PREFIX, self.registration.scope).pathname;
+    if (!url.pathname.startsWith(prefix)) {
+        return;
+    }
+
+    const id = url.pathname.slice(prefix.length);
+    const download = downloads.get(id);
+    if (!download) {
+        return;
+    }
+    downloads.delete(id);
+
+    event.respondWith(
+        new Response(download.stream, {
+            headers: {
+                "Content-Type": "application/octet-stream",
+                "Content-Disposition": `attachment; filename*=UTF-8''${encodeURIComponent(download.name)}`,
+            },
+        }),
+    );
+});
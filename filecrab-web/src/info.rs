@@ -0,0 +1,113 @@
+use anyhow::{Result, bail};
+use dioxus::prelude::*;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+
+use crate::BACKEND_URL;
+
+/// Represents the response of the asset info request.
+#[derive(Deserialize)]
+struct AssetInfo {
+    file_name: String,
+    size: u64,
+    encrypted: bool,
+    created_at: Option<String>,
+    expire: Option<String>,
+    remaining_downloads: Option<u32>,
+}
+
+/// Fetches what the server knows about the file, nothing is downloaded.
+async fn fetch_info(id: String) -> Result<AssetInfo> {
+    let query = vec![("file", &id)];
+    let res = Client::new()
+        // Here it's safe to unwrap since we know for sure it's initialized
+        .get(format!("{}/api/asset/info", BACKEND_URL.get().unwrap()))
+        .query(&query)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        bail!("There is no file with this ID, it may have expired")
+    }
+    if !res.status().is_success() {
+        bail!("Status: {}", res.status())
+    }
+
+    Ok(res.json().await?)
+}
+
+/// Formats a size in bytes with the largest unit it fits.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.2} {}", UNITS[unit]),
+    }
+}
+
+/// Formats a date sent by the server in the locale of the browser.
+fn local_date(date: &str) -> String {
+    js_sys::Date::new(&JsValue::from_str(date))
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+/// Describes the file of the given id, shown before it is downloaded.
+#[component]
+pub fn FileInfo(id: Signal<String>) -> Element {
+    let info = use_resource(move || async move {
+        let id = id();
+        if id.is_empty() {
+            return None;
+        }
+
+        Some(fetch_info(id).await)
+    });
+
+    match &*info.read() {
+        Some(Some(Ok(info))) => {
+            let size = human_size(info.size);
+            let encryption = if info.encrypted {
+                "encrypted, the password is needed"
+            } else {
+                "not encrypted"
+            };
+            let created_at = info
+                .created_at
+                .as_deref()
+                .map_or("an unknown date".to_string(), local_date);
+            let expire = match &info.expire {
+                Some(expire) => format!("Expires on {}", local_date(expire)),
+                None => "Never expires".to_string(),
+            };
+            let downloads = match info.remaining_downloads {
+                Some(1) => "1 download left".to_string(),
+                Some(remaining) => format!("{remaining} downloads left"),
+                None => "Unlimited downloads".to_string(),
+            };
+
+            rsx! {
+                div {
+                    class: "alert flex flex-col gap-2",
+                    p { class: "text-primary", "{info.file_name}" }
+                    p { "{size}, {encryption}" }
+                    p { class: "opacity-70", "Uploaded on {created_at}" }
+                    p { class: "opacity-70", "{expire}, {downloads}" }
+                }
+            }
+        }
+        Some(Some(Err(err))) => rsx! {
+            div { class: "alert alert-error", "{err}" }
+        },
+        _ => rsx! {},
+    }
+}
@@ -0,0 +1,70 @@
+use dioxus::prelude::*;
+use web_sys::Storage;
+
+/// Name under which the API key is remembered in the browser.
+const STORAGE_KEY: &str = "filecrab-key";
+
+fn storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Returns the API key remembered by the browser, if any.
+fn load() -> String {
+    storage()
+        .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+        .unwrap_or_default()
+}
+
+/// Remembers the API key so it does not have to be given again when the user asked so, called
+/// once the form is submitted. Otherwise, or if the key is empty, it is forgotten.
+pub fn save(api_key: &str, remember: bool) {
+    let Some(storage) = storage() else {
+        return;
+    };
+
+    let _ = if remember && !api_key.is_empty() {
+        storage.set_item(STORAGE_KEY, api_key)
+    } else {
+        storage.remove_item(STORAGE_KEY)
+    };
+}
+
+/// Returns the API key remembered by the browser, and whether it should still be remembered.
+pub fn use_api_key() -> (Signal<String>, Signal<bool>) {
+    let api_key = use_signal(load);
+    let remember = use_signal(|| !api_key.peek().is_empty());
+
+    (api_key, remember)
+}
+
+#[component]
+pub fn ApiKeyInput(api_key: Signal<String>, remember: Signal<bool>, disabled: bool) -> Element {
+    rsx! {
+        label { class: "input input-bordered input-primary flex items-center gap-2",
+            svg {
+                xmlns: "http://www.w3.org/2000/svg",
+                view_box: "0 0 16 16",
+                fill: "currentColor",
+                class: "h-4 w-4 opacity-70",
+                path { d: "M14 6a4 4 0 0 1-4.899 3.899l-1.955 1.955a.5.5 0 0 1-.353.146H5v1.5a.5.5 0 0 1-.5.5h-2a.5.5 0 0 1-.5-.5v-2.293a.5.5 0 0 1 .146-.353l3.955-3.955A4 4 0 1 1 14 6Zm-4-2a.75.75 0 0 0 0 1.5.5.5 0 0 1 .5.5.75.75 0 0 0 1.5 0 2 2 0 0 0-2-2Z" }
+            }
+            input {
+                oninput: move |event| api_key.set(event.value()),
+                class: "grow",
+                r#type: "password",
+                placeholder: "API key",
+                value: "{api_key}",
+                disabled
+            }
+        }
+        label { class: "flex items-center gap-2 opacity-70",
+            input {
+                onchange: move |event| remember.set(event.checked()),
+                r#type: "checkbox",
+                checked: remember(),
+                disabled
+            }
+            "Remember the key on this browser"
+        }
+    }
+}
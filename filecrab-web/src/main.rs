@@ -1,3 +1,9 @@
+mod info;
+mod key;
+mod save;
+mod text;
+mod upload;
+
 use age::{Decryptor, secrecy::SecretString};
 use anyhow::{Result, anyhow, bail};
 use async_std::task::sleep;
@@ -5,10 +11,14 @@ use dioxus::prelude::*;
 use dioxus_logger::tracing::{Level, info};
 use document::eval;
 use file_format::FileFormat;
-use futures_util::AsyncReadExt;
-use futures_util::{StreamExt, io};
+use futures_util::{AsyncRead, AsyncReadExt, TryStreamExt, io};
+use info::FileInfo;
 use reqwest::Client;
+use save::Sink;
 use std::{fmt::Display, sync::OnceLock, time::Duration};
+use text::{Paste, Reveal, RevealLink};
+use upload::Upload;
+use wasm_bindgen::JsValue;
 
 // Urls are relative to your Cargo.toml file
 const TAILWIND_CSS: Asset = asset!("public/tailwind.css");
@@ -28,6 +38,29 @@ static ACTION_IN_PROGRESS: GlobalSignal<Action> = Signal::global(|| Action::Idle
 
 static BACKEND_URL: OnceLock<String> = OnceLock::new();
 
+/// Size of the chunks written to the disk.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Number of bytes read to know whether a file is encrypted.
+const HEADER_SIZE: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Routable)]
+enum Route {
+    #[layout(Layout)]
+    #[route("/")]
+    Download {},
+    #[route("/upload")]
+    Upload {},
+    #[route("/paste")]
+    Paste {},
+    #[route("/reveal")]
+    Reveal {},
+    #[route("/d/:id")]
+    DownloadLink { id: String },
+    #[route("/t/:id")]
+    RevealLink { id: String },
+}
+
 fn main() {
     // We unwrap since without this we can't actually use our frontend
     let window = web_sys::window().unwrap();
@@ -60,7 +93,9 @@ impl Display for Action {
     }
 }
 
-async fn get_file(id: String, pwd: String) -> Result<(String, Vec<u8>)> {
+/// Downloads the file and saves it to the disk, decrypting it on the way if needed. The content is
+/// handled chunk by chunk as it comes, it is never held whole in memory.
+async fn fetch_file(id: String, pwd: String) -> Result<()> {
     if id.is_empty() {
         bail!("File name cannot be empty.")
     }
@@ -87,22 +122,17 @@ async fn get_file(id: String, pwd: String) -> Result<(String, Vec<u8>)> {
         None => return Err(anyhow!("Could not get filename from response header.")),
     };
 
-    // Inits the stream.
-    let mut stream = res.bytes_stream();
-
-    // Creates the buffer.
-    let mut buf: Vec<u8> = Vec::new();
-
     // Reads the stream.
-    while let Some(data) = stream.next().await {
-        let chunk = data?;
-        io::copy(&mut chunk.as_ref(), &mut buf).await?;
-    }
-
-    *ACTION_IN_PROGRESS.write() = Action::Idle;
-    sleep(Duration::from_millis(10)).await;
+    let mut reader = Box::pin(res.bytes_stream().map_err(io::Error::other)).into_async_read();
 
-    let is_encrypted = FileFormat::from_bytes(&buf) == FileFormat::AgeEncryption;
+    // Reads the beginning of the file, enough to know if it is encrypted, and puts it back
+    let mut head = Vec::with_capacity(HEADER_SIZE);
+    (&mut reader)
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut head)
+        .await?;
+    let is_encrypted = FileFormat::from_bytes(&head) == FileFormat::AgeEncryption;
+    let reader = io::Cursor::new(head).chain(reader);
 
     // If file is encoded try to decrypt it
     if is_encrypted && pwd.is_empty() {
@@ -111,87 +141,122 @@ async fn get_file(id: String, pwd: String) -> Result<(String, Vec<u8>)> {
         *ACTION_IN_PROGRESS.write() = Action::PreparingDecryption;
         sleep(Duration::from_millis(10)).await;
 
-        let output = decrypt_slice(&buf[..], pwd).await?;
-        return Ok((file_name, output));
+        // A wrong password fails here, before the user is asked where to save the file
+        let decryptor = Decryptor::new_async_buffered(reader).await?;
+        let decrypted = decryptor.decrypt_async(std::iter::once(&age::scrypt::Identity::new(
+            SecretString::from(pwd),
+        ) as _))?;
+
+        // Set the action to decrypting
+        *ACTION_IN_PROGRESS.write() = Action::Decrypting;
+        return save_file(&file_name, decrypted).await;
     }
 
-    // Simply return the file
-    Ok((file_name, buf))
+    // Simply save the file
+    save_file(&file_name, reader).await
 }
 
-/// Given a slice of bytes and a password, tries to decrypt it's values and returns the
-/// original content.
-/// Uses the age algorithm.
-async fn decrypt_slice(buf: &[u8], pwd: String) -> anyhow::Result<Vec<u8>> {
-    let decryptor = Decryptor::new_async_buffered(buf).await?;
-    let mut output = vec![];
-    let mut reader = decryptor.decrypt_async(std::iter::once(&age::scrypt::Identity::new(
-        SecretString::from(pwd),
-    ) as _))?;
-
-    // Set the action to decrypting
-    *ACTION_IN_PROGRESS.write() = Action::Decrypting;
-    sleep(Duration::from_millis(10)).await;
-
-    let mut buffer = vec![0u8; 1024 * 1024];
+/// Writes what the reader gives to a file of the user, chunk by chunk. The file is dropped if the
+/// reader fails.
+async fn save_file<R>(file_name: &str, mut reader: R) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let sink = Sink::open(file_name).await?;
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
     loop {
-        match reader.read(&mut buffer).await {
-            Ok(0) => {
-                break;
-            }
-            Ok(n) => {
-                output.extend_from_slice(&buffer[..n]);
-                sleep(Duration::from_millis(1)).await;
-            }
-            Err(err) => {
-                bail!(err)
-            }
+        let res = match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => sink.write(&buffer[..n]).await,
+            Err(err) => Err(err.into()),
+        };
+
+        if let Err(err) = res {
+            sink.abort(&err.to_string());
+            return Err(err);
         }
     }
+
     // Restore the in progress to idle after finishing the task
     *ACTION_IN_PROGRESS.write() = Action::FinishingFile;
     sleep(Duration::from_millis(50)).await;
 
-    Ok(output)
+    sink.close().await
 }
 
-async fn fetch_file(id: String, pwd: String) -> Result<()> {
-    let (file_name, data) = get_file(id, pwd).await?;
+#[component]
+fn App() -> Element {
+    rsx! {
+        document::Link{rel: "stylesheet", href: TAILWIND_CSS}
+        Router::<Route> {}
+    }
+}
 
-    let new_eval = eval(
-        r#"
-        let filename = await dioxus.recv();
-        let content = await dioxus.recv();
-
-        // Convert from uint8array
-        var contentBytes = new Uint8Array(content);
-
-        var contentType = 'application/octet-stream';
-        var a = document.createElement('a');
-        var blob = new Blob([contentBytes], {'type':contentType});
-        a.setAttribute("download", filename);
-        a.setAttribute("href", window.URL.createObjectURL(blob));
-        a.click();
-        "#,
-    );
+#[component]
+fn Layout() -> Element {
+    rsx! {
+        div { class: "container mx-auto max-w-screen-xl px-6 flex flex-col gap-4",
+            img { class: "mx-auto", src: "{ASSET}" },
+            nav { class: "join mx-auto",
+                Link { class: "btn join-item", active_class: "btn-active", to: Route::Download {}, "Download" }
+                Link { class: "btn join-item", active_class: "btn-active", to: Route::Upload {}, "Upload" }
+                Link { class: "btn join-item", active_class: "btn-active", to: Route::Paste {}, "Paste" }
+                Link { class: "btn join-item", active_class: "btn-active", to: Route::Reveal {}, "Reveal" }
+            }
+            div { Outlet::<Route> {} }
+        }
+    }
+}
 
-    // Convert the Vec<u8> to serde::Value using serde_bytes::ByteBuf
-    let data_value = serde_bytes::ByteBuf::from(data);
+/// Returns the password carried by the fragment of a shared link, the fragment is never sent to
+/// the server. Once read it is removed from the address bar and the history of the browser.
+fn link_password() -> String {
+    let Some(window) = web_sys::window() else {
+        return String::new();
+    };
+    let location = window.location();
 
-    new_eval
-        .send(file_name)
-        .map_err(|err| anyhow!("{err:?}").context("could not eval filename"))?;
-    new_eval
-        .send(serde_json::to_value(data_value)?)
-        .map_err(|err| anyhow!("{err:?}").context("could not eval data"))?;
+    let pwd: String = location
+        .hash()
+        .ok()
+        .and_then(|hash| js_sys::decode_uri_component(hash.trim_start_matches('#')).ok())
+        .map(String::from)
+        .unwrap_or_default();
+
+    if !pwd.is_empty() {
+        let url = format!(
+            "{}{}",
+            location.pathname().unwrap_or_default(),
+            location.search().unwrap_or_default()
+        );
+        if let Ok(history) = window.history() {
+            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&url));
+        }
+    }
 
-    Ok(())
+    pwd
 }
 
 #[component]
-fn App() -> Element {
-    let id = use_signal(|| "".to_string());
-    let pwd = use_signal(|| "".to_string());
+fn Download() -> Element {
+    rsx! {
+        DownloadView { memo_id: "" }
+    }
+}
+
+/// The download page of a shared link, ex. `/d/<ID>#<PASSWORD>`.
+#[component]
+fn DownloadLink(id: String) -> Element {
+    rsx! {
+        DownloadView { memo_id: id }
+    }
+}
+
+#[component]
+fn DownloadView(memo_id: String) -> Element {
+    let id = use_signal(|| memo_id);
+    let pwd = use_signal(link_password);
 
     let fetch_result = use_signal(|| None::<anyhow::Result<()>>);
 
@@ -205,9 +270,7 @@ fn App() -> Element {
     });
 
     rsx! {
-        document::Link{rel: "stylesheet", href: TAILWIND_CSS}
-        div { class: "container mx-auto max-w-screen-xl px-6",
-            img { class: "mx-auto", src: "{ASSET}" },
+        div {
             DownloadForm { id, pwd, fetch_result }
             {
                 match &*ACTION_IN_PROGRESS.read() {
@@ -268,6 +331,10 @@ fn DownloadForm(
     // Button is disabled unless there is something to sed
     let mut disable_button = use_signal(|| true);
     let mut disable_form = use_signal(|| false);
+
+    // The file is described once its id is entered, not at each keystroke
+    let mut described_id = use_signal(|| id.peek().clone());
+
     use_effect(move || {
         if !id().is_empty() {
             disable_button.set(false);
@@ -291,6 +358,7 @@ fn DownloadForm(
                     if result.is_ok() {
                         id.set("".to_string());
                         pwd.set("".to_string());
+                        described_id.set("".to_string());
                     }
 
                     // Set the result response
@@ -354,6 +422,7 @@ fn DownloadForm(
                 }
                 input {
                     oninput: move |event| id.set(event.value()),
+                    onchange: move |event| described_id.set(event.value().trim().to_string()),
                     class: "min-w-[90%]",
                     r#type: "text",
                     placeholder: "File id",
@@ -382,6 +451,7 @@ fn DownloadForm(
                     "Optional"
                 }
             }
+            FileInfo { id: described_id }
             button {
                 class: "btn btn-primary",
                 r#type: "submit",
@@ -392,6 +462,68 @@ fn DownloadForm(
     }
 }
 
+#[component]
+fn PasswordInput(
+    pwd: Signal<String>,
+    placeholder: String,
+    optional: bool,
+    disabled: bool,
+) -> Element {
+    rsx! {
+        label { class: "input input-bordered input-primary flex items-center gap-2",
+            svg {
+                xmlns: "http://www.w3.org/2000/svg",
+                view_box: "0 0 16 16",
+                fill: "currentColor",
+                class: "h-4 w-4 opacity-70",
+                path { d: "M14 6a4 4 0 0 1-4.899 3.899l-1.955 1.955a.5.5 0 0 1-.353.146H5v1.5a.5.5 0 0 1-.5.5h-2a.5.5 0 0 1-.5-.5v-2.293a.5.5 0 0 1 .146-.353l3.955-3.955A4 4 0 1 1 14 6Zm-4-2a.75.75 0 0 0 0 1.5.5.5 0 0 1 .5.5.75.75 0 0 0 1.5 0 2 2 0 0 0-2-2Z" }
+            }
+            input {
+                oninput: move |event| pwd.set(event.value()),
+                class: "grow",
+                r#type: "password",
+                placeholder,
+                value: "{pwd}",
+                disabled
+            }
+            if optional {
+                span {
+                    class: "badge badge-ghost",
+                    "Optional"
+                }
+            }
+        }
+    }
+}
+
+/// Copies the text to the clipboard of the user.
+fn copy_to_clipboard(text: String) -> Result<()> {
+    eval(
+        r#"
+        let text = await dioxus.recv();
+        await navigator.clipboard.writeText(text);
+        "#,
+    )
+    .send(text)
+    .map_err(|err| anyhow!("{err:?}").context("could not copy to the clipboard"))
+}
+
+#[component]
+fn CopyButton(text: String) -> Element {
+    let mut copied = use_signal(|| false);
+
+    rsx! {
+        button {
+            class: "btn btn-outline",
+            r#type: "button",
+            onclick: move |_| {
+                copied.set(copy_to_clipboard(text.clone()).is_ok());
+            },
+            if copied() { "Copied!" } else { "Copy to clipboard" }
+        }
+    }
+}
+
 #[component]
 fn ErrorToast(err: String, show: Signal<bool, SyncStorage>) -> Element {
     if *show.read() {
@@ -0,0 +1,151 @@
+// Sinks writing a download to the disk as it comes, so that it is never held in memory whole.
+//
+// The File System Access API is used where available. Elsewhere, or when the click which started
+// the download is too old to open its picker, a service worker answers a download request with a
+// stream fed by the page, the browser saves it like any other download. Without either, the
+// download is gathered in a Blob as a last resort.
+
+// Prefix of the urls the service worker answers with a download.
+const DOWNLOAD_PREFIX = "filecrab-download/";
+
+// Interval at which the service worker is pinged, browsers stop idle workers.
+const KEEP_ALIVE_INTERVAL = 10000;
+
+export async function openSink(name, serviceWorkerUrl) {
+    if ("showSaveFilePicker" in window) {
+        try {
+            return await fileSystemSink(name);
+        } catch (err) {
+            // The picker needs a recent user activation, spent if the server was slow to answer
+            if (err.name !== "SecurityError") {
+                throw err;
+            }
+            console.warn("could not open the file picker, streaming the download instead", err);
+        }
+    }
+    if ("serviceWorker" in navigator) {
+        try {
+            return await serviceWorkerSink(name, serviceWorkerUrl);
+        } catch (err) {
+            // Ex. private windows of Firefox do not allow service workers
+            console.warn("could not use the service worker, buffering the download", err);
+        }
+    }
+    return blobSink(name);
+}
+
+async function fileSystemSink(name) {
+    const handle = await window.showSaveFilePicker({ suggestedName: name });
+    const writable = await handle.createWritable();
+
+    return {
+        // The chunks are views on the memory of the wasm module, they are copied before it changes
+        write: (chunk) => writable.write(chunk.slice()),
+        close: () => writable.close(),
+        abort: (reason) => {
+            writable.abort(reason).catch(() => {});
+        },
+    };
+}
+
+async function serviceWorkerSink(name, serviceWorkerUrl) {
+    const registration = await navigator.serviceWorker.register(serviceWorkerUrl);
+    const worker = await activeWorker(registration);
+
+    // The worker asks for a chunk each time it sent the previous one to the browser, only that
+    // many chunks are sent
+    const channel = new MessageChannel();
+    let credits = 0;
+    let waiting = null;
+    let cancelled = false;
+    channel.port1.onmessage = (event) => {
+        if (event.data.type === "pull") {
+            credits += 1;
+        } else if (event.data.type === "cancel") {
+            cancelled = true;
+        }
+        if (waiting) {
+            waiting();
+            waiting = null;
+        }
+    };
+
+    const id = crypto.randomUUID();
+    worker.postMessage({ type: "filecrab-download", id, name }, [channel.port2]);
+    const keepAlive = setInterval(
+        () => worker.postMessage({ type: "filecrab-keep-alive" }),
+        KEEP_ALIVE_INTERVAL,
+    );
+
+    // Navigating a hidden frame starts the download, handled by the worker
+    const frame = document.createElement("iframe");
+    frame.hidden = true;
+    frame.src = registration.scope + DOWNLOAD_PREFIX + id;
+    document.body.appendChild(frame);
+
+    const finish = (message) => {
+        clearInterval(keepAlive);
+        channel.port1.postMessage(message);
+        // Give the browser the time to take over the download before removing the frame
+        setTimeout(() => frame.remove(), 60000);
+    };
+
+    return {
+        write: async (chunk) => {
+            // The chunk is a view on the memory of the wasm module, it is copied before it changes
+            const data = chunk.slice();
+
+            while (credits === 0 && !cancelled) {
+                await new Promise((resolve) => (waiting = resolve));
+            }
+            if (cancelled) {
+                throw new Error("The download was cancelled");
+            }
+            credits -= 1;
+
+            channel.port1.postMessage({ type: "chunk", data }, [data.buffer]);
+        },
+        close: async () => finish({ type: "end" }),
+        abort: (reason) => finish({ type: "abort", reason: String(reason) }),
+    };
+}
+
+// Waits for the worker of the registration to be activated.
+function activeWorker(registration) {
+    if (registration.active) {
+        return Promise.resolve(registration.active);
+    }
+
+    const worker = registration.installing || registration.waiting;
+    return new Promise((resolve, reject) => {
+        worker.addEventListener("statechange", () => {
+            if (worker.state === "activated") {
+                resolve(worker);
+            } else if (worker.state === "redundant") {
+                reject(new Error("The service worker could not be installed"));
+            }
+        });
+    });
+}
+
+function blobSink(name) {
+    const chunks = [];
+
+    return {
+        write: async (chunk) => {
+            chunks.push(chunk.slice());
+        },
+        close: async () => {
+            const blob = new Blob(chunks, { type: "application/octet-stream" });
+            const url = URL.createObjectURL(blob);
+            const a = document.createElement("a");
+            a.setAttribute("download", name);
+            a.setAttribute("href", url);
+            a.click();
+            setTimeout(() => URL.revokeObjectURL(url), 60000);
+        },
+        abort: () => {
+            chunks.length = 0;
+        },
+    };
+}
@@ -0,0 +1,61 @@
+use anyhow::{Result, anyhow};
+use dioxus::prelude::*;
+use wasm_bindgen::prelude::*;
+
+/// Service worker streaming the downloads of the browsers without the File System Access API.
+const DOWNLOAD_WORKER: Asset = asset!("assets/download-worker.js");
+
+#[wasm_bindgen(module = "/src/save.js")]
+extern "C" {
+    type JsSink;
+
+    #[wasm_bindgen(catch, js_name = openSink)]
+    async fn open_sink(name: &str, service_worker_url: &str) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    async fn write(this: &JsSink, chunk: &[u8]) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    async fn close(this: &JsSink) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method)]
+    fn abort(this: &JsSink, reason: &str);
+}
+
+/// A file on the disk of the user, written chunk by chunk.
+pub struct Sink(JsSink);
+
+impl Sink {
+    /// Asks the browser where to save the file, depending on what it supports.
+    pub async fn open(file_name: &str) -> Result<Sink> {
+        let sink = open_sink(file_name, &DOWNLOAD_WORKER.to_string())
+            .await
+            .map_err(|err| anyhow!("{err:?}").context("could not open the file"))?;
+
+        Ok(Sink(sink.unchecked_into()))
+    }
+
+    pub async fn write(&self, chunk: &[u8]) -> Result<()> {
+        self.0
+            .write(chunk)
+            .await
+            .map_err(|err| anyhow!("{err:?}").context("could not write to the file"))?;
+
+        Ok(())
+    }
+
+    /// Completes the file.
+    pub async fn close(self) -> Result<()> {
+        self.0
+            .close()
+            .await
+            .map_err(|err| anyhow!("{err:?}").context("could not finish the file"))?;
+
+        Ok(())
+    }
+
+    /// Drops what has been written so far.
+    pub fn abort(self, reason: &str) {
+        self.0.abort(reason);
+    }
+}
@@ -0,0 +1,319 @@
+use age::{Decryptor, Encryptor, secrecy::SecretString};
+use anyhow::{Result, anyhow, bail};
+use dioxus::prelude::*;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+use crate::{
+    BACKEND_URL, CopyButton, ErrorToast, PasswordInput,
+    key::{self, ApiKeyInput},
+    link_password,
+};
+
+/// Represents the body of the paste request.
+#[derive(Serialize)]
+struct PasteBody {
+    content: String,
+}
+
+/// Represents the response of the paste request.
+#[derive(Deserialize)]
+struct PasteResponse {
+    id: String,
+}
+
+/// Represents the response of the copy request.
+#[derive(Deserialize)]
+struct CopyResponse {
+    content: String,
+}
+
+/// Encrypts the text and returns it hex encoded, the format the CLI sends.
+fn encrypt_text(content: &str, pwd: String) -> Result<String> {
+    let encryptor = Encryptor::with_user_passphrase(SecretString::from(pwd));
+    let mut output = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut output)?;
+    writer.write_all(content.as_bytes())?;
+    writer.finish()?;
+
+    Ok(hex::encode(output))
+}
+
+/// Decrypts a hex encoded text, as sent by the CLI.
+fn decrypt_text(content: &str, pwd: String) -> Result<String> {
+    let encrypted_bytes = hex::decode(content.as_bytes())?;
+    let decryptor = Decryptor::new(&encrypted_bytes[..])?;
+
+    let mut output = vec![];
+    let mut reader = decryptor.decrypt(std::iter::once(&age::scrypt::Identity::new(
+        SecretString::from(pwd),
+    ) as _))?;
+    reader.read_to_end(&mut output)?;
+
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+/// Explains the failed requests the user can do something about.
+fn request_error(status: StatusCode, scope: &str) -> anyhow::Error {
+    match status {
+        StatusCode::UNAUTHORIZED => anyhow!("The API key is not valid"),
+        StatusCode::FORBIDDEN => anyhow!("The API key does not have the {scope} scope"),
+        status => anyhow!("Status: {status}"),
+    }
+}
+
+/// Encrypts and sends the text, returns its memorable id.
+async fn paste_text(api_key: String, content: String, pwd: String) -> Result<String> {
+    if api_key.is_empty() {
+        bail!("An API key is needed to paste a text")
+    }
+    if pwd.is_empty() {
+        bail!("A password is needed, texts are always encrypted")
+    }
+
+    let content = encrypt_text(&content, pwd)?;
+
+    let res = Client::new()
+        // Here it's safe to unwrap since we know for sure it's initialized
+        .post(format!("{}/api/paste", BACKEND_URL.get().unwrap()))
+        .header("filecrab-key", api_key)
+        .json(&PasteBody { content })
+        .send()
+        .await?;
+
+    if !res.status().is_success() {
+        return Err(request_error(res.status(), "paste"));
+    }
+
+    let body: PasteResponse = res.json().await?;
+    Ok(body.id)
+}
+
+/// Fetches and decrypts the text, the server destroys it once sent.
+async fn reveal_text(api_key: String, id: String, pwd: String) -> Result<String> {
+    if api_key.is_empty() {
+        bail!("An API key is needed to reveal a text")
+    }
+
+    let query = vec![("memo_id", &id)];
+    let res = Client::new()
+        // Here it's safe to unwrap since we know for sure it's initialized
+        .get(format!("{}/api/copy", BACKEND_URL.get().unwrap()))
+        .query(&query)
+        .header("filecrab-key", api_key)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        bail!("There is no text with this ID, it may have been read already or have expired")
+    }
+    if !res.status().is_success() {
+        return Err(request_error(res.status(), "copy"));
+    }
+
+    let body: CopyResponse = res.json().await?;
+
+    match decrypt_text(&body.content, pwd) {
+        Ok(content) => Ok(content),
+        Err(err) => bail!("Could not decrypt the text, it has been destroyed anyway: {err}"),
+    }
+}
+
+#[component]
+pub fn Paste() -> Element {
+    let (api_key, remember) = key::use_api_key();
+    let mut content = use_signal(|| "".to_string());
+    let mut pwd = use_signal(|| "".to_string());
+    let mut in_progress = use_signal(|| false);
+
+    let mut paste_result = use_signal(|| None::<Result<String>>);
+
+    // Boolean to control the fact of showing or not the error toast
+    let mut show = use_signal_sync(|| true);
+
+    use_effect(move || {
+        if let Some(Err(_)) = &*paste_result.read() {
+            show.set(true);
+        }
+    });
+
+    let disable_button =
+        in_progress() || content().is_empty() || pwd().is_empty() || api_key().is_empty();
+
+    rsx! {
+        form {
+            onsubmit: move |_| {
+                spawn(async move {
+                    in_progress.set(true);
+                    paste_result.set(None);
+                    key::save(&api_key(), remember());
+
+                    let result = paste_text(api_key(), content(), pwd()).await;
+
+                    // If the result is ok, clear the form
+                    if result.is_ok() {
+                        content.set("".to_string());
+                        pwd.set("".to_string());
+                    }
+
+                    paste_result.set(Some(result));
+                    in_progress.set(false);
+                });
+            },
+            class: "max-w-md mx-auto gap-4 flex flex-col",
+            ApiKeyInput { api_key, remember, disabled: in_progress() }
+            textarea {
+                oninput: move |event| content.set(event.value()),
+                class: "textarea textarea-bordered textarea-primary",
+                rows: "8",
+                placeholder: "Text to share",
+                value: "{content}",
+                disabled: in_progress()
+            }
+            PasswordInput {
+                pwd,
+                placeholder: "Password to encrypt the text",
+                optional: false,
+                disabled: in_progress()
+            }
+            button {
+                class: "btn btn-primary",
+                r#type: "submit",
+                disabled: disable_button,
+                "Paste",
+            }
+        }
+        {
+            match &*paste_result.read() {
+                Some(Ok(id)) => rsx! {
+                    div {
+                        class: "flex justify-center items-center flex-col gap-2 pt-8",
+                        p { "The ID to share is the following, the text can be revealed only once:" }
+                        p { class: "text-primary", "{id}" }
+                        CopyButton { text: id.clone() }
+                    }
+                },
+                Some(Err(err)) => rsx! {
+                    ErrorToast {
+                        err: err.to_string(),
+                        show: show,
+                    }
+                },
+                None => rsx! {},
+            }
+        }
+    }
+}
+
+#[component]
+pub fn Reveal() -> Element {
+    rsx! {
+        RevealView { memo_id: "" }
+    }
+}
+
+/// The reveal page of a shared link, ex. `/t/<ID>#<PASSWORD>`.
+#[component]
+pub fn RevealLink(id: String) -> Element {
+    rsx! {
+        RevealView { memo_id: id }
+    }
+}
+
+#[component]
+fn RevealView(memo_id: String) -> Element {
+    let (api_key, remember) = key::use_api_key();
+    let mut id = use_signal(|| memo_id);
+    let mut pwd = use_signal(link_password);
+    let mut in_progress = use_signal(|| false);
+
+    let mut reveal_result = use_signal(|| None::<Result<String>>);
+
+    // Boolean to control the fact of showing or not the error toast
+    let mut show = use_signal_sync(|| true);
+
+    use_effect(move || {
+        if let Some(Err(_)) = &*reveal_result.read() {
+            show.set(true);
+        }
+    });
+
+    let disable_button =
+        in_progress() || id().is_empty() || pwd().is_empty() || api_key().is_empty();
+
+    rsx! {
+        form {
+            onsubmit: move |_| {
+                spawn(async move {
+                    in_progress.set(true);
+                    reveal_result.set(None);
+                    key::save(&api_key(), remember());
+
+                    let result = reveal_text(api_key(), id(), pwd()).await;
+
+                    // If the result is ok, clear the form
+                    if result.is_ok() {
+                        id.set("".to_string());
+                        pwd.set("".to_string());
+                    }
+
+                    reveal_result.set(Some(result));
+                    in_progress.set(false);
+                });
+            },
+            class: "max-w-md mx-auto gap-4 flex flex-col",
+            ApiKeyInput { api_key, remember, disabled: in_progress() }
+            label { class: "input input-bordered input-primary flex items-center gap-2",
+                input {
+                    oninput: move |event| id.set(event.value()),
+                    class: "grow",
+                    r#type: "text",
+                    placeholder: "Text id",
+                    value: "{id}",
+                    disabled: in_progress()
+                }
+            }
+            PasswordInput {
+                pwd,
+                placeholder: "Password of the text",
+                optional: false,
+                disabled: in_progress()
+            }
+            p { class: "opacity-70", "The text is destroyed as soon as it is revealed." }
+            button {
+                class: "btn btn-primary",
+                r#type: "submit",
+                disabled: disable_button,
+                "Reveal",
+            }
+        }
+        {
+            match &*reveal_result.read() {
+                Some(Ok(content)) => rsx! {
+                    div {
+                        class: "max-w-md mx-auto flex flex-col gap-2 pt-8",
+                        div {
+                            class: "alert",
+                            "This text was destroyed after reading, copy it before leaving the page."
+                        }
+                        textarea {
+                            class: "textarea textarea-bordered",
+                            rows: "8",
+                            readonly: true,
+                            value: "{content}"
+                        }
+                        CopyButton { text: content.clone() }
+                    }
+                },
+                Some(Err(err)) => rsx! {
+                    ErrorToast {
+                        err: err.to_string(),
+                        show: show,
+                    }
+                },
+                None => rsx! {},
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+// Sends a form with an XMLHttpRequest, fetch does not tell how much of the body has been sent.
+export function sendForm(url, apiKey, form, onProgress) {
+    return new Promise((resolve, reject) => {
+        const request = new XMLHttpRequest();
+        request.open("POST", url);
+        request.setRequestHeader("filecrab-key", apiKey);
+
+        request.upload.onprogress = (event) => {
+            if (event.lengthComputable) {
+                onProgress(event.loaded, event.total);
+            }
+        };
+        request.onload = () => resolve({ status: request.status, body: request.responseText });
+        request.onerror = () => reject(new Error("Could not reach the server"));
+
+        request.send(form);
+    });
+}
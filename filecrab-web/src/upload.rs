@@ -0,0 +1,312 @@
+use age::{Encryptor, secrecy::SecretString};
+use anyhow::{Result, anyhow, bail};
+use async_std::task::sleep;
+use dioxus::{html::FileEngine, prelude::*};
+use js_sys::{Array, Uint8Array};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    io::{self, Write},
+    mem,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, File, FormData};
+
+use crate::{
+    BACKEND_URL, CopyButton, ErrorToast, PasswordInput,
+    key::{self, ApiKeyInput},
+};
+
+/// Size of the slices read and encrypted between two refreshes of the progress.
+const ENCRYPTION_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[wasm_bindgen(module = "/src/upload.js")]
+extern "C" {
+    type FormResponse;
+
+    #[wasm_bindgen(catch, js_name = sendForm)]
+    async fn send_form(
+        url: &str,
+        api_key: &str,
+        form: &FormData,
+        on_progress: &Closure<dyn FnMut(f64, f64)>,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, getter)]
+    fn status(this: &FormResponse) -> u16;
+
+    #[wasm_bindgen(method, getter)]
+    fn body(this: &FormResponse) -> String;
+}
+
+/// Step of the upload in progress.
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Idle,
+    PreparingEncryption,
+    /// Holds the percentage of the file encrypted so far.
+    Encrypting(u8),
+    /// Holds the percentage of the file sent so far.
+    Uploading(u8),
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Idle => f.write_str(""),
+            Step::PreparingEncryption => f.write_str("Encryption is about to start..."),
+            Step::Encrypting(percent) => write!(f, "Encrypting... {percent}%"),
+            Step::Uploading(percent) => write!(f, "Uploading... {percent}%"),
+        }
+    }
+}
+
+/// Represents the response of the upload request.
+#[derive(Deserialize)]
+struct UploadResponse {
+    id: String,
+}
+
+/// Output of the encryption, moved into a blob after each slice so that the page only holds a
+/// slice at a time.
+#[derive(Clone, Default)]
+struct EncryptedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl EncryptedOutput {
+    /// Hands what has been encrypted so far to the browser, which may keep it on the disk.
+    fn take_blob(&self) -> Result<Blob> {
+        let data = mem::take(&mut *self.0.borrow_mut());
+        let parts = Array::of1(&Uint8Array::from(data.as_slice()));
+
+        Blob::new_with_u8_array_sequence(&parts)
+            .map_err(|err| anyhow!("{err:?}").context("could not store the encrypted file"))
+    }
+}
+
+impl Write for EncryptedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Encrypts the selected file when a password is given and uploads it. Returns the memorable id
+/// of the file.
+async fn send_file(
+    files: Arc<dyn FileEngine>,
+    api_key: String,
+    pwd: String,
+    step: Signal<Step>,
+) -> Result<String> {
+    if api_key.is_empty() {
+        bail!("An API key is needed to upload a file")
+    }
+    let Some(file_name) = files.files().into_iter().next() else {
+        bail!("No file selected")
+    };
+    let file = files
+        .get_native_file(&file_name)
+        .await
+        .and_then(|file| file.downcast::<File>().ok())
+        .ok_or_else(|| anyhow!("Could not read the file"))?;
+
+    // The encryption is set before the file so the server knows it when the upload starts
+    let form = FormData::new().map_err(|err| anyhow!("{err:?}"))?;
+    if pwd.is_empty() {
+        // The browser reads the file from the disk while sending it
+        form.append_with_blob_and_filename("file", &file, &file_name)
+    } else {
+        let encrypted = encrypt_file(&file, pwd, step).await?;
+        form.append_with_str("encrypted", "true")
+            .and_then(|_| form.append_with_blob_and_filename("file", &encrypted, &file_name))
+    }
+    .map_err(|err| anyhow!("{err:?}"))?;
+
+    upload_form(&api_key, &form, step).await
+}
+
+/// Encrypts the file slice by slice the same way as the CLI, with the age algorithm and a
+/// passphrase.
+async fn encrypt_file(file: &File, pwd: String, mut step: Signal<Step>) -> Result<Blob> {
+    // Deriving the key from the password takes a moment, let the page show it
+    step.set(Step::PreparingEncryption);
+    sleep(Duration::from_millis(10)).await;
+
+    let encryptor = Encryptor::with_user_passphrase(SecretString::from(pwd));
+    let output = EncryptedOutput::default();
+    let mut writer = encryptor.wrap_output(output.clone())?;
+    let parts = Array::new();
+
+    let size = file.size() as usize;
+    let chunks = size.div_ceil(ENCRYPTION_CHUNK_SIZE).max(1);
+    for index in 0..chunks {
+        let start = index * ENCRYPTION_CHUNK_SIZE;
+        let end = (start + ENCRYPTION_CHUNK_SIZE).min(size);
+        let slice = file
+            .slice_with_f64_and_f64(start as f64, end as f64)
+            .map_err(|err| anyhow!("{err:?}"))?;
+        let data = JsFuture::from(slice.array_buffer())
+            .await
+            .map_err(|err| anyhow!("{err:?}").context("could not read the file"))?;
+
+        writer.write_all(&Uint8Array::new(&data).to_vec())?;
+        parts.push(&output.take_blob()?.into());
+
+        step.set(Step::Encrypting(((index + 1) * 100 / chunks) as u8));
+        sleep(Duration::from_millis(1)).await;
+    }
+    writer.finish()?;
+    parts.push(&output.take_blob()?.into());
+
+    Blob::new_with_blob_sequence(&parts)
+        .map_err(|err| anyhow!("{err:?}").context("could not store the encrypted file"))
+}
+
+/// Sends the form to the server in a single multipart request, following how much has been sent.
+async fn upload_form(api_key: &str, form: &FormData, mut step: Signal<Step>) -> Result<String> {
+    step.set(Step::Uploading(0));
+    let on_progress = Closure::new(move |sent: f64, total: f64| {
+        step.set(Step::Uploading((sent * 100.0 / total) as u8));
+    });
+
+    let res: FormResponse = send_form(
+        // Here it's safe to unwrap since we know for sure it's initialized
+        &format!("{}/api/upload", BACKEND_URL.get().unwrap()),
+        api_key,
+        form,
+        &on_progress,
+    )
+    .await
+    .map_err(|err| anyhow!("{err:?}").context("could not upload the file"))?
+    .unchecked_into();
+
+    // Explains the errors the user can do something about
+    match StatusCode::from_u16(res.status())? {
+        status if status.is_success() => {}
+        StatusCode::UNAUTHORIZED => bail!("The API key is not valid"),
+        StatusCode::FORBIDDEN => bail!("The API key is not allowed to upload files"),
+        StatusCode::PAYLOAD_TOO_LARGE => bail!("The file is larger than the server accepts"),
+        StatusCode::INSUFFICIENT_STORAGE => bail!("The quota of the API key is exhausted"),
+        status => bail!("Status: {status}"),
+    }
+
+    let body: UploadResponse = serde_json::from_str(&res.body())?;
+    Ok(body.id)
+}
+
+#[component]
+pub fn Upload() -> Element {
+    let (api_key, remember) = key::use_api_key();
+    let mut pwd = use_signal(|| "".to_string());
+    let mut files = use_signal(|| None::<Arc<dyn FileEngine>>);
+    let mut step = use_signal(|| Step::Idle);
+
+    let mut upload_result = use_signal(|| None::<Result<String>>);
+
+    // Boolean to control the fact of showing or not the error toast
+    let mut show = use_signal_sync(|| true);
+
+    use_effect(move || {
+        if let Some(Err(_)) = &*upload_result.read() {
+            show.set(true);
+        }
+    });
+
+    let in_progress = step() != Step::Idle;
+    let disable_button = in_progress || files.read().is_none() || api_key().is_empty();
+
+    rsx! {
+        form {
+            onsubmit: move |_| {
+                spawn(async move {
+                    let Some(selected) = files() else {
+                        return;
+                    };
+                    upload_result.set(None);
+                    key::save(&api_key(), remember());
+
+                    let result = send_file(selected, api_key(), pwd(), step).await;
+
+                    // If the result is ok, clear the form
+                    if result.is_ok() {
+                        files.set(None);
+                        pwd.set("".to_string());
+                    }
+
+                    upload_result.set(Some(result));
+                    step.set(Step::Idle);
+                });
+            },
+            class: "max-w-md mx-auto gap-4 flex flex-col",
+            ApiKeyInput { api_key, remember, disabled: in_progress }
+            input {
+                onchange: move |event| files.set(event.files()),
+                class: "input input-bordered input-primary",
+                r#type: "file",
+                disabled: in_progress
+            }
+            PasswordInput {
+                pwd,
+                placeholder: "Password to encrypt the file",
+                optional: true,
+                disabled: in_progress
+            }
+            button {
+                class: "btn btn-primary",
+                r#type: "submit",
+                disabled: disable_button,
+                "Upload",
+            }
+        }
+        {
+            match step() {
+                Step::Idle => rsx! {},
+                Step::Encrypting(percent) | Step::Uploading(percent) => rsx! {
+                    div {
+                        class: "flex justify-center items-center flex-col gap-2 pt-8",
+                        progress { class: "progress max-w-md", value: "{percent}", max: "100" }
+                        p { "{step}" }
+                    }
+                },
+                _ => rsx! {
+                    div {
+                        class: "flex justify-center items-center flex-col gap-2 pt-8",
+                        span {
+                            class: "loading loading-spinner loading-lg text-primary"
+                        }
+                        p { "{step}" }
+                    }
+                },
+            }
+        }
+        {
+            match &*upload_result.read() {
+                Some(Ok(id)) => rsx! {
+                    div {
+                        class: "flex justify-center items-center flex-col gap-2 pt-8",
+                        p { "The ID to share is the following:" }
+                        p { class: "text-primary", "{id}" }
+                        CopyButton { text: id.clone() }
+                    }
+                },
+                Some(Err(err)) => rsx! {
+                    ErrorToast {
+                        err: err.to_string(),
+                        show: show,
+                    }
+                },
+                None => rsx! {},
+            }
+        }
+    }
+}